@@ -1,18 +1,56 @@
 //! Contains the "unresolved" AST which is output by the parser
 //!
 //! Unresolved meaning it needs to be checked for duplicate
-//! definitions, unknown types, etc.
-/// Unresolved is an "unchecked" type, which may be an array type
+//! definitions, unknown types, etc. Declarations, fields, type references,
+//! enum variants and exports carry the span they were parsed from.
+use super::diagnostic::Span;
+
+/// Unresolved is an "unchecked" type, which may be an array type. Arrays
+/// written as `type[field]` take their length from an earlier field.
+///
+/// (identifier, is_array, is_optional, range, length field, condition, tag,
+/// span of identifier)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Unresolved<'a>(
+    pub &'a str,
+    pub bool,
+    pub bool,
+    pub Option<Range<'a>>,
+    pub Option<&'a str>,
+    pub Option<Condition<'a>>,
+    pub Option<u16>,
+    pub Span,
+);
+/// A condition in the form `if field == Enum.VARIANT`, which makes a field
+/// present only when an earlier enum field holds that variant
+///
+/// (field, enum, variant, span)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Condition<'a>(pub &'a str, pub &'a str, pub &'a str, pub Span);
+/// A constraint in the form `min..max` or `min..=max`, where either bound
+/// may be omitted. The bounds are kept as written, and parsed by the checker
+/// once the type they apply to is known.
+///
+/// (min, max, is_inclusive)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Range<'a>(pub Option<&'a str>, pub Option<&'a str>, pub bool);
+/// Lines of a doc comment (`## ...`), without the leading `##`
+pub type Doc<'a> = Vec<&'a str>;
+/// An attribute in the form `@name(arg0, arg1, ...)`
 ///
-/// (identifier, is_array, is_optional)
+/// (name, arguments)
 #[derive(Clone, PartialEq, Debug)]
-pub struct Unresolved<'a>(pub &'a str, pub bool, pub bool);
+pub struct Attribute<'a>(pub &'a str, pub Vec<&'a str>);
+pub type Attributes<'a> = Vec<Attribute<'a>>;
 /// Enum is just a list of its variants, which are plain strings
 #[derive(Clone, PartialEq, Debug)]
-pub struct Enum<'a>(pub Vec<&'a str>);
-/// Struct is a list of pairs of `identifier:type`, where `type` may be an array
+pub struct Enum<'a>(pub Vec<(&'a str, Doc<'a>, Span)>);
+/// (identifier, type, doc, attributes, span of identifier)
+pub type Field<'a> = (&'a str, Unresolved<'a>, Doc<'a>, Attributes<'a>, Span);
+/// Struct is a list of `identifier:type` fields, where `type` may be an array,
+/// and the struct it extends, if any
 #[derive(Clone, PartialEq, Debug)]
-pub struct Struct<'a>(pub Vec<(&'a str, Unresolved<'a>)>);
+pub struct Struct<'a>(pub Vec<Field<'a>>, pub Option<(&'a str, Span)>);
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum Type<'a> {
@@ -22,7 +60,13 @@ pub enum Type<'a> {
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum Node<'a> {
-    Decl(&'a str, Type<'a>),
-    Export(&'a str),
+    /// (identifier, type, doc, attributes, span of identifier)
+    Decl(&'a str, Type<'a>, Doc<'a>, Attributes<'a>, Span),
+    /// (identifier, packet id, span of identifier)
+    Export(&'a str, Option<u8>, Span),
+    /// Path to another schema, relative to this one
+    Import(&'a str, Span),
+    /// Dot-separated path of the namespace the schema's types are declared in
+    Package(&'a str, Span),
 }
 pub type AST<'a> = Vec<Node<'a>>;
@@ -2,42 +2,852 @@
 //!
 //! Type-checking is done in two passes, so that it's possible to have lexical
 //! scoping.
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::rc::Rc;
 use std::{cell::RefCell, fmt, fmt::Display, fmt::Formatter};
 
+use super::diagnostic::{Code, Diagnostic, Span};
 use super::*;
 
-// TODO: real error type + report in a nice way
+fn get_exports<'a>(ast: &[ast::Node<'a>], errors: &mut Vec<Diagnostic>) -> Vec<(&'a str, Option<u8>)> {
+    let mut exports: Vec<(&'a str, Option<u8>, Span)> = Vec::new();
+    for node in ast {
+        if let ast::Node::Export(n, id, span) = node {
+            if let Some((.., first)) = exports.iter().find(|(e, ..)| e == n) {
+                errors.push(
+                    Diagnostic::error(Code::Duplicate, format!("Schema has duplicate export: {}", n))
+                        .with_span(*span)
+                        .with_label(*first, "first exported here"),
+                );
+                continue;
+            }
+            if let Some((other, ..)) = exports.iter().find(|(_, e, _)| id.is_some() && e == id) {
+                errors.push(
+                    Diagnostic::error(
+                        Code::Export,
+                        format!(
+                            "Exports '{}' and '{}' have the same packet id ({})",
+                            other,
+                            n,
+                            id.unwrap()
+                        ),
+                    )
+                    .with_span(*span),
+                );
+            }
+            exports.push((*n, *id, *span));
+        }
+    }
+    // packet ids are all or nothing, otherwise some exports couldn't be dispatched
+    if exports.iter().any(|(_, id, _)| id.is_some()) {
+        for (n, _, span) in exports.iter().filter(|(_, id, _)| id.is_none()) {
+            errors.push(
+                Diagnostic::error(Code::Export, format!("Export '{}' is missing a packet id", n)).with_span(*span),
+            );
+        }
+    }
+    exports.into_iter().map(|(n, id, _)| (n, id)).collect()
+}
+
+fn get_package<'a>(ast: &[ast::Node<'a>], errors: &mut Vec<Diagnostic>) -> Vec<&'a str> {
+    let mut packages = ast.iter().filter_map(|node| match node {
+        ast::Node::Package(p, span) => Some((*p, *span)),
+        _ => None,
+    });
+    let package = packages.next();
+    for (other, span) in packages {
+        errors.push(
+            Diagnostic::error(
+                Code::Duplicate,
+                format!(
+                    "Schema has more than one package declaration: '{}' and '{}'",
+                    package.unwrap().0,
+                    other
+                ),
+            )
+            .with_span(span),
+        );
+    }
+    package.map(|(p, _)| p.split('.').collect()).unwrap_or_default()
+}
+
+/// Returns the order in which schemas should be checked, so that every schema
+/// comes after the ones it imports.
+///
+/// `imports[i]` holds the indices of the schemas imported by schema `i`, along
+/// with the spans of their imports, and `names[i]` is the path of schema `i`.
+/// A cycle is reported at the import which closes it.
+#[allow(clippy::result_large_err)]
+pub fn import_order(names: &[String], imports: &[Vec<(usize, Span)>]) -> Result<Vec<usize>, Diagnostic> {
+    #[allow(clippy::result_large_err)]
+    fn visit(
+        node: usize,
+        via: Option<Span>,
+        names: &[String],
+        imports: &[Vec<(usize, Span)>],
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), Diagnostic> {
+        if order.contains(&node) {
+            return Ok(());
+        }
+        if let Some(start) = stack.iter().position(|n| *n == node) {
+            let cycle = stack[start..]
+                .iter()
+                .chain(std::iter::once(&node))
+                .map(|n| names[*n].as_str())
+                .collect::<Vec<_>>();
+            let importer = names[*stack.last().unwrap()].as_str();
+            return Err(
+                Diagnostic::error(Code::Cycle, format!("Found an import cycle: {}", cycle.join(" -> ")))
+                    .with_span(via)
+                    .with_path(importer),
+            );
+        }
+        stack.push(node);
+        for &(next, span) in imports[node].iter() {
+            visit(next, Some(span), names, imports, stack, order)?;
+        }
+        stack.pop();
+        order.push(node);
+        Ok(())
+    }
+
+    let mut order = Vec::with_capacity(names.len());
+    for node in 0..names.len() {
+        visit(node, None, names, imports, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Types declared by another schema
+#[derive(Clone, PartialEq, Debug)]
+pub struct Import<'a> {
+    /// Path to the module generated for the imported schema, relative to the
+    /// schema being checked and without an extension, e.g. `./shared`
+    pub module: String,
+    /// Whether the imported schema's types can be referred to by name. Schemas
+    /// which are only imported transitively are still needed to know where
+    /// their types are declared.
+    pub direct: bool,
+    /// Package of the imported schema, empty if it doesn't declare one
+    pub package: Vec<&'a str>,
+    pub types: TypeMap<'a>,
+}
+
+/// Spans of the items declared by the schema being checked, so that errors
+/// found once its types are resolved can still point at them
+#[derive(Default)]
+struct Spans<'a> {
+    decls: HashMap<&'a str, Span>,
+    exports: HashMap<&'a str, Span>,
+    /// Keyed by (struct, field)
+    fields: HashMap<(&'a str, &'a str), Span>,
+    /// Conditions of fields, keyed by (struct, field)
+    conditions: HashMap<(&'a str, &'a str), Span>,
+    /// Keyed by (enum, variant)
+    variants: HashMap<(&'a str, &'a str), Span>,
+}
+
+impl<'a> Spans<'a> {
+    fn collect(ast: &[ast::Node<'a>]) -> Spans<'a> {
+        let mut spans = Spans::default();
+        for node in ast {
+            match node {
+                ast::Node::Decl(name, ty, _, _, span) => {
+                    spans.decls.entry(name).or_insert(*span);
+                    match ty {
+                        ast::Type::Struct(s) => {
+                            for (fname, fty, _, _, fspan) in s.0.iter() {
+                                spans.fields.entry((name, fname)).or_insert(*fspan);
+                                if let Some(cond) = &fty.5 {
+                                    spans.conditions.entry((name, fname)).or_insert(cond.3);
+                                }
+                            }
+                        }
+                        ast::Type::Enum(e) => {
+                            for (variant, _, vspan) in e.0.iter() {
+                                spans.variants.entry((name, variant)).or_insert(*vspan);
+                            }
+                        }
+                    }
+                }
+                ast::Node::Export(name, _, span) => {
+                    spans.exports.entry(name).or_insert(*span);
+                }
+                _ => (),
+            }
+        }
+        spans
+    }
+
+    fn decl(&self, name: &str) -> Option<Span> { self.decls.get(name).copied() }
+
+    fn export(&self, name: &str) -> Option<Span> { self.exports.get(name).copied() }
+
+    /// Inherited fields aren't declared by the struct, so they point at it
+    /// instead
+    fn field(&self, name: &'a str, field: &'a str) -> Option<Span> {
+        self.fields.get(&(name, field)).copied().or_else(|| self.decl(name))
+    }
+
+    fn variant(&self, name: &'a str, variant: &'a str) -> Option<Span> {
+        self.variants.get(&(name, variant)).copied().or_else(|| self.decl(name))
+    }
+
+    fn condition(&self, name: &'a str, field: &'a str) -> Option<Span> {
+        self.conditions
+            .get(&(name, field))
+            .copied()
+            .or_else(|| self.field(name, field))
+    }
+}
+
+fn check_import_names<'a>(
+    local: &HashMap<&'a str, ast::Type<'a>>,
+    imports: &[Import<'a>],
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    // `None` means the type is declared by the schema being checked
+    let mut seen: HashMap<&str, Option<&str>> = local.keys().map(|name| (*name, None)).collect();
+    for import in imports {
+        for name in import.types.keys() {
+            match seen.insert(name, Some(&import.module)) {
+                Some(None) => errors.push(
+                    Diagnostic::error(
+                        Code::Duplicate,
+                        format!(
+                            "Type '{}' is declared in both this schema and '{}'",
+                            name, import.module
+                        ),
+                    )
+                    .with_span(spans.decl(name)),
+                ),
+                Some(Some(other)) => errors.push(Diagnostic::error(
+                    Code::Duplicate,
+                    format!(
+                        "Type '{}' is declared in both '{}' and '{}'",
+                        name, other, import.module
+                    ),
+                )),
+                None => (),
+            }
+        }
+    }
+}
+
+/// The kind of item attributes are attached to
+#[derive(Clone, Copy, PartialEq)]
+enum AttributeTarget {
+    Struct,
+    Enum,
+    Field,
+}
+
+/// Resolves the attributes of a declaration or field. `target` describes
+/// what they're attached to, and `span` where, for error messages.
+fn resolve_attributes<'a>(
+    attrs: ast::Attributes<'a>,
+    target: &str,
+    kind: AttributeTarget,
+    span: Span,
+    errors: &mut Vec<Diagnostic>,
+) -> Vec<Attribute<'a>> {
+    let mut resolved: Vec<Attribute> = Vec::with_capacity(attrs.len());
+    for ast::Attribute(name, args) in attrs {
+        let expected_args = match name {
+            "deprecated" => 0..=1,
+            "rename" | "since" => 1..=1,
+            "bitmask" | "extensible" | "delta" | "packed" => 0..=0,
+            _ => {
+                errors.push(
+                    Diagnostic::error(Code::Attribute, format!("Unknown attribute '@{}' on {}", name, target))
+                        .with_span(span),
+                );
+                continue;
+            }
+        };
+        if !expected_args.contains(&args.len()) {
+            let expected = match (expected_args.start(), expected_args.end()) {
+                (0, 0) => "no arguments",
+                (0, _) => "at most one argument",
+                _ => "exactly one argument",
+            };
+            errors.push(
+                Diagnostic::error(
+                    Code::Attribute,
+                    format!("Attribute '@{}' on {} expects {}", name, target, expected),
+                )
+                .with_span(span),
+            );
+            continue;
+        }
+        let attr = match name {
+            "deprecated" => Attribute::Deprecated(args.first().copied()),
+            "rename" => {
+                if kind != AttributeTarget::Field {
+                    errors.push(
+                        Diagnostic::error(
+                            Code::Attribute,
+                            format!("Attribute '@rename' on {} can only be used on fields", target),
+                        )
+                        .with_span(span),
+                    );
+                    continue;
+                }
+                let ident = args[0];
+                let valid = ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && ident.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    errors.push(
+                        Diagnostic::error(
+                            Code::Attribute,
+                            format!(
+                                "Attribute '@rename' on {} expects an identifier, got '{}'",
+                                target, ident
+                            ),
+                        )
+                        .with_span(span),
+                    );
+                    continue;
+                }
+                Attribute::Rename(ident)
+            }
+            "bitmask" | "extensible" | "delta" | "packed" => {
+                if kind != AttributeTarget::Struct {
+                    errors.push(
+                        Diagnostic::error(
+                            Code::Attribute,
+                            format!("Attribute '@{}' on {} can only be used on structs", name, target),
+                        )
+                        .with_span(span),
+                    );
+                    continue;
+                }
+                match name {
+                    "bitmask" => Attribute::Bitmask,
+                    "extensible" => Attribute::Extensible,
+                    "delta" => Attribute::Delta,
+                    _ => Attribute::Packed,
+                }
+            }
+            _ => Attribute::Since(args[0]),
+        };
+        if resolved
+            .iter()
+            .any(|a| std::mem::discriminant(a) == std::mem::discriminant(&attr))
+        {
+            errors.push(
+                Diagnostic::error(
+                    Code::Duplicate,
+                    format!("Duplicate attribute '@{}' on {}", name, target),
+                )
+                .with_span(span),
+            );
+            continue;
+        }
+        resolved.push(attr);
+    }
+    resolved
+}
 
-fn get_export<'a>(ast: &[ast::Node<'a>]) -> Result<&'a str, String> {
-    let mut export = None;
+/// Declarations are resolved without their doc comments and attributes, which
+/// are attached once every type has been resolved
+#[allow(clippy::type_complexity)]
+fn collect_decl_meta<'a>(
+    ast: &[ast::Node<'a>],
+    errors: &mut Vec<Diagnostic>,
+) -> HashMap<&'a str, (ast::Doc<'a>, Vec<Attribute<'a>>)> {
+    let mut meta = HashMap::new();
     for node in ast {
-        match node {
-            ast::Node::Export(n) if export.is_none() => export = Some(n),
-            ast::Node::Export(_) => return Err("Schema has more than one export".to_string()),
-            _ => (),
+        if let ast::Node::Decl(n, ty, doc, attrs, span) = node {
+            let kind = match ty {
+                ast::Type::Struct(_) => AttributeTarget::Struct,
+                ast::Type::Enum(_) => AttributeTarget::Enum,
+            };
+            let attrs = resolve_attributes(attrs.clone(), &format!("declaration '{}'", n), kind, *span, errors);
+            meta.insert(*n, (doc.clone(), attrs));
+        }
+    }
+    meta
+}
+
+fn attach_decl_meta<'a>(
+    meta: HashMap<&'a str, (ast::Doc<'a>, Vec<Attribute<'a>>)>,
+    resolved: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+) {
+    for (name, (doc, attrs)) in meta {
+        if let Some(ty) = resolved.get(name) {
+            match &mut ty.borrow_mut().1 {
+                ResolvedType::Struct(s) => {
+                    s.doc = doc;
+                    s.attrs = attrs;
+                }
+                ResolvedType::Enum(e) => {
+                    e.doc = doc;
+                    e.attrs = attrs;
+                }
+                ResolvedType::Builtin(_) => (),
+            }
+        }
+    }
+}
+
+/// Renamed fields may not clash with the identifiers of other fields
+fn check_field_idents<'a>(
+    declared: &[&'a str],
+    resolved: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    for name in declared {
+        if let ResolvedType::Struct(s) = &resolved[name].borrow().1 {
+            let mut idents = HashMap::new();
+            for field in s.fields.iter() {
+                if let Some(other) = idents.insert(field.ident(), field.name) {
+                    errors.push(
+                        Diagnostic::error(
+                            Code::Duplicate,
+                            format!(
+                                "Fields '{}' and '{}' in struct '{}' both use the identifier '{}'",
+                                other,
+                                field.name,
+                                name,
+                                field.ident()
+                            ),
+                        )
+                        .with_span(spans.field(name, field.name)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Warns about fields which only differ in case, as they're easy to mix up,
+/// and may collide once the generated code changes their case
+fn check_field_case<'a>(
+    declared: &[&'a str],
+    resolved: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+    spans: &Spans<'a>,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    for name in declared {
+        if let ResolvedType::Struct(s) = &resolved[name].borrow().1 {
+            for (i, field) in s.fields.iter().enumerate() {
+                // pairs of inherited fields are reported on the base
+                if !spans.fields.contains_key(&(*name, field.name)) {
+                    continue;
+                }
+                let other = s.fields[..i]
+                    .iter()
+                    .find(|other| other.name != field.name && other.name.eq_ignore_ascii_case(field.name));
+                if let Some(other) = other {
+                    let mut warning = Diagnostic::warning(
+                        Code::FieldCase,
+                        format!(
+                            "Fields '{}' and '{}' in struct '{}' only differ in case",
+                            other.name, field.name, name
+                        ),
+                    )
+                    .with_span(spans.field(name, field.name));
+                    if let Some(span) = spans.fields.get(&(*name, other.name)) {
+                        warning = warning.with_label(*span, "similar to this field");
+                    }
+                    warnings.push(warning);
+                }
+            }
+        }
+    }
+}
+
+/// Warns about type names which only differ in case from a builtin or
+/// another type, which makes it unclear which one is meant
+fn check_type_names<'a>(
+    declared: &[&'a str],
+    imports: &[Import<'a>],
+    spans: &Spans<'a>,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    let builtins = get_builtins();
+    let mut names = declared.to_vec();
+    names.sort_unstable();
+    let mut visible = imports
+        .iter()
+        .filter(|i| i.direct)
+        .flat_map(|i| i.types.keys().copied())
+        .collect::<Vec<_>>();
+    visible.sort_unstable();
+    for (i, name) in names.iter().enumerate() {
+        let similar = |other: &&str| other != name && other.eq_ignore_ascii_case(name);
+        if let Some(builtin) = builtins.keys().copied().find(|other| similar(other)) {
+            warnings.push(
+                Diagnostic::warning(
+                    Code::ShadowingName,
+                    format!(
+                        "Type '{}' only differs in case from the builtin type '{}'",
+                        name, builtin
+                    ),
+                )
+                .with_span(spans.decl(name)),
+            );
+        } else if let Some(other) = names[..i]
+            .iter()
+            .chain(visible.iter())
+            .copied()
+            .find(|other| similar(other))
+        {
+            let mut warning = Diagnostic::warning(
+                Code::ShadowingName,
+                format!("Type '{}' only differs in case from the type '{}'", name, other),
+            )
+            .with_span(spans.decl(name));
+            if let Some(span) = spans.decl(other) {
+                warning = warning.with_label(span, "similar to this type");
+            }
+            warnings.push(warning);
+        }
+    }
+}
+
+/// Warns about the variants of enums used in conditions which no condition
+/// checks for, as the fields that would be present for them are likely missing
+fn check_unused_variants<'a>(
+    declared: &[&'a str],
+    resolved: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+    spans: &Spans<'a>,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    let mut checked = HashSet::new();
+    for name in declared {
+        if let ResolvedType::Struct(s) = &resolved[name].borrow().1 {
+            checked.extend(
+                s.fields
+                    .iter()
+                    .filter_map(|f| f.condition.as_ref())
+                    .map(|c| (c.r#enum, c.variant)),
+            );
+        }
+    }
+    for name in declared {
+        if !checked.iter().any(|(e, _)| e == name) {
+            continue;
+        }
+        if let ResolvedType::Enum(e) = &resolved[name].borrow().1 {
+            for variant in e.variants.iter().filter(|v| !checked.contains(&(*name, v.name))) {
+                warnings.push(
+                    Diagnostic::warning(
+                        Code::UnusedVariant,
+                        format!(
+                            "Variant '{}' of enum '{}' isn't checked by any condition",
+                            variant.name, name
+                        ),
+                    )
+                    .with_span(spans.variant(name, variant.name)),
+                );
+            }
+        }
+    }
+}
+
+/// Finds the field `name` which the field at `index` refers to in `what`. It
+/// must be declared before it, so that its value is known by the time the
+/// referring field is read.
+#[allow(clippy::result_large_err)]
+fn find_earlier_field<'s, 'a>(
+    s: &'s Struct<'a>,
+    index: usize,
+    name: &str,
+    what: &str,
+    target: &str,
+    span: Option<Span>,
+) -> Result<&'s StructField<'a>, Diagnostic> {
+    match s.fields.iter().position(|f| f.name == name) {
+        Some(pos) if pos < index => Ok(&s.fields[pos]),
+        Some(_) => Err(Diagnostic::error(
+            Code::FieldReference,
+            format!(
+                "{} of {} refers to field '{}', which is declared after it",
+                what, target, name
+            ),
+        )
+        .with_span(span)),
+        None => Err(Diagnostic::error(
+            Code::Unresolved,
+            format!("{} of {} refers to unknown field '{}'", what, target, name),
+        )
+        .with_span(span)),
+    }
+}
+
+/// Array lengths may only refer to unsigned integer fields, and conditions to
+/// enum fields, which are always present
+fn check_field_refs<'a>(
+    declared: &[&'a str],
+    resolved: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    for name in declared {
+        if let ResolvedType::Struct(s) = &resolved[name].borrow().1 {
+            for (i, field) in s.fields.iter().enumerate() {
+                let target = format!("field '{}' in struct '{}'", field.name, name);
+                let span = spans.field(name, field.name);
+                if let Some(length) = field.length {
+                    let length_field = match find_earlier_field(s, i, length, "Length", &target, span) {
+                        Ok(length_field) => length_field,
+                        Err(err) => {
+                            errors.push(err);
+                            continue;
+                        }
+                    };
+                    let unsigned = matches!(
+                        length_field.r#type.borrow().1,
+                        ResolvedType::Builtin(Builtin::Uint8)
+                            | ResolvedType::Builtin(Builtin::Uint16)
+                            | ResolvedType::Builtin(Builtin::Uint32)
+                    );
+                    if !unsigned || length_field.array || length_field.is_optional() {
+                        errors.push(
+                            Diagnostic::error(
+                                Code::FieldReference,
+                                format!(
+                                    "Length of {} refers to field '{}', which is not an unsigned integer",
+                                    target, length
+                                ),
+                            )
+                            .with_span(span),
+                        );
+                    }
+                }
+                if let Some(cond) = &field.condition {
+                    let span = spans.condition(name, field.name);
+                    let cond_field = match find_earlier_field(s, i, cond.field, "Condition", &target, span) {
+                        Ok(cond_field) => cond_field,
+                        Err(err) => {
+                            errors.push(err);
+                            continue;
+                        }
+                    };
+                    let cond_type = &*cond_field.r#type.borrow();
+                    let variants = match &cond_type.1 {
+                        ResolvedType::Enum(e) if !cond_field.array && !cond_field.is_optional() => &e.variants,
+                        _ => {
+                            errors.push(
+                                Diagnostic::error(
+                                    Code::FieldReference,
+                                    format!(
+                                        "Condition of {} refers to field '{}', which is not an enum",
+                                        target, cond.field
+                                    ),
+                                )
+                                .with_span(span),
+                            );
+                            continue;
+                        }
+                    };
+                    if cond_type.0 != cond.r#enum {
+                        errors.push(
+                            Diagnostic::error(
+                                Code::FieldReference,
+                                format!(
+                                    "Condition of {} compares field '{}' of type '{}' with '{}.{}'",
+                                    target, cond.field, cond_type.0, cond.r#enum, cond.variant
+                                ),
+                            )
+                            .with_span(span),
+                        );
+                        continue;
+                    }
+                    if !variants.iter().any(|v| v.name == cond.variant) {
+                        errors.push(
+                            Diagnostic::error(
+                                Code::Unresolved,
+                                format!(
+                                    "Condition of {} refers to unknown variant '{}.{}'",
+                                    target, cond.r#enum, cond.variant
+                                ),
+                            )
+                            .with_span(span),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Every field of an extensible struct must have a unique tag, as that's what
+/// identifies it on the wire. Their layout can't depend on other fields, as
+/// those may not be known to the reader.
+fn check_tags<'a>(
+    declared: &[&'a str],
+    resolved: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    for name in declared {
+        let s = match &resolved[name].borrow().1 {
+            ResolvedType::Struct(s) => s.clone(),
+            _ => continue,
+        };
+        if !s.is_extensible() {
+            if let Some(field) = s.fields.iter().find(|f| f.tag.is_some()) {
+                errors.push(
+                    Diagnostic::error(
+                        Code::Tag,
+                        format!(
+                            "Field '{}' in struct '{}' has a tag, but the struct is not @extensible",
+                            field.name, name
+                        ),
+                    )
+                    .with_span(spans.field(name, field.name)),
+                );
+            }
+            continue;
+        }
+        for (attr, attr_name) in [
+            (Attribute::Bitmask, "bitmask"),
+            (Attribute::Delta, "delta"),
+            (Attribute::Packed, "packed"),
+        ]
+        .iter()
+        {
+            if s.attrs.contains(attr) {
+                errors.push(
+                    Diagnostic::error(
+                        Code::Attribute,
+                        format!("Struct '{}' cannot be both @extensible and @{}", name, attr_name),
+                    )
+                    .with_span(spans.decl(name)),
+                );
+            }
+        }
+        let mut tags = HashMap::new();
+        for field in s.fields.iter() {
+            let target = format!("field '{}' in extensible struct '{}'", field.name, name);
+            let span = spans.field(name, field.name);
+            let tag = match field.tag {
+                Some(0) => {
+                    errors.push(
+                        Diagnostic::error(Code::Tag, format!("Tag @0 on {} is reserved", target)).with_span(span),
+                    );
+                    continue;
+                }
+                Some(tag) => tag,
+                None => {
+                    errors.push(Diagnostic::error(Code::Tag, format!("Missing tag on {}", target)).with_span(span));
+                    continue;
+                }
+            };
+            if field.length.is_some() {
+                errors.push(
+                    Diagnostic::error(
+                        Code::FieldReference,
+                        format!("Array length of {} can't refer to another field", target),
+                    )
+                    .with_span(span),
+                );
+                continue;
+            }
+            if field.condition.is_some() {
+                errors.push(
+                    Diagnostic::error(Code::FieldReference, format!("Condition on {} isn't supported", target))
+                        .with_span(spans.condition(name, field.name)),
+                );
+                continue;
+            }
+            if let Some(other) = tags.insert(tag, field.name) {
+                errors.push(
+                    Diagnostic::error(
+                        Code::Tag,
+                        format!(
+                            "Fields '{}' and '{}' in struct '{}' both use the tag @{}",
+                            other, field.name, name, tag
+                        ),
+                    )
+                    .with_span(span),
+                );
+            }
         }
     }
-    match export {
-        Some(e) => Ok(e),
-        None => Err("Schema has no export".to_string()),
+}
+
+/// Fields can only be one of optional, conditional or an array. This doesn't
+/// depend on their type, so it's checked whether or not that can be resolved.
+fn check_field_shape(name: &str, fname: &str, fty: &ast::Unresolved, span: Span, errors: &mut Vec<Diagnostic>) {
+    if fty.1 && fty.2 {
+        errors.push(
+            Diagnostic::error(
+                Code::FieldShape,
+                format!(
+                    "Field '{}' in struct '{}' cannot be optional and array at once",
+                    fname, name
+                ),
+            )
+            .with_span(span),
+        );
+    } else if fty.5.is_some() && (fty.1 || fty.2) {
+        errors.push(
+            Diagnostic::error(
+                Code::FieldShape,
+                format!(
+                    "Field '{}' in struct '{}' cannot be conditional and {} at once",
+                    fname,
+                    name,
+                    if fty.1 { "array" } else { "optional" }
+                ),
+            )
+            .with_span(span),
+        );
     }
 }
 
-fn collect_types<'a>(ast: &[ast::Node<'a>]) -> Result<HashMap<&'a str, ast::Type<'a>>, String> {
+/// Collects the declared types, leaving out duplicate declarations and fields
+fn collect_types<'a>(ast: &[ast::Node<'a>], errors: &mut Vec<Diagnostic>) -> HashMap<&'a str, ast::Type<'a>> {
     let mut cache = HashMap::new();
+    let mut declared_at = HashMap::new();
 
     for node in ast {
-        if let ast::Node::Decl(n, t) = node {
-            if cache.contains_key(n) {
-                return Err(format!("Schema has duplicate declaration: {}", n));
+        if let ast::Node::Decl(n, t, _, _, span) = node {
+            if let Some(first) = declared_at.get(n) {
+                errors.push(
+                    Diagnostic::error(Code::Duplicate, format!("Schema has duplicate declaration: {}", n))
+                        .with_span(*span)
+                        .with_label(*first, "first declared here"),
+                );
+                continue;
+            }
+            let mut t = t.clone();
+            if let ast::Type::Struct(s) = &mut t {
+                let mut field_names = HashMap::new();
+                s.0.retain(|(fname, fty, .., fspan)| {
+                    let first = match field_names.get(fname) {
+                        Some(first) => *first,
+                        None => {
+                            field_names.insert(*fname, *fspan);
+                            check_field_shape(n, fname, fty, *fspan, errors);
+                            return true;
+                        }
+                    };
+                    errors.push(
+                        Diagnostic::error(
+                            Code::Duplicate,
+                            format!("Duplicate field '{}' on struct '{}'", fname, n),
+                        )
+                        .with_span(*fspan)
+                        .with_label(first, "first declared here"),
+                    );
+                    false
+                });
             }
-            cache.insert(*n, t.clone());
+            declared_at.insert(*n, *span);
+            cache.insert(*n, t);
         }
     }
 
-    Ok(cache)
+    cache
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -56,6 +866,7 @@ pub enum Builtin {
 pub struct EnumVariant<'a> {
     pub name: &'a str,
     pub value: usize,
+    pub doc: ast::Doc<'a>,
 }
 #[derive(Clone, PartialEq, Debug)]
 pub enum EnumRepr {
@@ -76,6 +887,12 @@ impl Display for EnumRepr {
 pub struct Enum<'a> {
     pub repr: EnumRepr,
     pub variants: Vec<EnumVariant<'a>>,
+    pub doc: ast::Doc<'a>,
+    pub attrs: Vec<Attribute<'a>>,
+}
+impl<'a> Enum<'a> {
+    /// Bits needed to write the index of a variant in packed exports
+    pub fn packed_bits(&self) -> u32 { 32 - (self.variants.len() as u32 - 1).leading_zeros() }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -84,10 +901,121 @@ pub struct StructField<'a> {
     pub r#type: Ptr<(&'a str, ResolvedType<'a>)>,
     pub array: bool,
     pub optional: bool,
+    pub doc: ast::Doc<'a>,
+    pub attrs: Vec<Attribute<'a>>,
+    /// Values outside of the range fail to be read or written
+    pub range: Option<Range<'a>>,
+    /// Name of an earlier field in the same struct which holds the length of
+    /// this array, instead of it being written before the items
+    pub length: Option<&'a str>,
+    /// The field is only present when the condition holds, instead of being
+    /// preceded by a presence byte like optional fields
+    pub condition: Option<Condition<'a>>,
+    /// Identifies the field in an extensible struct
+    pub tag: Option<u16>,
+}
+impl<'a> StructField<'a> {
+    /// Whether the field may be missing, either because it's optional or
+    /// conditional
+    pub fn is_optional(&self) -> bool { self.optional || self.condition.is_some() }
+
+    /// The identifier used for this field in generated code
+    pub fn ident(&self) -> &'a str {
+        self.attrs
+            .iter()
+            .find_map(|a| match a {
+                Attribute::Rename(ident) => Some(*ident),
+                _ => None,
+            })
+            .unwrap_or(self.name)
+    }
 }
 #[derive(Clone, PartialEq, Debug)]
 pub struct Struct<'a> {
+    /// All of the struct's fields, starting with the ones of its base
     pub fields: Vec<StructField<'a>>,
+    pub doc: ast::Doc<'a>,
+    pub attrs: Vec<Attribute<'a>>,
+    /// The struct this one extends
+    pub base: Option<Ptr<(&'a str, ResolvedType<'a>)>>,
+}
+impl<'a> Struct<'a> {
+    pub fn is_extensible(&self) -> bool { self.attrs.contains(&Attribute::Extensible) }
+    pub fn is_delta(&self) -> bool { self.attrs.contains(&Attribute::Delta) }
+    pub fn is_packed(&self) -> bool { self.attrs.contains(&Attribute::Packed) }
+
+    /// The fields declared by the struct itself, rather than its base
+    pub fn own_fields(&self) -> &[StructField<'a>] {
+        let inherited = match &self.base {
+            Some(base) => match &base.borrow().1 {
+                ResolvedType::Struct(s) => s.fields.len(),
+                _ => 0,
+            },
+            None => 0,
+        };
+        &self.fields[inherited..]
+    }
+
+    /// The optional fields whose presence is written as a leading bitmask,
+    /// if the struct uses one. Conditional fields don't need to be in it.
+    pub fn bitmask_fields(&self) -> Vec<&StructField<'a>> {
+        if !self.attrs.contains(&Attribute::Bitmask) {
+            return Vec::new();
+        }
+        self.fields.iter().filter(|f| f.optional).collect()
+    }
+}
+
+/// Bounds of a range constraint, as written in the schema. On strings, they
+/// apply to the length.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Range<'a> {
+    pub min: Option<&'a str>,
+    pub max: Option<&'a str>,
+    /// Whether `max` is part of the range
+    pub inclusive: bool,
+}
+impl<'a> Range<'a> {
+    /// The lower bound and the bits needed to write a value's offset from it
+    /// in packed exports, if both bounds are integers
+    pub fn packed_bits(&self) -> Option<(i64, u32)> {
+        let min = self.min?.parse::<i64>().ok()?;
+        let max = self.max?.parse::<i64>().ok()?;
+        let values = (max - min) as u64 + self.inclusive as u64;
+        Some((min, 64 - (values - 1).leading_zeros()))
+    }
+}
+
+/// A field is present when the enum `field` holds `variant`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Condition<'a> {
+    pub field: &'a str,
+    pub r#enum: &'a str,
+    pub variant: &'a str,
+}
+
+/// Built-in attributes, which may be attached to declarations and fields
+#[derive(Clone, PartialEq, Debug)]
+pub enum Attribute<'a> {
+    /// `@deprecated` or `@deprecated("reason")`
+    Deprecated(Option<&'a str>),
+    /// `@rename(ident)`, only on fields. Changes the identifier used for the
+    /// field in generated code, but not the wire format.
+    Rename(&'a str),
+    /// `@since(version)`, the version of the schema the item was added in
+    Since(&'a str),
+    /// `@bitmask`, only on structs. The presence of optional fields is written
+    /// as a leading bitmask, instead of a byte before each field.
+    Bitmask,
+    /// `@extensible`, only on structs. Each field is written with its tag and
+    /// length, so that readers can skip the fields they don't know about.
+    Extensible,
+    /// `@delta`, only on exported structs. They can also be written as the
+    /// fields which changed from a baseline.
+    Delta,
+    /// `@packed`, only on exported structs. The whole export is written as a
+    /// bit stream, where values only take as many bits as they need.
+    Packed,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -120,6 +1048,7 @@ impl<T> std::ops::Deref for Ptr<T> {
 #[derive(Clone, PartialEq, Debug)]
 pub struct Export<'a> {
     pub name: &'a str,
+    pub id: Option<u8>,
     pub r#struct: Struct<'a>,
 }
 
@@ -139,57 +1068,181 @@ fn get_builtins<'a>() -> HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>> {
 }
 
 fn resolve_struct_field<'a>(
-    fname: &'a str,
-    fty: ast::Unresolved<'a>,
+    (fname, fty, doc, attrs, span): ast::Field<'a>,
     resolved: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
     ttypename: &'a str,
-) -> Result<Option<StructField<'a>>, String> {
+    errors: &mut Vec<Diagnostic>,
+) -> Option<StructField<'a>> {
     match resolved.get(&fty.0) {
         Some(rty) => {
-            if fty.1 && fty.2 {
-                return Err(format!(
-                    "Field '{}' in struct '{}' cannot be optional and array at once",
-                    fname, ttypename
-                ));
-            }
-            Ok(Some(StructField {
+            let target = format!("field '{}' in struct '{}'", fname, ttypename);
+            let range = fty.3.and_then(|range| {
+                resolve_range(range, &rty.borrow(), &target, span)
+                    .map_err(|err| errors.push(err))
+                    .ok()
+            });
+            let attrs = resolve_attributes(attrs, &target, AttributeTarget::Field, span, errors);
+            Some(StructField {
                 name: fname,
                 r#type: rty.clone(),
                 array: fty.1,
                 optional: fty.2,
-            }))
+                doc,
+                attrs,
+                range,
+                length: fty.4,
+                condition: fty
+                    .5
+                    .map(|ast::Condition(field, r#enum, variant, _)| Condition { field, r#enum, variant }),
+                tag: fty.6,
+            })
+        }
+        None => None,
+    }
+}
+
+/// Checks that the bounds of a range make sense for the type it's applied to.
+/// Ranges on strings constrain their length.
+#[allow(clippy::result_large_err)]
+fn resolve_range<'a>(
+    range: ast::Range<'a>,
+    (name, ty): &(&'a str, ResolvedType<'a>),
+    target: &str,
+    span: Span,
+) -> Result<Range<'a>, Diagnostic> {
+    let ast::Range(min, max, inclusive) = range;
+    let builtin = match ty {
+        ResolvedType::Builtin(b) => b,
+        _ => {
+            return Err(Diagnostic::error(
+                Code::Range,
+                format!("Range on {} is only supported on numbers and strings", target),
+            )
+            .with_span(span))
+        }
+    };
+    if min.is_none() && max.is_none() {
+        return Err(
+            Diagnostic::error(Code::Range, format!("Range on {} must have at least one bound", target)).with_span(span),
+        );
+    }
+    if inclusive && max.is_none() {
+        return Err(Diagnostic::error(
+            Code::Range,
+            format!("Inclusive range on {} must have an upper bound", target),
+        )
+        .with_span(span));
+    }
+    // the bounds a value of this type can have, and whether they may be fractional
+    let (lower, upper, fractional) = match builtin {
+        Builtin::Uint8 => (0f64, u8::MAX as f64, false),
+        Builtin::Uint16 => (0f64, u16::MAX as f64, false),
+        Builtin::Uint32 | Builtin::String => (0f64, u32::MAX as f64, false),
+        Builtin::Int8 => (i8::MIN as f64, i8::MAX as f64, false),
+        Builtin::Int16 => (i16::MIN as f64, i16::MAX as f64, false),
+        Builtin::Int32 => (i32::MIN as f64, i32::MAX as f64, false),
+        Builtin::Float => (f32::MIN as f64, f32::MAX as f64, true),
+    };
+    // an exclusive upper bound may be one past the largest integer, e.g.
+    // `0..256` on a uint8
+    let max_limit = if inclusive || fractional { upper } else { upper + 1.0 };
+    let parse = |bound: &str, limit: f64| -> Result<f64, Diagnostic> {
+        let value = bound.parse::<f64>().unwrap();
+        if (!fractional && bound.contains('.')) || value < lower || value > limit {
+            return Err(Diagnostic::error(
+                Code::Range,
+                format!(
+                    "Range bound '{}' on {} does not fit in type '{}'",
+                    bound,
+                    target,
+                    if let Builtin::String = builtin { "uint32" } else { name }
+                ),
+            )
+            .with_span(span));
+        }
+        Ok(value)
+    };
+    let min_value = min.map(|bound| parse(bound, upper)).transpose()?;
+    let max_value = max.map(|bound| parse(bound, max_limit)).transpose()?;
+    if let (Some(min), Some(max)) = (min_value, max_value) {
+        if min > max || (!inclusive && min == max) {
+            return Err(Diagnostic::error(Code::Range, format!("Range on {} is empty", target)).with_span(span));
         }
-        None => Ok(None),
     }
+    // the generated code compares values with the bound in their own type,
+    // so one past the largest value is turned into an inclusive bound
+    if matches!(max_value, Some(max) if max > upper) {
+        let max = match builtin {
+            Builtin::Uint8 => "255",
+            Builtin::Uint16 => "65535",
+            Builtin::Int8 => "127",
+            Builtin::Int16 => "32767",
+            Builtin::Int32 => "2147483647",
+            Builtin::Uint32 | Builtin::String => "4294967295",
+            Builtin::Float => unreachable!(),
+        };
+        return Ok(Range {
+            min,
+            max: Some(max),
+            inclusive: true,
+        });
+    }
+    Ok(Range { min, max, inclusive })
 }
 
-fn resolve_enum<'a>(name: &'a str, ty: ast::Enum<'a>) -> Result<(EnumRepr, Vec<EnumVariant<'a>>), String> {
+fn resolve_enum<'a>(
+    name: &'a str,
+    ty: ast::Enum<'a>,
+    span: Option<Span>,
+    errors: &mut Vec<Diagnostic>,
+) -> (EnumRepr, Vec<EnumVariant<'a>>) {
     // find the smallest possible representation for this enum
     let repr = match ty.0.len() {
-        n if n == 0 => return Err(format!("Enum '{}' must have at least one variant", name)),
+        0 => {
+            errors.push(
+                Diagnostic::error(Code::Enum, format!("Enum '{}' must have at least one variant", name))
+                    .with_span(span),
+            );
+            EnumRepr::U8
+        }
         n if n <= 8 => EnumRepr::U8,
         n if n <= 16 => EnumRepr::U16,
         n if n <= 32 => EnumRepr::U32,
-        n => return Err(format!("Enum '{}' has too many variants ({}/32)", name, n)),
+        n => {
+            errors.push(
+                Diagnostic::error(Code::Enum, format!("Enum '{}' has too many variants ({}/32)", name, n))
+                    .with_span(span),
+            );
+            EnumRepr::U32
+        }
     };
     // resolve the variants by assigning each one to a single bit
-    let mut variant_names = HashSet::new();
+    let mut variant_names = HashMap::new();
     let mut count = 0usize;
     let mut variants = Vec::with_capacity(ty.0.len());
-    for variant in ty.0.into_iter() {
-        if variant_names.contains(&variant) {
-            return Err(format!("Duplicate variant '{}' on enum '{}'", variant, name));
+    for (variant, doc, variant_span) in ty.0.into_iter() {
+        if let Some(first) = variant_names.get(&variant) {
+            errors.push(
+                Diagnostic::error(
+                    Code::Duplicate,
+                    format!("Duplicate variant '{}' on enum '{}'", variant, name),
+                )
+                .with_span(variant_span)
+                .with_label(*first, "first declared here"),
+            );
+            continue;
         }
-        variant_names.insert(variant);
+        variant_names.insert(variant, variant_span);
         variants.push(EnumVariant {
             name: variant,
             value: {
                 count += 1;
                 count - 1
             },
+            doc,
         });
     }
-    Ok((repr, variants))
+    (repr, variants)
 }
 
 fn resolve_one_first_pass<'a>(
@@ -198,361 +1251,2399 @@ fn resolve_one_first_pass<'a>(
     builtins: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
     first_pass: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
     unresolved: &mut HashMap<&'a str, ast::Type<'a>>,
-) -> Result<(), String> {
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+) {
     match ty {
         ast::Type::Enum(e) => {
-            match resolve_enum(name, e) {
-                Ok(rty) => {
-                    unresolved.remove(name);
-                    first_pass.insert(
-                        name,
-                        Ptr::new((
-                            name,
-                            ResolvedType::Enum(Enum {
-                                repr: rty.0,
-                                variants: rty.1,
-                            }),
-                        )),
-                    );
-                }
-                Err(err) => return Err(err),
-            };
+            let (repr, variants) = resolve_enum(name, e, spans.decl(name), errors);
+            unresolved.remove(name);
+            first_pass.insert(
+                name,
+                Ptr::new((
+                    name,
+                    ResolvedType::Enum(Enum {
+                        repr,
+                        variants,
+                        doc: vec![],
+                        attrs: vec![],
+                    }),
+                )),
+            );
         }
-        ast::Type::Struct(s) => {
-            let mut field_names = HashSet::new();
-            let mut fields = Vec::new();
-            for (fname, fty) in s.0.iter() {
-                if field_names.contains(&fname) {
-                    return Err(format!("Duplicate field '{}' on struct '{}'", fname, name));
-                }
-                field_names.insert(fname);
-                if let Some(field) = resolve_struct_field(fname, fty.clone(), builtins, name)? {
-                    fields.push(field);
-                } else {
-                    break;
-                }
-            }
-            if fields.len() == s.0.len() {
-                unresolved.remove(&name);
-                first_pass.insert(name, Ptr::new((name, ResolvedType::Struct(Struct { fields }))));
-            }
+        // structs which extend another are resolved once their base is
+        ast::Type::Struct(s) if s.1.is_none() && s.0.iter().all(|(_, fty, ..)| builtins.contains_key(fty.0)) => {
+            let fields =
+                s.0.into_iter()
+                    .filter_map(|field| resolve_struct_field(field, builtins, name, errors))
+                    .collect();
+            unresolved.remove(&name);
+            first_pass.insert(
+                name,
+                Ptr::new((
+                    name,
+                    ResolvedType::Struct(Struct {
+                        fields,
+                        doc: vec![],
+                        attrs: vec![],
+                        base: None,
+                    }),
+                )),
+            );
         }
+        ast::Type::Struct(_) => (),
     }
-    Ok(())
 }
 
-// This should consume the AST and return a type-checked version
 fn resolve_first_pass<'a>(
-    ast: ast::AST<'a>,
     builtins: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
     first_pass: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
     unresolved: &mut HashMap<&'a str, ast::Type<'a>>,
-) -> Result<(), String> {
-    for node in ast {
-        if let ast::Node::Decl(name, ty) = node {
-            if let Err(err) = resolve_one_first_pass(name, ty, builtins, first_pass, unresolved) {
-                return Err(err);
-            }
-        }
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    for (name, ty) in unresolved.clone() {
+        resolve_one_first_pass(name, ty, builtins, first_pass, unresolved, spans, errors);
     }
-    Ok(())
 }
 
+/// Resolves the struct `name` once the types it uses are, returning whether it
+/// could be. A type which fails to resolve is only reported once, rather than
+/// by every struct that uses it.
 fn resolve_one_second_pass<'a>(
     name: &'a str,
-    ty: ast::Type<'a>,
     cache: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
     visited: &mut HashSet<&'a str>,
-    unresolved: &mut HashMap<&'a str, ast::Type<'a>>,
-) -> Result<(), String> {
+    failed: &mut HashSet<&'a str>,
+    unresolved: &HashMap<&'a str, ast::Type<'a>>,
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+) -> bool {
     // if it's already resolved, dont resolve again
     if cache.contains_key(&name) {
-        return Ok(());
+        return true;
+    }
+    if failed.contains(&name) {
+        return false;
     }
     // otherwise try to resolve it
-    if let ast::Type::Struct(s) = ty {
+    if let ast::Type::Struct(s) = unresolved[name].clone() {
         // if we've already visited this type, it's a cycle
         if visited.contains(&name) {
-            return Err(format!(
-                "Found a cycle between two or more top level definitions in type '{}'",
-                &name
-            ));
+            errors.push(
+                Diagnostic::error(
+                    Code::Cycle,
+                    format!(
+                        "Found a cycle between two or more top level definitions in type '{}'",
+                        &name
+                    ),
+                )
+                .with_span(spans.decl(name)),
+            );
+            failed.insert(name);
+            return false;
         }
         visited.insert(name);
-        // iterate over each field, trying to resolve it
-        // store any field (+ its type) which could not be resolved
+        // store the name of any type (the base, or a field's type) which
+        // isn't resolved yet
         let mut not_resolved = Vec::new();
-        let mut fields = Vec::new();
-        for (field_name, field_type) in s.0.into_iter() {
-            if let Some(field) = resolve_struct_field(field_name, field_type.clone(), cache, name)? {
-                fields.push(field);
-            } else {
-                not_resolved.push((field_name, field_type));
+        if let Some(base) = s.1 {
+            if !cache.contains_key(&base.0) {
+                not_resolved.push(base);
             }
         }
-        if not_resolved.is_empty() {
-            // if all the fields are resolved, construct the type and cache it
-            cache.insert(name, Ptr::new((name, ResolvedType::Struct(Struct { fields }))));
-        } else {
-            // otherwise, for each field that couldn't be resolved, try to resolve it
-            for (_, field_type) in not_resolved.iter() {
-                let ftype_name = field_type.0;
-                // try to find the field's typename in whatever is left unresolved
-                if let Some(utype) = unresolved.remove(&ftype_name) {
-                    // if it exists, try to resolve it by recursively calling
-                    // the function we're in
-
-                    // it may fail, so propagate the error out
-                    resolve_one_second_pass(ftype_name, utype, cache, visited, unresolved)?;
-                } else if !cache.contains_key(&ftype_name) {
-                    //  if the field's typename is unresolved and not in the cache (resolved), it
-                    // doesn't exist.
-                    return Err(format!("Declaration for type '{}' does not exist", ftype_name));
-                }
+        for (_, fty, ..) in s.0.iter() {
+            if !cache.contains_key(&fty.0) {
+                not_resolved.push((fty.0, fty.7));
             }
-            // if we get here, it means all the field's types were successfully resolved and
-            // placed in the cache so finish resolving our fields
-            let mut now_resolved = Vec::new();
-            for (fname, fty) in not_resolved.into_iter() {
-                now_resolved.push(resolve_struct_field(fname, fty, cache, name)?.unwrap());
+        }
+        // for each type that isn't resolved yet, try to resolve it
+        let mut resolved = true;
+        for (ftype_name, ftype_span) in not_resolved.into_iter() {
+            if unresolved.contains_key(&ftype_name) {
+                // if it's declared, try to resolve it by recursively calling
+                // the function we're in
+                resolved &= resolve_one_second_pass(ftype_name, cache, visited, failed, unresolved, spans, errors);
+            } else if !cache.contains_key(&ftype_name) {
+                // if the typename is not declared and not in the cache
+                // (resolved), it doesn't exist.
+                errors.push(
+                    Diagnostic::error(
+                        Code::Unresolved,
+                        format!("Declaration for type '{}' does not exist", ftype_name),
+                    )
+                    .with_span(ftype_span),
+                );
+                resolved = false;
             }
-            // and we have a complete type
-            cache.insert(
-                name,
-                Ptr::new((
-                    name,
-                    ResolvedType::Struct(Struct {
-                        fields: fields.into_iter().chain(now_resolved).collect(),
-                    }),
-                )),
-            );
         }
+        let base = match s.1 {
+            Some(base) if resolved => match resolve_base(name, base, cache) {
+                Ok(base) => Some(base),
+                Err(err) => {
+                    errors.push(err);
+                    resolved = false;
+                    None
+                }
+            },
+            _ => None,
+        };
+        if !resolved {
+            failed.insert(name);
+            return false;
+        }
+        // if we get here, it means all the types were successfully resolved and placed
+        // in the cache so finish resolving our fields, in the order they were declared
+        // in
+        let fields =
+            s.0.into_iter()
+                .filter_map(|field| resolve_struct_field(field, cache, name, errors))
+                .collect();
+        // and we have a complete type
+        cache.insert(
+            name,
+            Ptr::new((
+                name,
+                ResolvedType::Struct(Struct {
+                    fields: flatten_fields(name, base.as_ref(), fields, spans, errors),
+                    doc: vec![],
+                    attrs: vec![],
+                    base,
+                }),
+            )),
+        );
     } else {
         panic!(
             "Something unresolved which is not a struct got into the second pass: {:#?}",
-            ty
+            unresolved[name]
+        );
+    }
+    true
+}
+
+/// Finds the struct `name` extends, which must already be resolved
+#[allow(clippy::result_large_err)]
+fn resolve_base<'a>(
+    name: &str,
+    (base, span): (&'a str, Span),
+    cache: &HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+) -> Result<Ptr<(&'a str, ResolvedType<'a>)>, Diagnostic> {
+    let ty = cache[&base].clone();
+    if !matches!(ty.borrow().1, ResolvedType::Struct(_)) {
+        return Err(Diagnostic::error(
+            Code::Extends,
+            format!("Struct '{}' cannot extend '{}', which is not a struct", name, base),
+        )
+        .with_span(span));
+    }
+    Ok(ty)
+}
+
+/// Prepends the fields of `base` to the struct's own fields. Fields may not be
+/// declared by both, in which case the base's one is kept.
+fn flatten_fields<'a>(
+    name: &'a str,
+    base: Option<&Ptr<(&'a str, ResolvedType<'a>)>>,
+    fields: Vec<StructField<'a>>,
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+) -> Vec<StructField<'a>> {
+    let base = match base {
+        Some(base) => base.borrow(),
+        None => return fields,
+    };
+    let base_fields = match &base.1 {
+        ResolvedType::Struct(s) => &s.fields,
+        _ => unreachable!(),
+    };
+    let fields = fields.into_iter().filter(|field| {
+        if !base_fields.iter().any(|f| f.name == field.name) {
+            return true;
+        }
+        errors.push(
+            Diagnostic::error(
+                Code::Duplicate,
+                format!(
+                    "Field '{}' on struct '{}' is already declared by its base '{}'",
+                    field.name, name, base.0
+                ),
+            )
+            .with_span(spans.field(name, field.name)),
+        );
+        false
+    });
+    base_fields.iter().cloned().chain(fields).collect()
+}
+
+/// Resolves the structs left over from the first pass, leaving the ones which
+/// fail out of `cache`
+fn resolve_second_pass<'a>(
+    cache: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+    unresolved: HashMap<&'a str, ast::Type<'a>>,
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    let mut visited = HashSet::new();
+    let mut failed = HashSet::new();
+    for name in unresolved.keys() {
+        resolve_one_second_pass(name, cache, &mut visited, &mut failed, &unresolved, spans, errors);
+    }
+}
+
+fn collect_used_types<'a>(visited: &mut HashSet<&'a str>, ty: &(&'a str, ResolvedType<'a>)) {
+    visited.insert(ty.0);
+    if let Some(ty) = ty.1.get_struct_variant() {
+        let base = ty.base.iter();
+        for used in base.chain(ty.fields.iter().map(|f| &f.r#type)) {
+            let used = &*used.borrow();
+            if visited.contains(&used.0) {
+                continue;
+            }
+            collect_used_types(visited, used);
+        }
+    }
+}
+
+fn remove_unused<'a>(
+    visited: HashSet<&'a str>,
+    declared: &[&'a str],
+    resolved: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType)>>,
+    spans: &Spans<'a>,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    for name in declared.iter().filter(|name| !visited.contains(*name)) {
+        warnings.push(
+            Diagnostic::warning(
+                Code::UnusedDeclaration,
+                format!("Type '{}' is declared but not used by any export", name),
+            )
+            .with_span(spans.decl(name)),
+        );
+    }
+    resolved.retain(|name, _| visited.contains(name));
+}
+
+fn resolve_exports<'a>(
+    names: Vec<(&'a str, Option<u8>)>,
+    declared: &[&'a str],
+    resolved: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+    spans: &Spans<'a>,
+    errors: &mut Vec<Diagnostic>,
+    warnings: &mut Vec<Diagnostic>,
+) -> Vec<Export<'a>> {
+    // exports with packet ids are wrapped in a generated `Message` type and
+    // dispatched through a generated `Handler`
+    if names.iter().any(|(_, id)| id.is_some()) {
+        for generated in ["Message", "Handler"].iter() {
+            if resolved.contains_key(generated) {
+                errors.push(
+                    Diagnostic::error(
+                        Code::Duplicate,
+                        format!(
+                            "Declaration '{}' conflicts with a generated type of the same name",
+                            generated
+                        ),
+                    )
+                    .with_span(spans.decl(generated)),
+                );
+            }
+        }
+    }
+    let mut exports = Vec::with_capacity(names.len());
+    let mut visited = HashSet::new();
+    for (name, id) in names {
+        if let Some(export) = resolved.get(&name).cloned() {
+            if let Some(ty) = export.borrow().1.get_struct_variant() {
+                collect_used_types(&mut visited, &export.borrow());
+                exports.push(Export { name, id, r#struct: ty });
+            } else {
+                errors.push(
+                    Diagnostic::error(
+                        Code::Export,
+                        format!("Attempted to export '{}', which is not a struct", name),
+                    )
+                    .with_span(spans.export(name)),
+                );
+            }
+        } else {
+            errors.push(
+                Diagnostic::error(Code::Unresolved, format!("Export '{}' could not be resolved", name))
+                    .with_span(spans.export(name)),
+            );
+        }
+    }
+    // delta encoding and packing are generated along with the export's read
+    // and write
+    for name in declared {
+        if let ResolvedType::Struct(s) = &resolved[name].borrow().1 {
+            for (attr, attr_name) in [(Attribute::Delta, "delta"), (Attribute::Packed, "packed")].iter() {
+                if s.attrs.contains(attr) && !exports.iter().any(|e| e.name == *name) {
+                    errors.push(
+                        Diagnostic::error(
+                            Code::Attribute,
+                            format!(
+                                "Attribute '@{}' on declaration '{}' can only be used on exported structs",
+                                attr_name, name
+                            ),
+                        )
+                        .with_span(spans.decl(name)),
+                    );
+                }
+            }
+        }
+    }
+    for export in exports.iter().filter(|e| e.r#struct.is_packed()) {
+        if export.r#struct.is_delta() {
+            errors.push(
+                Diagnostic::error(
+                    Code::Attribute,
+                    format!("Struct '{}' cannot be both @packed and @delta", export.name),
+                )
+                .with_span(spans.decl(export.name)),
+            );
+            continue;
+        }
+        // the lengths of tagged fields are patched in at byte positions, which
+        // a bit stream doesn't have
+        let mut used = HashSet::new();
+        collect_used_types(&mut used, &resolved[export.name].borrow());
+        let mut used = used.into_iter().collect::<Vec<_>>();
+        used.sort_unstable();
+        for name in used {
+            if let Some(ResolvedType::Struct(s)) = resolved.get(name).map(|ty| ty.borrow().1.clone()) {
+                if s.is_extensible() {
+                    errors.push(
+                        Diagnostic::error(
+                            Code::Attribute,
+                            format!(
+                                "Struct '{}' is @extensible, so it can't be used in @packed export '{}'",
+                                name, export.name
+                            ),
+                        )
+                        .with_span(spans.decl(name).or_else(|| spans.export(export.name))),
+                    );
+                }
+            }
+        }
+    }
+    if exports.is_empty() {
+        // a schema without exports only declares types for other schemas to import
+        for name in declared {
+            collect_used_types(&mut visited, &resolved[name].borrow());
+        }
+    }
+    // Use this opportunity to discard types which aren't used by any export.
+    remove_unused(visited, declared, resolved, spans, warnings);
+
+    exports
+}
+
+pub type TypeMap<'a> = HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Resolved<'a> {
+    pub exports: Vec<Export<'a>>,
+    /// Types declared by this schema, and the builtins they use
+    pub types: TypeMap<'a>,
+    /// Package the types are declared in, empty if the schema doesn't
+    /// declare one
+    pub package: Vec<&'a str>,
+    /// Imported types used by this schema, grouped by the module they're
+    /// declared in
+    pub imports: Vec<ImportedTypes<'a>>,
+    /// Problems which don't stop the schema from compiling, sorted by location
+    pub warnings: Vec<Diagnostic>,
+    /// Names of the types declared by this schema, in the order they're
+    /// declared in. Unused ones are left out of `types`.
+    pub declared: Vec<&'a str>,
+}
+
+impl<'a> Resolved<'a> {
+    /// The types declared by this schema which are used, in source order
+    pub fn declarations(&self) -> Vec<Ptr<(&'a str, ResolvedType<'a>)>> {
+        self.declared
+            .iter()
+            .filter_map(|name| self.types.get(name).cloned())
+            .collect()
+    }
+
+    /// The schema as it's encoded on the wire, without comments, formatting
+    /// or attributes which only affect the generated code. Types are sorted
+    /// by name, and the imported ones which are used are included as well.
+    pub fn canonical(&self) -> String {
+        let mut types = BTreeMap::new();
+        for ty in self.types.values() {
+            canonical_type(&ty.borrow(), &mut types);
+        }
+        for export in self.exports.iter() {
+            for field in export.r#struct.fields.iter() {
+                canonical_type(&field.r#type.borrow(), &mut types);
+            }
+        }
+        let mut out = types.into_values().collect::<Vec<_>>();
+        for export in self.exports.iter() {
+            match export.id {
+                Some(id) => out.push(format!("export {} = {}", export.name, id)),
+                None => out.push(format!("export {}", export.name)),
+            }
+        }
+        out.join("\n")
+    }
+
+    /// 32-bit FNV-1a hash of the canonical schema, which differs between
+    /// schemas that aren't wire-compatible
+    pub fn fingerprint(&self) -> u32 {
+        self.canonical()
+            .bytes()
+            .fold(0x811c_9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+    }
+}
+
+/// Adds the canonical declaration of `ty` and of the types it uses to `out`
+fn canonical_type<'a>((name, ty): &(&'a str, ResolvedType<'a>), out: &mut BTreeMap<&'a str, String>) {
+    if out.contains_key(name) {
+        return;
+    }
+    match ty {
+        ResolvedType::Builtin(_) => (),
+        ResolvedType::Enum(e) => {
+            let variants = e
+                .variants
+                .iter()
+                .map(|v| format!("{} = {}", v.name, v.value))
+                .collect::<Vec<_>>();
+            out.insert(name, format!("{}: enum {} {{ {} }}", name, e.repr, variants.join(", ")));
+        }
+        ResolvedType::Struct(s) => {
+            let mut fields = Vec::new();
+            for field in s.fields.iter() {
+                canonical_type(&field.r#type.borrow(), out);
+                let mut decl = field.name.to_string();
+                if field.optional {
+                    decl += "?";
+                }
+                if let Some(tag) = field.tag {
+                    decl += &format!(" @{}", tag);
+                }
+                decl += &format!(": {}", field.r#type.borrow().0);
+                if field.array {
+                    decl += &format!("[{}]", field.length.unwrap_or(""));
+                }
+                if let Some(range) = &field.range {
+                    let op = if range.inclusive { "..=" } else { ".." };
+                    decl += &format!(" in {}{}{}", range.min.unwrap_or(""), op, range.max.unwrap_or(""));
+                }
+                if let Some(cond) = &field.condition {
+                    decl += &format!(" if {} == {}.{}", cond.field, cond.r#enum, cond.variant);
+                }
+                fields.push(decl);
+            }
+            let attrs = s
+                .attrs
+                .iter()
+                .filter_map(|attr| match attr {
+                    Attribute::Bitmask => Some("@bitmask "),
+                    Attribute::Extensible => Some("@extensible "),
+                    Attribute::Packed => Some("@packed "),
+                    _ => None,
+                })
+                .collect::<String>();
+            out.insert(name, format!("{}{}: struct {{ {} }}", attrs, name, fields.join(", ")));
+        }
+    }
+}
+
+/// Types which are used from an imported schema
+#[derive(Clone, PartialEq, Debug)]
+pub struct ImportedTypes<'a> {
+    /// See [`Import::module`]
+    pub module: String,
+    pub package: Vec<&'a str>,
+    pub names: Vec<&'a str>,
+}
+
+/// Moves the used imported types out of `resolved`, because they're generated
+/// as part of the imported schema's module.
+fn collect_imports<'a>(
+    imports: &[Import<'a>],
+    resolved: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
+) -> Vec<ImportedTypes<'a>> {
+    let mut out = Vec::new();
+    for import in imports {
+        let mut names = import
+            .types
+            .keys()
+            .copied()
+            .filter(|name| resolved.remove(name).is_some())
+            .collect::<Vec<_>>();
+        if !names.is_empty() {
+            names.sort_unstable();
+            out.push(ImportedTypes {
+                module: import.module.clone(),
+                package: import.package.clone(),
+                names,
+            });
+        }
+    }
+    out
+}
+
+pub fn type_check(ast: ast::AST<'_>) -> Result<Resolved<'_>, Vec<Diagnostic>> {
+    type_check_with_imports(ast, Vec::new())
+}
+
+/// Type checks a schema which may use the types declared by `imports`
+pub fn type_check_with_imports<'a>(
+    ast: ast::AST<'a>,
+    imports: Vec<Import<'a>>,
+) -> Result<Resolved<'a>, Vec<Diagnostic>> {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let spans = Spans::collect(&ast);
+    let mut exports = get_exports(&ast, &mut errors);
+    let package = get_package(&ast, &mut errors);
+    let meta = collect_decl_meta(&ast, &mut errors);
+    let mut unresolved = collect_types(&ast, &mut errors);
+    check_import_names(&unresolved, &imports, &spans, &mut errors);
+    // in source order, which the generated declarations follow
+    let mut seen = HashSet::new();
+    let mut declared = ast
+        .iter()
+        .filter_map(|node| match node {
+            ast::Node::Decl(name, ..) => Some(*name),
+            _ => None,
+        })
+        .filter(|name| seen.insert(*name))
+        .collect::<Vec<_>>();
+
+    // pre-pass: collect builtins + the types of directly imported schemas
+    let mut cache = get_builtins();
+    for import in imports.iter().filter(|i| i.direct) {
+        cache.extend(import.types.iter().map(|(name, ty)| (*name, ty.clone())));
+    }
+    // first pass: collect enums + structs with only builtins as field types
+    let mut first_pass = HashMap::new();
+    resolve_first_pass(&cache, &mut first_pass, &mut unresolved, &spans, &mut errors);
+    // second pass: collect structs with other structs (made up of builtins) as
+    // field types
+    let mut cache = cache.into_iter().chain(first_pass).collect::<HashMap<_, _>>();
+    resolve_second_pass(&mut cache, unresolved, &spans, &mut errors);
+    // types which failed to resolve have been reported already, checking what
+    // uses them would only repeat that
+    let failed = declared
+        .iter()
+        .filter(|name| !cache.contains_key(*name))
+        .copied()
+        .collect::<HashSet<_>>();
+    declared.retain(|name| !failed.contains(name));
+    exports.retain(|(name, _)| !failed.contains(name));
+    attach_decl_meta(meta, &cache);
+    check_field_idents(&declared, &cache, &spans, &mut errors);
+    check_field_refs(&declared, &cache, &spans, &mut errors);
+    check_tags(&declared, &cache, &spans, &mut errors);
+    check_field_case(&declared, &cache, &spans, &mut warnings);
+    check_type_names(&declared, &imports, &spans, &mut warnings);
+    check_unused_variants(&declared, &cache, &spans, &mut warnings);
+    // types of transitively imported schemas can't be named, but may still be
+    // used through the fields of an imported struct
+    for import in imports.iter().filter(|i| !i.direct) {
+        cache.extend(import.types.iter().map(|(name, ty)| (*name, ty.clone())));
+    }
+    // export pass: collect the resolved types we're exporting
+    let exports = resolve_exports(exports, &declared, &mut cache, &spans, &mut errors, &mut warnings);
+    if !errors.is_empty() {
+        // errors without a location come last
+        errors.sort_by_key(|e| (e.primary_span.is_none(), e.primary_span));
+        return Err(errors);
+    }
+    warnings.sort_by_key(|w| (w.primary_span.is_none(), w.primary_span));
+    let imports = collect_imports(&imports, &mut cache);
+    Ok(Resolved {
+        exports,
+        types: cache,
+        package,
+        imports,
+        warnings,
+        declared,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn type_check_passes() {
+        // check if a valid AST containing all language features passes the type check
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "Flag",
+                Type::Enum(Enum(vec![
+                    ("A", vec![], Span::default()),
+                    ("B", vec![], Span::default()),
+                ])),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "Position",
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "x",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "y",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "Value",
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "a",
+                            Unresolved("uint32", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "b",
+                            Unresolved("int32", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "c",
+                            Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "d",
+                            Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "ComplexType",
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "flag",
+                            Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "pos",
+                            Unresolved("Position", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "names",
+                            Unresolved("string", true, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "values",
+                            Unresolved("Value", true, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("ComplexType", None, Span::default()),
+        ];
+        // TODO: check equality of Resolved AST instead of checking if this is an error
+        // Rc<T> == Rc<T> if T == T, according to https://doc.rust-lang.org/src/alloc/rc.rs.html#1325
+        type_check(test).unwrap();
+    }
+
+    #[test]
+    fn empty_enum() {
+        // an enum must have at least one variant
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl("Flag", Type::Enum(Enum(vec![])), vec![], vec![], Span::default()),
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "flag",
+                        Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Test", None, Span::default()),
+        ];
+        assert_eq!(
+            type_check(test).unwrap_err()[0].message,
+            "Enum 'Flag' must have at least one variant"
+        );
+    }
+
+    #[test]
+    fn optional_and_array() {
+        // field cannot be optional and array at once
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "a",
+                        Unresolved("uint8", true, true, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Test", None, Span::default()),
+        ];
+        assert_eq!(
+            type_check(test).unwrap_err()[0].message,
+            "Field 'a' in struct 'Test' cannot be optional and array at once"
+        );
+    }
+
+    #[test]
+    fn duplicate_export() {
+        // a type may only be exported once
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "Position",
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "x",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "y",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Position", None, Span::default()),
+            Node::Export("Position", None, Span::default()),
+        ];
+        let err = &type_check(test).unwrap_err()[0];
+        assert_eq!(err.code, Code::Duplicate);
+        assert_eq!(err.message, "Schema has duplicate export: Position");
+    }
+
+    #[test]
+    fn multiple_exports() {
+        // each export is resolved, and the types they share are kept once
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "Position",
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "x",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "y",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "Move",
+                Type::Struct(Struct(
+                    vec![(
+                        "to",
+                        Unresolved("Position", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "Spawn",
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "id",
+                            Unresolved("uint32", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "at",
+                            Unresolved("Position", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Move", None, Span::default()),
+            Node::Export("Spawn", None, Span::default()),
+        ];
+        let checked = type_check(test).unwrap();
+        assert_eq!(
+            checked.exports.iter().map(|e| e.name).collect::<Vec<_>>(),
+            vec!["Move", "Spawn"]
+        );
+        assert!(checked.types.contains_key("Position"));
+    }
+
+    #[test]
+    fn duplicate_packet_id() {
+        // two exports may not share a packet id
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "A",
+                Type::Struct(Struct(
+                    vec![(
+                        "v",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "B",
+                Type::Struct(Struct(
+                    vec![(
+                        "v",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("A", Some(1), Span::default()),
+            Node::Export("B", Some(1), Span::default()),
+        ];
+        assert_eq!(
+            type_check(test).unwrap_err()[0].message,
+            "Exports 'A' and 'B' have the same packet id (1)"
+        );
+    }
+
+    #[test]
+    fn missing_packet_id() {
+        // if one export has a packet id, all of them must have one
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "A",
+                Type::Struct(Struct(
+                    vec![(
+                        "v",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "B",
+                Type::Struct(Struct(
+                    vec![(
+                        "v",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("A", Some(1), Span::default()),
+            Node::Export("B", None, Span::default()),
+        ];
+        assert_eq!(
+            type_check(test).unwrap_err()[0].message,
+            "Export 'B' is missing a packet id"
+        );
+    }
+
+    #[test]
+    fn message_name_conflict() {
+        // `Message` and `Handler` are generated when exports have packet ids
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "Message",
+                Type::Struct(Struct(
+                    vec![(
+                        "v",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Message", Some(1), Span::default()),
+        ];
+        assert_eq!(
+            type_check(test).unwrap_err()[0].message,
+            "Declaration 'Message' conflicts with a generated type of the same name"
+        );
+    }
+
+    #[test]
+    fn no_export() {
+        // a schema file without exports only declares types to be imported
+        // elsewhere, so none of them are discarded
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "Flag",
+                Type::Enum(Enum(vec![
+                    ("A", vec![], Span::default()),
+                    ("B", vec![], Span::default()),
+                ])),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "Position",
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "x",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "y",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+        ];
+        let checked = type_check(test).unwrap();
+        assert!(checked.exports.is_empty());
+        assert!(checked.types.contains_key("Flag"));
+        assert!(checked.types.contains_key("Position"));
+    }
+
+    #[test]
+    fn imported_types_are_resolved() {
+        // types from an imported schema can be used, but aren't part of `types`
+        use ast::*;
+        let shared = type_check(vec![Node::Decl(
+            "Position",
+            Type::Struct(Struct(
+                vec![(
+                    "x",
+                    Unresolved("float", false, false, None, None, None, None, Span::default()),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                )],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::default(),
+        )])
+        .unwrap();
+        let test: AST = vec![
+            Node::Import("shared.pkt", Span::default()),
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "pos",
+                        Unresolved("Position", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Test", None, Span::default()),
+        ];
+        let imports = vec![Import {
+            module: "./shared".to_string(),
+            direct: true,
+            package: vec![],
+            types: shared.types.into_iter().filter(|(n, _)| *n == "Position").collect(),
+        }];
+        let checked = type_check_with_imports(test, imports).unwrap();
+        assert!(!checked.types.contains_key("Position"));
+        assert_eq!(
+            checked.imports,
+            vec![ImportedTypes {
+                module: "./shared".to_string(),
+                package: vec![],
+                names: vec!["Position"]
+            }]
+        );
+    }
+
+    #[test]
+    fn transitive_imports_are_not_visible() {
+        // types from a schema which is only imported transitively can't be named
+        use ast::*;
+        let shared = type_check(vec![Node::Decl(
+            "Flag",
+            Type::Enum(Enum(vec![("A", vec![], Span::default())])),
+            vec![],
+            vec![],
+            Span::default(),
+        )])
+        .unwrap();
+        let test: AST = vec![
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "flag",
+                        Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Test", None, Span::default()),
+        ];
+        let imports = vec![Import {
+            module: "./shared".to_string(),
+            direct: false,
+            package: vec![],
+            types: shared.types.into_iter().filter(|(n, _)| *n == "Flag").collect(),
+        }];
+        assert_eq!(
+            type_check_with_imports(test, imports).unwrap_err()[0].message,
+            "Declaration for type 'Flag' does not exist"
+        );
+    }
+
+    #[test]
+    fn transitive_imports_are_collected() {
+        // a type used through an imported struct is imported from its own schema
+        use ast::*;
+        let shared = type_check(vec![Node::Decl(
+            "Flag",
+            Type::Enum(Enum(vec![("A", vec![], Span::default())])),
+            vec![],
+            vec![],
+            Span::default(),
+        )])
+        .unwrap();
+        let shared_types = shared
+            .types
+            .into_iter()
+            .filter(|(n, _)| *n == "Flag")
+            .collect::<TypeMap>();
+        let entity = type_check_with_imports(
+            vec![
+                Node::Import("shared.pkt", Span::default()),
+                Node::Decl(
+                    "Entity",
+                    Type::Struct(Struct(
+                        vec![(
+                            "flag",
+                            Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        )],
+                        None,
+                    )),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+            ],
+            vec![Import {
+                module: "./shared".to_string(),
+                direct: true,
+                package: vec![],
+                types: shared_types.clone(),
+            }],
+        )
+        .unwrap();
+        let test: AST = vec![
+            Node::Import("entity.pkt", Span::default()),
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "entity",
+                        Unresolved("Entity", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Test", None, Span::default()),
+        ];
+        let imports = vec![
+            Import {
+                module: "./entity".to_string(),
+                direct: true,
+                package: vec![],
+                types: entity.types.into_iter().filter(|(n, _)| *n == "Entity").collect(),
+            },
+            Import {
+                module: "./shared".to_string(),
+                direct: false,
+                package: vec!["game", "common"],
+                types: shared_types,
+            },
+        ];
+        let checked = type_check_with_imports(test, imports).unwrap();
+        assert_eq!(
+            checked.imports,
+            vec![
+                ImportedTypes {
+                    module: "./entity".to_string(),
+                    package: vec![],
+                    names: vec!["Entity"]
+                },
+                ImportedTypes {
+                    module: "./shared".to_string(),
+                    package: vec!["game", "common"],
+                    names: vec!["Flag"]
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_declaration_across_imports() {
+        // a name may only be declared once across all imported schemas
+        use ast::*;
+        let shared = type_check(vec![Node::Decl(
+            "Flag",
+            Type::Enum(Enum(vec![("A", vec![], Span::default())])),
+            vec![],
+            vec![],
+            Span::default(),
+        )])
+        .unwrap();
+        let test: AST = vec![
+            Node::Decl(
+                "Flag",
+                Type::Enum(Enum(vec![("A", vec![], Span::default())])),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "flag",
+                        Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Test", None, Span::default()),
+        ];
+        let imports = vec![Import {
+            module: "./shared".to_string(),
+            direct: true,
+            package: vec![],
+            types: shared.types.into_iter().filter(|(n, _)| *n == "Flag").collect(),
+        }];
+        assert_eq!(
+            type_check_with_imports(test, imports).unwrap_err()[0].message,
+            "Type 'Flag' is declared in both this schema and './shared'"
+        );
+    }
+
+    #[test]
+    fn doc_comments() {
+        // doc comments are carried over to the resolved types
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "Flag",
+                Type::Enum(Enum(vec![("A", vec!["The first one"], Span::default())])),
+                vec!["A flag"],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "hp",
+                            Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                            vec!["Health points"],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "flag",
+                            Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec!["A test"],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Test", None, Span::default()),
+        ];
+        let checked = type_check(test).unwrap();
+        let test = checked.exports[0].r#struct.clone();
+        assert_eq!(test.doc, vec!["A test"]);
+        assert_eq!(test.fields[0].doc, vec!["Health points"]);
+        let flag = checked.types["Flag"].borrow();
+        match &flag.1 {
+            ResolvedType::Enum(e) => {
+                assert_eq!(e.doc, vec!["A flag"]);
+                assert_eq!(e.variants[0].doc, vec!["The first one"]);
+            }
+            _ => panic!("Flag should be an enum"),
+        };
+    }
+
+    #[test]
+    fn attributes() {
+        // attributes are resolved and carried over to the resolved types
+        use ast::*;
+        let test: AST = vec![
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "hp",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![Attribute("rename", vec!["health"]), Attribute("since", vec!["2"])],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![Attribute("deprecated", vec![])],
+                Span::default(),
+            ),
+            Node::Export("Test", None, Span::default()),
+        ];
+        let checked = type_check(test).unwrap();
+        let test = checked.exports[0].r#struct.clone();
+        assert_eq!(test.attrs, vec![check::Attribute::Deprecated(None)]);
+        assert_eq!(
+            test.fields[0].attrs,
+            vec![check::Attribute::Rename("health"), check::Attribute::Since("2")]
+        );
+        assert_eq!(test.fields[0].ident(), "health");
+    }
+
+    #[test]
+    fn invalid_attributes() {
+        use ast::*;
+        let with_attrs = |decl: Vec<ast::Attribute<'static>>, field: Vec<ast::Attribute<'static>>| -> AST<'static> {
+            vec![
+                Node::Decl(
+                    "Test",
+                    Type::Struct(Struct(
+                        vec![
+                            (
+                                "a",
+                                Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                                vec![],
+                                field,
+                                Span::default(),
+                            ),
+                            (
+                                "b",
+                                Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                                vec![],
+                                vec![],
+                                Span::default(),
+                            ),
+                        ],
+                        None,
+                    )),
+                    vec![],
+                    decl,
+                    Span::default(),
+                ),
+                Node::Export("Test", None, Span::default()),
+            ]
+        };
+        assert_eq!(
+            type_check(with_attrs(vec![Attribute("foo", vec![])], vec![])).unwrap_err()[0].message,
+            "Unknown attribute '@foo' on declaration 'Test'"
+        );
+        assert_eq!(
+            type_check(with_attrs(vec![], vec![Attribute("since", vec![])])).unwrap_err()[0].message,
+            "Attribute '@since' on field 'a' in struct 'Test' expects exactly one argument"
+        );
+        assert_eq!(
+            type_check(with_attrs(vec![Attribute("deprecated", vec!["a", "b"])], vec![])).unwrap_err()[0].message,
+            "Attribute '@deprecated' on declaration 'Test' expects at most one argument"
+        );
+        assert_eq!(
+            type_check(with_attrs(vec![Attribute("rename", vec!["Other"])], vec![])).unwrap_err()[0].message,
+            "Attribute '@rename' on declaration 'Test' can only be used on fields"
+        );
+        assert_eq!(
+            type_check(with_attrs(vec![], vec![Attribute("rename", vec!["1a"])])).unwrap_err()[0].message,
+            "Attribute '@rename' on field 'a' in struct 'Test' expects an identifier, got '1a'"
+        );
+        assert_eq!(
+            type_check(with_attrs(
+                vec![],
+                vec![Attribute("since", vec!["1"]), Attribute("since", vec!["2"])]
+            ))
+            .unwrap_err()[0]
+                .message,
+            "Duplicate attribute '@since' on field 'a' in struct 'Test'"
+        );
+        assert_eq!(
+            type_check(with_attrs(vec![], vec![Attribute("rename", vec!["b"])])).unwrap_err()[0].message,
+            "Fields 'a' and 'b' in struct 'Test' both use the identifier 'b'"
+        );
+        assert_eq!(
+            type_check(with_attrs(vec![], vec![Attribute("bitmask", vec![])])).unwrap_err()[0].message,
+            "Attribute '@bitmask' on field 'a' in struct 'Test' can only be used on structs"
+        );
+        assert_eq!(
+            type_check(with_attrs(vec![Attribute("bitmask", vec!["8"])], vec![])).unwrap_err()[0].message,
+            "Attribute '@bitmask' on declaration 'Test' expects no arguments"
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        use ast::*;
+        let with_range = |ty: &'static str, range: ast::Range<'static>| -> AST<'static> {
+            vec![
+                Node::Decl(
+                    "Flag",
+                    Type::Enum(Enum(vec![("A", vec![], Span::default())])),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Test",
+                    Type::Struct(Struct(
+                        vec![(
+                            "a",
+                            Unresolved(ty, false, false, Some(range), None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        )],
+                        None,
+                    )),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Export("Test", None, Span::default()),
+            ]
+        };
+        let checked = type_check(with_range("int8", ast::Range(Some("-128"), Some("127"), true))).unwrap();
+        assert_eq!(
+            checked.exports[0].r#struct.fields[0].range,
+            Some(check::Range {
+                min: Some("-128"),
+                max: Some("127"),
+                inclusive: true
+            })
+        );
+        assert!(type_check(with_range("float", ast::Range(Some("-0.5"), Some("0.5"), false))).is_ok());
+        assert!(type_check(with_range("string", ast::Range(None, Some("16"), true))).is_ok());
+
+        assert_eq!(
+            type_check(with_range("Flag", ast::Range(Some("0"), None, false))).unwrap_err()[0].message,
+            "Range on field 'a' in struct 'Test' is only supported on numbers and strings"
+        );
+        assert_eq!(
+            type_check(with_range("uint8", ast::Range(None, None, false))).unwrap_err()[0].message,
+            "Range on field 'a' in struct 'Test' must have at least one bound"
+        );
+        assert_eq!(
+            type_check(with_range("uint8", ast::Range(Some("0"), None, true))).unwrap_err()[0].message,
+            "Inclusive range on field 'a' in struct 'Test' must have an upper bound"
+        );
+        assert_eq!(
+            type_check(with_range("uint8", ast::Range(Some("0"), Some("256"), true))).unwrap_err()[0].message,
+            "Range bound '256' on field 'a' in struct 'Test' does not fit in type 'uint8'"
+        );
+        assert_eq!(
+            type_check(with_range("uint8", ast::Range(Some("0"), Some("257"), false))).unwrap_err()[0].message,
+            "Range bound '257' on field 'a' in struct 'Test' does not fit in type 'uint8'"
+        );
+        // the exclusive end of a type's whole range
+        let checked = type_check(with_range("uint8", ast::Range(Some("0"), Some("256"), false))).unwrap();
+        assert_eq!(
+            checked.exports[0].r#struct.fields[0].range,
+            Some(check::Range {
+                min: Some("0"),
+                max: Some("255"),
+                inclusive: true
+            })
+        );
+        let checked = type_check(with_range("uint32", ast::Range(None, Some("4294967296"), false))).unwrap();
+        assert_eq!(
+            checked.exports[0].r#struct.fields[0].range,
+            Some(check::Range {
+                min: None,
+                max: Some("4294967295"),
+                inclusive: true
+            })
+        );
+        assert_eq!(
+            type_check(with_range("uint16", ast::Range(Some("0.5"), None, false))).unwrap_err()[0].message,
+            "Range bound '0.5' on field 'a' in struct 'Test' does not fit in type 'uint16'"
+        );
+        assert_eq!(
+            type_check(with_range("string", ast::Range(Some("-1"), None, false))).unwrap_err()[0].message,
+            "Range bound '-1' on field 'a' in struct 'Test' does not fit in type 'uint32'"
+        );
+        assert_eq!(
+            type_check(with_range("int32", ast::Range(Some("5"), Some("5"), false))).unwrap_err()[0].message,
+            "Range on field 'a' in struct 'Test' is empty"
+        );
+    }
+
+    #[test]
+    fn length_fields() {
+        use ast::*;
+        let with_fields = |fields: Vec<(&'static str, Unresolved<'static>)>| -> AST<'static> {
+            vec![
+                Node::Decl(
+                    "Inner",
+                    Type::Struct(Struct(
+                        vec![(
+                            "v",
+                            Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        )],
+                        None,
+                    )),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Test",
+                    Type::Struct(Struct(
+                        fields
+                            .into_iter()
+                            .map(|(name, ty)| (name, ty, vec![], vec![], Span::default()))
+                            .collect(),
+                        None,
+                    )),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Export("Test", None, Span::default()),
+            ]
+        };
+        // fields keep their order even when their types are resolved later
+        let checked = type_check(with_fields(vec![
+            (
+                "items",
+                Unresolved("Inner", true, false, None, Some("count"), None, None, Span::default()),
+            ),
+            (
+                "count",
+                Unresolved("uint16", false, false, None, None, None, None, Span::default()),
+            ),
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            checked[0].message,
+            "Length of field 'items' in struct 'Test' refers to field 'count', which is declared after it"
+        );
+        let checked = type_check(with_fields(vec![
+            (
+                "count",
+                Unresolved("uint16", false, false, None, None, None, None, Span::default()),
+            ),
+            (
+                "items",
+                Unresolved("Inner", true, false, None, Some("count"), None, None, Span::default()),
+            ),
+            (
+                "data",
+                Unresolved("uint8", true, false, None, Some("count"), None, None, Span::default()),
+            ),
+        ]))
+        .unwrap();
+        let fields = &checked.exports[0].r#struct.fields;
+        assert_eq!(
+            fields.iter().map(|f| (f.name, f.length)).collect::<Vec<_>>(),
+            vec![("count", None), ("items", Some("count")), ("data", Some("count"))]
+        );
+
+        assert_eq!(
+            type_check(with_fields(vec![(
+                "data",
+                Unresolved("uint8", true, false, None, Some("count"), None, None, Span::default())
+            )]))
+            .unwrap_err()[0]
+                .message,
+            "Length of field 'data' in struct 'Test' refers to unknown field 'count'"
+        );
+        for count in [
+            Unresolved("int8", false, false, None, None, None, None, Span::default()),
+            Unresolved("uint8", true, false, None, None, None, None, Span::default()),
+            Unresolved("uint8", false, true, None, None, None, None, Span::default()),
+            Unresolved("Inner", false, false, None, None, None, None, Span::default()),
+        ]
+        .iter()
+        .cloned()
+        {
+            assert_eq!(
+                type_check(with_fields(vec![
+                    ("count", count),
+                    (
+                        "data",
+                        Unresolved("uint8", true, false, None, Some("count"), None, None, Span::default())
+                    ),
+                ]))
+                .unwrap_err()[0]
+                    .message,
+                "Length of field 'data' in struct 'Test' refers to field 'count', which is not an unsigned integer"
+            );
+        }
+    }
+
+    #[test]
+    fn conditions() {
+        use ast::*;
+        let with_fields = |fields: Vec<(&'static str, Unresolved<'static>)>| -> AST<'static> {
+            vec![
+                Node::Decl(
+                    "Kind",
+                    Type::Enum(Enum(vec![("A", vec![], Span::default())])),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Other",
+                    Type::Enum(Enum(vec![("A", vec![], Span::default())])),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Test",
+                    Type::Struct(Struct(
+                        fields
+                            .into_iter()
+                            .map(|(name, ty)| (name, ty, vec![], vec![], Span::default()))
+                            .collect(),
+                        None,
+                    )),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Export("Test", None, Span::default()),
+            ]
+        };
+        let kind = || {
+            (
+                "kind",
+                Unresolved("Kind", false, false, None, None, None, None, Span::default()),
+            )
+        };
+        let cond = |e, v| Some(Condition("kind", e, v, Span::default()));
+        let checked = type_check(with_fields(vec![
+            kind(),
+            (
+                "a",
+                Unresolved(
+                    "uint8",
+                    false,
+                    false,
+                    None,
+                    None,
+                    cond("Kind", "A"),
+                    None,
+                    Span::default(),
+                ),
+            ),
+        ]))
+        .unwrap();
+        let field = &checked.exports[0].r#struct.fields[1];
+        assert_eq!(
+            field.condition,
+            Some(check::Condition {
+                field: "kind",
+                r#enum: "Kind",
+                variant: "A"
+            })
+        );
+        assert!(field.is_optional());
+
+        assert_eq!(
+            type_check(with_fields(vec![
+                (
+                    "a",
+                    Unresolved(
+                        "uint8",
+                        false,
+                        false,
+                        None,
+                        None,
+                        cond("Kind", "A"),
+                        None,
+                        Span::default()
+                    )
+                ),
+                kind(),
+            ]))
+            .unwrap_err()[0]
+                .message,
+            "Condition of field 'a' in struct 'Test' refers to field 'kind', which is declared after it"
+        );
+        assert_eq!(
+            type_check(with_fields(vec![(
+                "a",
+                Unresolved(
+                    "uint8",
+                    false,
+                    false,
+                    None,
+                    None,
+                    cond("Kind", "A"),
+                    None,
+                    Span::default()
+                )
+            )]))
+            .unwrap_err()[0]
+                .message,
+            "Condition of field 'a' in struct 'Test' refers to unknown field 'kind'"
+        );
+        assert_eq!(
+            type_check(with_fields(vec![
+                (
+                    "kind",
+                    Unresolved("uint8", false, false, None, None, None, None, Span::default())
+                ),
+                (
+                    "a",
+                    Unresolved(
+                        "uint8",
+                        false,
+                        false,
+                        None,
+                        None,
+                        cond("Kind", "A"),
+                        None,
+                        Span::default()
+                    )
+                ),
+            ]))
+            .unwrap_err()[0]
+                .message,
+            "Condition of field 'a' in struct 'Test' refers to field 'kind', which is not an enum"
+        );
+        assert_eq!(
+            type_check(with_fields(vec![
+                kind(),
+                (
+                    "a",
+                    Unresolved(
+                        "uint8",
+                        false,
+                        false,
+                        None,
+                        None,
+                        cond("Other", "A"),
+                        None,
+                        Span::default()
+                    )
+                ),
+            ]))
+            .unwrap_err()[0]
+                .message,
+            "Condition of field 'a' in struct 'Test' compares field 'kind' of type 'Kind' with 'Other.A'"
+        );
+        assert_eq!(
+            type_check(with_fields(vec![
+                kind(),
+                (
+                    "a",
+                    Unresolved(
+                        "uint8",
+                        false,
+                        false,
+                        None,
+                        None,
+                        cond("Kind", "B"),
+                        None,
+                        Span::default()
+                    )
+                ),
+            ]))
+            .unwrap_err()[0]
+                .message,
+            "Condition of field 'a' in struct 'Test' refers to unknown variant 'Kind.B'"
+        );
+        assert_eq!(
+            type_check(with_fields(vec![
+                kind(),
+                (
+                    "a",
+                    Unresolved(
+                        "uint8",
+                        false,
+                        true,
+                        None,
+                        None,
+                        cond("Kind", "A"),
+                        None,
+                        Span::default()
+                    )
+                ),
+            ]))
+            .unwrap_err()[0]
+                .message,
+            "Field 'a' in struct 'Test' cannot be conditional and optional at once"
+        );
+        assert_eq!(
+            type_check(with_fields(vec![
+                kind(),
+                (
+                    "a",
+                    Unresolved(
+                        "uint8",
+                        true,
+                        false,
+                        None,
+                        None,
+                        cond("Kind", "A"),
+                        None,
+                        Span::default()
+                    )
+                ),
+            ]))
+            .unwrap_err()[0]
+                .message,
+            "Field 'a' in struct 'Test' cannot be conditional and array at once"
+        );
+    }
+
+    #[test]
+    fn extends() {
+        use ast::*;
+        let field = |name| {
+            (
+                name,
+                Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                vec![],
+                vec![],
+                Span::default(),
+            )
+        };
+        let with_player = |base: &'static str, fields| -> AST<'static> {
+            vec![
+                Node::Decl(
+                    "Entity",
+                    Type::Struct(Struct(vec![field("id")], None)),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Living",
+                    Type::Struct(Struct(vec![field("hp")], Some(("Entity", Span::default())))),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Kind",
+                    Type::Enum(Enum(vec![("A", vec![], Span::default())])),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Player",
+                    Type::Struct(Struct(fields, Some((base, Span::default())))),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Export("Player", None, Span::default()),
+            ]
+        };
+        let checked = type_check(with_player("Living", vec![field("level")])).unwrap();
+        let player = &checked.exports[0].r#struct;
+        assert_eq!(
+            player.fields.iter().map(|f| f.name).collect::<Vec<_>>(),
+            vec!["id", "hp", "level"]
         );
-    }
-    Ok(())
-}
+        assert_eq!(
+            player.own_fields().iter().map(|f| f.name).collect::<Vec<_>>(),
+            vec!["level"]
+        );
+        assert_eq!(player.base.as_ref().unwrap().borrow().0, "Living");
+        // the bases are kept, as they're referred to by the generated code
+        assert!(checked.types.contains_key("Living"));
+        assert!(checked.types.contains_key("Entity"));
 
-fn resolve_second_pass<'a>(
-    cache: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
-    mut unresolved: HashMap<&'a str, ast::Type<'a>>,
-) -> Result<(), String> {
-    let mut visited = HashSet::new();
-    for (name, ty) in unresolved.clone() {
-        if let Err(err) = resolve_one_second_pass(name, ty, cache, &mut visited, &mut unresolved) {
-            return Err(err);
-        }
+        assert_eq!(
+            type_check(with_player("Living", vec![field("level"), field("id")])).unwrap_err()[0].message,
+            "Field 'id' on struct 'Player' is already declared by its base 'Living'"
+        );
+        assert_eq!(
+            type_check(with_player("Living", vec![field("level"), field("level")])).unwrap_err()[0].message,
+            "Duplicate field 'level' on struct 'Player'"
+        );
+        assert_eq!(
+            type_check(with_player("Kind", vec![])).unwrap_err()[0].message,
+            "Struct 'Player' cannot extend 'Kind', which is not a struct"
+        );
+        assert_eq!(
+            type_check(with_player("Unknown", vec![])).unwrap_err()[0].message,
+            "Declaration for type 'Unknown' does not exist"
+        );
+        assert_eq!(
+            type_check(with_player("Player", vec![])).unwrap_err()[0].message,
+            "Found a cycle between two or more top level definitions in type 'Player'"
+        );
     }
-    Ok(())
-}
 
-fn collect_used_types<'a>(visited: &mut HashSet<&'a str>, ty: &(&'a str, ResolvedType<'a>)) {
-    visited.insert(ty.0);
-    if let Some(ty) = ty.1.get_struct_variant() {
-        for field in ty.fields.iter() {
-            let field = &*field.r#type.borrow();
-            if visited.contains(&field.0) {
-                continue;
+    #[test]
+    fn fingerprint() {
+        let resolve = |schema| type_check(parser::pkt::schema(schema).unwrap()).unwrap();
+        let schema = resolve(
+            "Kind: enum { A, B }
+            Stats: struct { hp: uint8 in ..=100, kind: Kind }
+            ## A test
+            @extensible
+            Test: struct { stats @1: Stats, names @2: string[], nick? @3: string }
+            export Test = 1",
+        );
+        assert_eq!(
+            schema.canonical(),
+            "Kind: enum u8 { A = 0, B = 1 }
+Stats: struct { hp: uint8 in ..=100, kind: Kind }
+@extensible Test: struct { stats @1: Stats, names @2: string[], nick? @3: string }
+export Test = 1"
+        );
+        // comments, formatting and declaration order don't matter
+        let same = resolve(
+            "# the test
+            @extensible Test: struct {
+                stats @1: Stats
+                @deprecated names @2: string[], nick? @3: string
             }
-            collect_used_types(visited, field);
-        }
+            Stats: struct { hp: uint8 in ..=100, kind: Kind }
+            Kind: enum { A, B }
+            export Test = 1",
+        );
+        assert_eq!(schema.fingerprint(), same.fingerprint());
+        let changed = resolve(
+            "Kind: enum { A, B }
+            Stats: struct { hp: uint16 in ..=100, kind: Kind }
+            @extensible
+            Test: struct { stats @1: Stats, names @2: string[], nick? @3: string }
+            export Test = 1",
+        );
+        assert_ne!(schema.fingerprint(), changed.fingerprint());
     }
-}
-
-fn remove_unused<'a>(visited: HashSet<&'a str>, resolved: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType)>>) {
-    // TODO: print a warning (if configured) for each unused type
-    resolved.retain(|name, _| visited.contains(name));
-}
 
-fn resolve_export<'a>(
-    name: &'a str,
-    resolved: &mut HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>,
-) -> Result<Export<'a>, String> {
-    if let Some(export) = resolved.get(&name).cloned() {
-        if let Some(ty) = export.borrow().1.get_struct_variant() {
-            // Use this opportunity to discard unused types.
-            let mut visited = [name].iter().copied().collect();
-            for field in ty.fields.iter() {
-                collect_used_types(&mut visited, &*field.r#type.borrow());
-            }
-            remove_unused(visited, resolved);
+    #[test]
+    fn tags() {
+        use ast::*;
+        let field = |name, tag| {
+            (
+                name,
+                Unresolved("uint8", false, false, None, None, None, tag, Span::default()),
+                vec![],
+                vec![],
+                Span::default(),
+            )
+        };
+        let with_fields = |attrs: Vec<&'static str>, fields| -> AST<'static> {
+            let attrs = attrs.into_iter().map(|a| Attribute(a, vec![])).collect();
+            vec![
+                Node::Decl(
+                    "Kind",
+                    Type::Enum(Enum(vec![("A", vec![], Span::default())])),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Test",
+                    Type::Struct(Struct(fields, None)),
+                    vec![],
+                    attrs,
+                    Span::default(),
+                ),
+                Node::Export("Test", None, Span::default()),
+            ]
+        };
+        let checked = type_check(with_fields(
+            vec!["extensible"],
+            vec![field("a", Some(2)), field("b", Some(1))],
+        ))
+        .unwrap();
+        let test = &checked.exports[0].r#struct;
+        assert!(test.is_extensible());
+        assert_eq!(
+            test.fields.iter().map(|f| f.tag).collect::<Vec<_>>(),
+            vec![Some(2), Some(1)]
+        );
 
-            Ok(Export { name, r#struct: ty })
-        } else {
-            Err(format!("Attempted to export '{}', which is not a struct", name))
-        }
-    } else {
-        Err(format!("Export '{}' could not be resolved", name))
+        assert_eq!(
+            type_check(with_fields(vec![], vec![field("a", Some(1))])).unwrap_err()[0].message,
+            "Field 'a' in struct 'Test' has a tag, but the struct is not @extensible"
+        );
+        assert_eq!(
+            type_check(with_fields(
+                vec!["extensible"],
+                vec![field("a", Some(1)), field("b", None)]
+            ))
+            .unwrap_err()[0]
+                .message,
+            "Missing tag on field 'b' in extensible struct 'Test'"
+        );
+        assert_eq!(
+            type_check(with_fields(vec!["extensible"], vec![field("a", Some(0))])).unwrap_err()[0].message,
+            "Tag @0 on field 'a' in extensible struct 'Test' is reserved"
+        );
+        assert_eq!(
+            type_check(with_fields(
+                vec!["extensible"],
+                vec![field("a", Some(3)), field("b", Some(3))]
+            ))
+            .unwrap_err()[0]
+                .message,
+            "Fields 'a' and 'b' in struct 'Test' both use the tag @3"
+        );
+        assert_eq!(
+            type_check(with_fields(vec!["extensible", "bitmask"], vec![field("a", Some(1))])).unwrap_err()[0].message,
+            "Struct 'Test' cannot be both @extensible and @bitmask"
+        );
+        assert_eq!(
+            type_check(with_fields(
+                vec!["extensible"],
+                vec![
+                    field("a", Some(1)),
+                    (
+                        "b",
+                        Unresolved("uint8", true, false, None, Some("a"), None, Some(2), Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default()
+                    )
+                ]
+            ))
+            .unwrap_err()[0]
+                .message,
+            "Array length of field 'b' in extensible struct 'Test' can't refer to another field"
+        );
+        assert_eq!(
+            type_check(with_fields(
+                vec!["extensible"],
+                vec![
+                    (
+                        "k",
+                        Unresolved("Kind", false, false, None, None, None, Some(1), Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default()
+                    ),
+                    (
+                        "b",
+                        Unresolved(
+                            "uint8",
+                            false,
+                            false,
+                            None,
+                            None,
+                            Some(Condition("k", "Kind", "A", Span::default())),
+                            Some(2),
+                            Span::default()
+                        ),
+                        vec![],
+                        vec![],
+                        Span::default()
+                    )
+                ]
+            ))
+            .unwrap_err()[0]
+                .message,
+            "Condition on field 'b' in extensible struct 'Test' isn't supported"
+        );
     }
-}
-
-pub type TypeMap<'a> = HashMap<&'a str, Ptr<(&'a str, ResolvedType<'a>)>>;
-
-#[derive(Clone, PartialEq, Debug)]
-pub struct Resolved<'a> {
-    pub export: Export<'a>,
-    pub types: TypeMap<'a>,
-}
-
-pub fn type_check(ast: ast::AST<'_>) -> Result<Resolved<'_>, String> {
-    let export = match get_export(&ast) {
-        Ok(e) => e,
-        Err(e) => return Err(e),
-    };
-    let mut unresolved = match collect_types(&ast) {
-        Ok(t) => t,
-        Err(e) => return Err(e),
-    };
-
-    // pre-pass: collect builtins
-    let cache = get_builtins();
-    // first pass: collect enums + structs with only builtins as field types
-    let mut first_pass = HashMap::new();
-    if let Err(err) = resolve_first_pass(ast, &cache, &mut first_pass, &mut unresolved) {
-        return Err(err);
-    };
-    // second pass: collect structs with other structs (made up of builtins) as
-    // field types
-    let mut cache = cache.into_iter().chain(first_pass).collect::<HashMap<_, _>>();
-    if let Err(err) = resolve_second_pass(&mut cache, unresolved) {
-        return Err(err);
-    };
-    // export pass: collect the resolved type we're exporting
-    let export = match resolve_export(export, &mut cache) {
-        Ok(e) => e,
-        Err(err) => return Err(err),
-    };
-    Ok(Resolved { export, types: cache })
-}
-
-#[cfg(test)]
-mod tests {
-    use std::vec;
-
-    use pretty_assertions::assert_eq;
-
-    use super::*;
 
     #[test]
-    fn type_check_passes() {
-        // check if a valid AST containing all language features passes the type check
+    fn delta() {
         use ast::*;
-        let test: AST = vec![
-            Node::Decl("Flag", Type::Enum(Enum(vec!["A", "B"]))),
-            Node::Decl(
-                "Position",
-                Type::Struct(Struct(vec![
-                    ("x", Unresolved("float", false, false)),
-                    ("y", Unresolved("float", false, false)),
-                ])),
-            ),
-            Node::Decl(
-                "Value",
-                Type::Struct(Struct(vec![
-                    ("a", Unresolved("uint32", false, false)),
-                    ("b", Unresolved("int32", false, false)),
-                    ("c", Unresolved("uint8", false, false)),
-                    ("d", Unresolved("uint8", false, false)),
-                ])),
-            ),
-            Node::Decl(
-                "ComplexType",
-                Type::Struct(Struct(vec![
-                    ("flag", Unresolved("Flag", false, false)),
-                    ("pos", Unresolved("Position", false, false)),
-                    ("names", Unresolved("string", true, false)),
-                    ("values", Unresolved("Value", true, false)),
-                ])),
-            ),
-            Node::Export("ComplexType"),
-        ];
-        // TODO: check equality of Resolved AST instead of checking if this is an error
-        // Rc<T> == Rc<T> if T == T, according to https://doc.rust-lang.org/src/alloc/rc.rs.html#1325
-        type_check(test).unwrap();
+        let with_attrs = |attrs: Vec<&'static str>, export| -> AST<'static> {
+            let attrs = attrs.into_iter().map(|a| Attribute(a, vec![])).collect();
+            let field = (
+                "a",
+                Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                vec![],
+                vec![],
+                Span::default(),
+            );
+            let mut ast = vec![Node::Decl(
+                "Test",
+                Type::Struct(Struct(vec![field], None)),
+                vec![],
+                attrs,
+                Span::default(),
+            )];
+            if export {
+                ast.push(Node::Export("Test", None, Span::default()));
+            }
+            ast
+        };
+        let checked = type_check(with_attrs(vec!["delta"], true)).unwrap();
+        assert!(checked.exports[0].r#struct.is_delta());
+
+        assert_eq!(
+            type_check(with_attrs(vec!["delta"], false)).unwrap_err()[0].message,
+            "Attribute '@delta' on declaration 'Test' can only be used on exported structs"
+        );
+        assert_eq!(
+            type_check(with_attrs(vec!["extensible", "delta"], true)).unwrap_err()[0].message,
+            "Struct 'Test' cannot be both @extensible and @delta"
+        );
     }
 
     #[test]
-    fn empty_enum() {
-        // an enum must have at least one variant
+    fn packed() {
         use ast::*;
-        let test: AST = vec![
-            Node::Decl("Flag", Type::Enum(Enum(vec![]))),
-            Node::Decl(
-                "Test",
-                Type::Struct(Struct(vec![("flag", Unresolved("Flag", false, false))])),
-            ),
-            Node::Export("Test"),
-        ];
+        let field = |name, ty| {
+            (
+                name,
+                Unresolved(ty, false, false, None, None, None, None, Span::default()),
+                vec![],
+                vec![],
+                Span::default(),
+            )
+        };
+        let with_attrs = |attrs: Vec<&'static str>, inner_attrs: Vec<&'static str>, export| -> AST<'static> {
+            let attrs = attrs.into_iter().map(|a| Attribute(a, vec![])).collect();
+            let inner_attrs = inner_attrs.into_iter().map(|a| Attribute(a, vec![])).collect();
+            let inner = (
+                "a",
+                Unresolved("uint8", false, false, None, None, None, Some(1), Span::default()),
+                vec![],
+                vec![],
+                Span::default(),
+            );
+            let mut ast = vec![
+                Node::Decl(
+                    "Inner",
+                    Type::Struct(Struct(vec![inner], None)),
+                    vec![],
+                    inner_attrs,
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Test",
+                    Type::Struct(Struct(vec![field("a", "uint8"), field("b", "Inner")], None)),
+                    vec![],
+                    attrs,
+                    Span::default(),
+                ),
+            ];
+            if export {
+                ast.push(Node::Export("Test", None, Span::default()));
+            }
+            ast
+        };
         assert_eq!(
-            type_check(test).unwrap_err(),
-            "Enum 'Flag' must have at least one variant"
+            type_check(with_attrs(vec!["packed"], vec!["extensible"], true)).unwrap_err()[0].message,
+            "Struct 'Inner' is @extensible, so it can't be used in @packed export 'Test'"
+        );
+        assert!(type_check(with_attrs(vec![], vec!["extensible"], true)).is_ok());
+        assert_eq!(
+            type_check(with_attrs(vec!["packed"], vec!["extensible"], false)).unwrap_err()[0].message,
+            "Attribute '@packed' on declaration 'Test' can only be used on exported structs"
         );
+        assert_eq!(
+            type_check(with_attrs(vec!["packed", "delta"], vec!["extensible"], true)).unwrap_err()[0].message,
+            "Struct 'Test' cannot be both @packed and @delta"
+        );
+
+        let range = |min, max, inclusive| super::Range { min, max, inclusive };
+        assert_eq!(range(Some("0"), Some("100"), true).packed_bits(), Some((0, 7)));
+        assert_eq!(range(Some("-10"), Some("10"), false).packed_bits(), Some((-10, 5)));
+        assert_eq!(range(Some("3"), Some("4"), false).packed_bits(), Some((3, 0)));
+        assert_eq!(range(Some("0.5"), Some("1"), false).packed_bits(), None);
+        assert_eq!(range(None, Some("100"), true).packed_bits(), None);
     }
 
     #[test]
-    fn optional_and_array() {
-        // field cannot be optional and array at once
+    fn package() {
         use ast::*;
         let test: AST = vec![
+            Node::Package("game.combat", Span::default()),
             Node::Decl(
                 "Test",
-                Type::Struct(Struct(vec![("a", Unresolved("uint8", true, true))])),
+                Type::Struct(Struct(
+                    vec![(
+                        "hp",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("Test"),
+            Node::Export("Test", None, Span::default()),
         ];
-        assert_eq!(
-            type_check(test).unwrap_err(),
-            "Field 'a' in struct 'Test' cannot be optional and array at once"
-        );
+        assert_eq!(type_check(test).unwrap().package, vec!["game", "combat"]);
     }
 
     #[test]
-    fn too_many_exports() {
-        // a schema file may have only one export
+    fn duplicate_package() {
         use ast::*;
         let test: AST = vec![
+            Node::Package("game.combat", Span::default()),
+            Node::Package("game", Span::default()),
             Node::Decl(
-                "Position",
-                Type::Struct(Struct(vec![
-                    ("x", Unresolved("float", false, false)),
-                    ("y", Unresolved("float", false, false)),
-                ])),
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "hp",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("Position"),
-            Node::Export("Position"),
+            Node::Export("Test", None, Span::default()),
         ];
-        assert_eq!(type_check(test).unwrap_err(), "Schema has more than one export");
+        assert_eq!(
+            type_check(test).unwrap_err()[0].message,
+            "Schema has more than one package declaration: 'game.combat' and 'game'"
+        );
     }
 
     #[test]
-    fn no_export() {
-        // a schema file must export something
-        use ast::*;
-        let test: AST = vec![Node::Decl(
-            "Position",
-            Type::Struct(Struct(vec![
-                ("x", Unresolved("float", false, false)),
-                ("y", Unresolved("float", false, false)),
-            ])),
-        )];
-        assert_eq!(type_check(test).unwrap_err(), "Schema has no export");
+    fn import_cycle() {
+        let names = vec!["a.pkt".to_string(), "b.pkt".to_string(), "c.pkt".to_string()];
+        let span = Span::new(7, 14);
+        assert_eq!(
+            import_order(&names, &[vec![(1, span)], vec![(2, span)], vec![]]).unwrap(),
+            vec![2, 1, 0]
+        );
+        let cycle = import_order(
+            &names,
+            &[vec![(1, span)], vec![(2, span)], vec![(0, Span::new(20, 27))]],
+        )
+        .unwrap_err();
+        assert_eq!(cycle.message, "Found an import cycle: a.pkt -> b.pkt -> c.pkt -> a.pkt");
+        // reported at the import in c.pkt which leads back to a.pkt
+        assert_eq!(cycle.primary_span, Some(Span::new(20, 27)));
+        assert_eq!(cycle.path, Some(std::path::PathBuf::from("c.pkt")));
     }
 
     #[test]
     fn duplicate_enum_variants() {
         use ast::*;
         let test: AST = vec![
-            Node::Decl("Flag", Type::Enum(Enum(vec!["A", "A"]))),
+            Node::Decl(
+                "Flag",
+                Type::Enum(Enum(vec![
+                    ("A", vec![], Span::default()),
+                    ("A", vec![], Span::default()),
+                ])),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
             Node::Decl(
                 "Test",
-                Type::Struct(Struct(vec![("flag", Unresolved("Flag", false, false))])),
+                Type::Struct(Struct(
+                    vec![(
+                        "flag",
+                        Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("Test"),
+            Node::Export("Test", None, Span::default()),
         ];
-        assert_eq!(type_check(test).unwrap_err(), "Duplicate variant 'A' on enum 'Flag'");
+        assert_eq!(
+            type_check(test).unwrap_err()[0].message,
+            "Duplicate variant 'A' on enum 'Flag'"
+        );
     }
 
     #[test]
@@ -562,15 +3653,33 @@ mod tests {
         let test: AST = vec![
             Node::Decl(
                 "Position",
-                Type::Struct(Struct(vec![
-                    ("x", Unresolved("float", false, false)),
-                    ("x", Unresolved("float", false, false)),
-                ])),
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "x",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "x",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("Position"),
+            Node::Export("Position", None, Span::default()),
         ];
         assert_eq!(
-            type_check(test).unwrap_err(),
+            type_check(test).unwrap_err()[0].message,
             "Duplicate field 'x' on struct 'Position'"
         );
     }
@@ -582,22 +3691,58 @@ mod tests {
         let test: AST = vec![
             Node::Decl(
                 "Position",
-                Type::Struct(Struct(vec![
-                    ("x", Unresolved("float", false, false)),
-                    ("y", Unresolved("float", false, false)),
-                ])),
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "x",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "y",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
             Node::Decl(
                 "Position",
-                Type::Struct(Struct(vec![
-                    ("x", Unresolved("float", false, false)),
-                    ("y", Unresolved("float", false, false)),
-                ])),
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "x",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "y",
+                            Unresolved("float", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("Position"),
+            Node::Export("Position", None, Span::default()),
         ];
         assert_eq!(
-            type_check(test).unwrap_err(),
+            type_check(test).unwrap_err()[0].message,
             "Schema has duplicate declaration: Position"
         );
     }
@@ -612,20 +3757,64 @@ mod tests {
             Node::Decl(
                 "Flag",
                 Type::Enum(Enum(vec![
-                    "A0", "A1", "A2", "A3", "A4", "A5", "A6", "A7", "A8", "A9", "A10", "A11", "A12", "A13", "A14",
-                    "A15", "A16", "A17", "A18", "A19", "A20", "A21", "A22", "A23", "A24", "A25", "A26", "A27", "A28",
-                    "A29", "A30", "A31", // one too many
-                    "A32",
+                    ("A0", vec![], Span::default()),
+                    ("A1", vec![], Span::default()),
+                    ("A2", vec![], Span::default()),
+                    ("A3", vec![], Span::default()),
+                    ("A4", vec![], Span::default()),
+                    ("A5", vec![], Span::default()),
+                    ("A6", vec![], Span::default()),
+                    ("A7", vec![], Span::default()),
+                    ("A8", vec![], Span::default()),
+                    ("A9", vec![], Span::default()),
+                    ("A10", vec![], Span::default()),
+                    ("A11", vec![], Span::default()),
+                    ("A12", vec![], Span::default()),
+                    ("A13", vec![], Span::default()),
+                    ("A14", vec![], Span::default()),
+                    ("A15", vec![], Span::default()),
+                    ("A16", vec![], Span::default()),
+                    ("A17", vec![], Span::default()),
+                    ("A18", vec![], Span::default()),
+                    ("A19", vec![], Span::default()),
+                    ("A20", vec![], Span::default()),
+                    ("A21", vec![], Span::default()),
+                    ("A22", vec![], Span::default()),
+                    ("A23", vec![], Span::default()),
+                    ("A24", vec![], Span::default()),
+                    ("A25", vec![], Span::default()),
+                    ("A26", vec![], Span::default()),
+                    ("A27", vec![], Span::default()),
+                    ("A28", vec![], Span::default()),
+                    ("A29", vec![], Span::default()),
+                    ("A30", vec![], Span::default()),
+                    ("A31", vec![], Span::default()), // one too many
+                    ("A32", vec![], Span::default()),
                 ])),
+                vec![],
+                vec![],
+                Span::default(),
             ),
             Node::Decl(
                 "Test",
-                Type::Struct(Struct(vec![("flag", Unresolved("Flag", false, false))])),
+                Type::Struct(Struct(
+                    vec![(
+                        "flag",
+                        Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("Test"),
+            Node::Export("Test", None, Span::default()),
         ];
         assert_eq!(
-            type_check(test).unwrap_err(),
+            type_check(test).unwrap_err()[0].message,
             "Enum 'Flag' has too many variants (33/32)"
         );
     }
@@ -638,12 +3827,24 @@ mod tests {
         let test: AST = vec![
             Node::Decl(
                 "Test",
-                Type::Struct(Struct(vec![("flag", Unresolved("Flag", false, false))])),
+                Type::Struct(Struct(
+                    vec![(
+                        "flag",
+                        Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("Test"),
+            Node::Export("Test", None, Span::default()),
         ];
         assert_eq!(
-            type_check(test).unwrap_err(),
+            type_check(test).unwrap_err()[0].message,
             "Declaration for type 'Flag' does not exist"
         );
     }
@@ -654,17 +3855,62 @@ mod tests {
         let test = vec![
             Node::Decl(
                 "A",
-                Type::Struct(Struct(vec![("b", Unresolved("int32", false, false))])),
+                Type::Struct(Struct(
+                    vec![(
+                        "b",
+                        Unresolved("int32", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Decl(
+                "B",
+                Type::Struct(Struct(
+                    vec![(
+                        "a",
+                        Unresolved("A", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Decl("B", Type::Struct(Struct(vec![("a", Unresolved("A", false, false))]))),
             Node::Decl(
                 "D",
-                Type::Struct(Struct(vec![
-                    ("b1", Unresolved("B", false, false)),
-                    ("b2", Unresolved("B", false, false)),
-                ])),
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "b1",
+                            Unresolved("B", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                        (
+                            "b2",
+                            Unresolved("B", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("D"),
+            Node::Export("D", None, Span::default()),
         ];
         assert!(type_check(test).is_ok());
     }
@@ -678,14 +3924,47 @@ mod tests {
             vec![
                 Node::Decl(
                     "UnusedType",
-                    Type::Struct(Struct(vec![("test", Unresolved("uint8", false, false))])),
+                    Type::Struct(Struct(
+                        vec![(
+                            "test",
+                            Unresolved("uint8", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        )],
+                        None,
+                    )),
+                    vec![],
+                    vec![],
+                    Span::default(),
+                ),
+                Node::Decl(
+                    "Flag",
+                    Type::Enum(Enum(vec![
+                        ("A", vec![], Span::default()),
+                        ("B", vec![], Span::default()),
+                    ])),
+                    vec![],
+                    vec![],
+                    Span::default(),
                 ),
-                Node::Decl("Flag", Type::Enum(Enum(vec!["A", "B"]))),
                 Node::Decl(
                     "Test",
-                    Type::Struct(Struct(vec![("flag", Unresolved("Flag", false, false))])),
+                    Type::Struct(Struct(
+                        vec![(
+                            "flag",
+                            Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                            vec![],
+                            vec![],
+                            Span::default(),
+                        )],
+                        None,
+                    )),
+                    vec![],
+                    vec![],
+                    Span::default(),
                 ),
-                Node::Export("Test"),
+                Node::Export("Test", None, Span::default()),
             ]
         };
         let checked = type_check(test).unwrap();
@@ -697,20 +3976,65 @@ mod tests {
         // the type that's being resolved is too deeply nested
         use ast::*;
         let test: AST = vec![
-            Node::Decl("Flag", Type::Enum(Enum(vec!["A", "B"]))),
+            Node::Decl(
+                "Flag",
+                Type::Enum(Enum(vec![
+                    ("A", vec![], Span::default()),
+                    ("B", vec![], Span::default()),
+                ])),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
             Node::Decl(
                 "TestA",
-                Type::Struct(Struct(vec![("test", Unresolved("Flag", false, false))])),
+                Type::Struct(Struct(
+                    vec![(
+                        "test",
+                        Unresolved("Flag", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
             Node::Decl(
                 "TestB",
-                Type::Struct(Struct(vec![("test", Unresolved("TestA", false, false))])),
+                Type::Struct(Struct(
+                    vec![(
+                        "test",
+                        Unresolved("TestA", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
             Node::Decl(
                 "TestC",
-                Type::Struct(Struct(vec![("test", Unresolved("TestB", false, false))])),
+                Type::Struct(Struct(
+                    vec![(
+                        "test",
+                        Unresolved("TestB", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("TestC"),
+            Node::Export("TestC", None, Span::default()),
         ];
         type_check(test).unwrap();
     }
@@ -722,13 +4046,25 @@ mod tests {
         let test: AST = vec![
             Node::Decl(
                 "Test",
-                Type::Struct(Struct(vec![("test", Unresolved("Test", false, false))])),
+                Type::Struct(Struct(
+                    vec![(
+                        "test",
+                        Unresolved("Test", false, false, None, None, None, None, Span::default()),
+                        vec![],
+                        vec![],
+                        Span::default(),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::default(),
             ),
-            Node::Export("Test"),
+            Node::Export("Test", None, Span::default()),
         ];
         let actual = type_check(test);
         assert_eq!(
-            actual.unwrap_err(),
+            actual.unwrap_err()[0].message,
             "Found a cycle between two or more top level definitions in type 'Test'"
         );
     }
@@ -737,8 +4073,11 @@ mod tests {
     fn could_not_resolve_export() {
         // the type does not exist
         use ast::*;
-        let test: AST = vec![Node::Export("Test")];
-        assert_eq!(type_check(test).unwrap_err(), "Export 'Test' could not be resolved");
+        let test: AST = vec![Node::Export("Test", None, Span::default())];
+        assert_eq!(
+            type_check(test).unwrap_err()[0].message,
+            "Export 'Test' could not be resolved"
+        );
     }
 
     #[test]
@@ -746,12 +4085,151 @@ mod tests {
         // only structs may be exported
         use ast::*;
         let test: AST = vec![
-            Node::Decl("Flag", Type::Enum(Enum(vec!["A", "B"]))),
-            Node::Export("Flag"),
+            Node::Decl(
+                "Flag",
+                Type::Enum(Enum(vec![
+                    ("A", vec![], Span::default()),
+                    ("B", vec![], Span::default()),
+                ])),
+                vec![],
+                vec![],
+                Span::default(),
+            ),
+            Node::Export("Flag", None, Span::default()),
         ];
         assert_eq!(
-            type_check(test).unwrap_err(),
+            type_check(test).unwrap_err()[0].message,
             "Attempted to export 'Flag', which is not a struct"
         );
     }
+
+    #[test]
+    fn reports_every_error() {
+        let schema = "A: struct { a: Missing, b?: uint8[] }\n\
+                      B: struct { a: A }\n\
+                      E: enum { X, X }\n\
+                      B: struct { c: uint8 }\n\
+                      export B\n\
+                      export C";
+        let errors = type_check(parser::parse(schema).unwrap()).unwrap_err();
+        // `B` isn't reported for using `A`, which failed to resolve
+        assert_eq!(
+            errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>(),
+            vec![
+                "Declaration for type 'Missing' does not exist",
+                "Field 'b' in struct 'A' cannot be optional and array at once",
+                "Duplicate variant 'X' on enum 'E'",
+                "Schema has duplicate declaration: B",
+                "Export 'C' could not be resolved",
+            ]
+        );
+    }
+
+    #[test]
+    fn declarations_in_source_order() {
+        let schema = "Z: enum { X }\nB: struct { z: Z }\nUnused: struct { a: uint8 }\nA: struct { b: B }\nexport A";
+        let checked = type_check(parser::parse(schema).unwrap()).unwrap();
+        assert_eq!(
+            checked.declarations().iter().map(|t| t.borrow().0).collect::<Vec<_>>(),
+            vec!["Z", "B", "A"]
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let warnings = |schema: &'static str| {
+            let resolved = type_check(parser::parse(schema).unwrap()).unwrap();
+            resolved
+                .warnings
+                .into_iter()
+                .map(|w| (w.code, w.message))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(warnings("A: struct { a: uint8 }\nexport A"), vec![]);
+        // schemas without exports declare types for others to import
+        assert_eq!(warnings("A: struct { a: uint8 }"), vec![]);
+        assert_eq!(
+            warnings("A: struct { a: uint8 }\nB: enum { X }\nexport A"),
+            vec![(
+                Code::UnusedDeclaration,
+                "Type 'B' is declared but not used by any export".to_string()
+            )]
+        );
+        assert_eq!(
+            warnings("K: enum { X, Y }\nA: struct { k: K, x: uint8 if k == K.X }\nexport A"),
+            vec![(
+                Code::UnusedVariant,
+                "Variant 'Y' of enum 'K' isn't checked by any condition".to_string()
+            )]
+        );
+        assert_eq!(
+            warnings("Float: struct { a: uint8 }\nexport Float"),
+            vec![(
+                Code::ShadowingName,
+                "Type 'Float' only differs in case from the builtin type 'float'".to_string()
+            )]
+        );
+        assert_eq!(
+            warnings("a: struct { x: uint8 }\nA: struct { a: a }\nexport A"),
+            vec![(
+                Code::ShadowingName,
+                "Type 'a' only differs in case from the type 'A'".to_string()
+            )]
+        );
+        assert_eq!(
+            warnings("B: struct { id: uint8 }\nA: struct extends B { ID: uint8 }\nexport A"),
+            vec![(
+                Code::FieldCase,
+                "Fields 'id' and 'ID' in struct 'A' only differ in case".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn duplicates_point_to_first_declaration() {
+        let first_label = |schema: &'static str| {
+            let label = type_check(parser::parse(schema).unwrap()).unwrap_err()[0].labels[0].clone();
+            (&schema[label.span.start..label.span.end], label.message)
+        };
+        assert_eq!(
+            first_label("A: struct { a: uint8 }\nA: struct { b: uint8 }\nexport A"),
+            ("A", "first declared here".into())
+        );
+        assert_eq!(
+            first_label("A: struct { a: uint8, b: uint8, a: uint8 }\nexport A"),
+            ("a", "first declared here".into())
+        );
+        assert_eq!(
+            first_label("E: enum { Xa, Y, Xa }\nA: struct { e: E }\nexport A"),
+            ("Xa", "first declared here".into())
+        );
+        assert_eq!(
+            first_label("A: struct { a: uint8 }\nexport A\nexport A"),
+            ("A", "first exported here".into())
+        );
+    }
+
+    #[test]
+    fn errors_have_spans() {
+        let located = |schema: &'static str| {
+            let err = &type_check(parser::parse(schema).unwrap()).unwrap_err()[0];
+            err.primary_span.map(|span| &schema[span.start..span.end])
+        };
+        assert_eq!(located("A: struct { a: Missing }\nexport A"), Some("Missing"));
+        assert_eq!(located("A: struct { a: uint8, b: uint8[c] }\nexport A"), Some("b"));
+        assert_eq!(located("A: enum { X, X }\nB: struct { a: A }\nexport B"), Some("X"));
+        assert_eq!(located("A: struct { a: uint8 }\nexport B"), Some("B"));
+        assert_eq!(
+            located("B: enum { X }\nA: struct extends B { a: uint8 }\nexport A"),
+            Some("B")
+        );
+        assert_eq!(
+            located("E: enum { X }\nA: struct { e: E, b?: uint8 if e == E.Y }\nexport A"),
+            Some("b")
+        );
+        assert_eq!(
+            located("E: enum { X }\nA: struct { e: E, b: uint8 if e == E.Y }\nexport A"),
+            Some("if e == E.Y")
+        );
+    }
 }
@@ -0,0 +1,350 @@
+//! Compares two versions of a schema, to find the changes which break
+//! decoding packets written by the other version
+//!
+//! Only the wire format is compared, so renaming a type or a field is fine,
+//! but e.g. reordering fields or changing their type isn't. Fields are matched
+//! by position, so fields of the same type may even swap names. A field which
+//! doesn't match and whose name is found elsewhere is reported as moved.
+//! Enum variants are matched by value, and exports by their packet id. Exports
+//! without one can only be matched by name, so renaming them is breaking.
+use std::collections::HashSet;
+
+use check::{Enum, Resolved, ResolvedType, Struct, StructField};
+
+use super::*;
+
+#[derive(Default)]
+struct Compat {
+    changes: Vec<String>,
+    /// Pairs of old and new type names which were already compared, so that
+    /// types used in several places are only reported once
+    visited: HashSet<(String, String)>,
+}
+
+/// Returns the breaking changes between `old` and `new`, in the order of the
+/// old schema's exports. An empty list means they're wire-compatible.
+pub fn compare(old: &Resolved, new: &Resolved) -> Vec<String> {
+    let mut compat = Compat::default();
+    for old_export in old.exports.iter() {
+        let by_id = old_export
+            .id
+            .and_then(|id| new.exports.iter().find(|e| e.id == Some(id)));
+        let by_name = || new.exports.iter().find(|e| e.name == old_export.name);
+        let new_export = match by_id.or_else(by_name) {
+            Some(export) => export,
+            None => {
+                compat.changes.push(format!("Export '{}' was removed", old_export.name));
+                continue;
+            }
+        };
+        if old_export.id != new_export.id {
+            let id = |id: Option<u8>| id.map_or("none".to_string(), |id| id.to_string());
+            compat.changes.push(format!(
+                "Export '{}' changed its packet id from {} to {}",
+                old_export.name,
+                id(old_export.id),
+                id(new_export.id)
+            ));
+        }
+        compat.compare_struct(
+            (old_export.name, &old_export.r#struct),
+            (new_export.name, &new_export.r#struct),
+        );
+    }
+    compat.changes
+}
+
+/// The field's type as written in the schema, e.g. `uint8[]` or `string?`
+fn shape(field: &StructField) -> String {
+    let ty = field.r#type.borrow().0;
+    let array = match (field.array, field.length) {
+        (false, _) => String::new(),
+        (true, None) => "[]".to_string(),
+        (true, Some(length)) => format!("[{}]", length),
+    };
+    let optional = if field.optional { "?" } else { "" };
+    format!("{}{}{}", ty, array, optional)
+}
+
+/// Position of the field `name` in the struct, which is what length and
+/// condition references are resolved to on the wire
+fn position(ty: &Struct, name: &str) -> Option<usize> { ty.fields.iter().position(|f| f.name == name) }
+
+impl Compat {
+    fn visit(&mut self, old: &str, new: &str) -> bool { self.visited.insert((old.to_string(), new.to_string())) }
+
+    fn compare_struct(&mut self, (old_name, old): (&str, &Struct), (new_name, new): (&str, &Struct)) {
+        if !self.visit(old_name, new_name) {
+            return;
+        }
+        if old.is_extensible() != new.is_extensible() {
+            let now = if new.is_extensible() { "now" } else { "no longer" };
+            self.changes
+                .push(format!("Struct '{}' is {} @extensible", old_name, now));
+            return;
+        }
+        for (attr, attr_name) in [
+            (check::Attribute::Bitmask, "bitmask"),
+            (check::Attribute::Packed, "packed"),
+        ]
+        .iter()
+        {
+            if old.attrs.contains(attr) != new.attrs.contains(attr) {
+                let now = if new.attrs.contains(attr) { "now" } else { "no longer" };
+                self.changes
+                    .push(format!("Struct '{}' is {} @{}", old_name, now, attr_name));
+            }
+        }
+        if old.is_extensible() {
+            // fields are identified by their tags, so they may be added,
+            // removed and reordered freely
+            for old_field in old.fields.iter() {
+                if let Some(new_field) = new.fields.iter().find(|f| f.tag == old_field.tag) {
+                    self.compare_field(old_name, (old, old_field), (new, new_field));
+                }
+            }
+            return;
+        }
+        let mut moved_fields = HashSet::new();
+        for i in 0..old.fields.len().max(new.fields.len()) {
+            match (old.fields.get(i), new.fields.get(i)) {
+                (Some(old_field), new_field) => {
+                    let moved = position(new, old_field.name).filter(|&j| j != i);
+                    if let Some(new_field) = new_field {
+                        // the fields at the same position are compatible,
+                        // whatever their names are
+                        let (changes, visited) = (self.changes.len(), self.visited.clone());
+                        self.compare_field(old_name, (old, old_field), (new, new_field));
+                        if self.changes.len() == changes || moved.is_none() {
+                            continue;
+                        }
+                        self.changes.truncate(changes);
+                        self.visited = visited;
+                    }
+                    match moved {
+                        Some(j) => {
+                            moved_fields.insert(old_field.name);
+                            self.changes.push(format!(
+                                "Field '{}' in struct '{}' moved from position {} to {}",
+                                old_field.name, old_name, i, j
+                            ))
+                        }
+                        None => self.changes.push(format!(
+                            "Field '{}' was removed from struct '{}'",
+                            old_field.name, old_name
+                        )),
+                    }
+                }
+                (None, Some(new_field)) => {
+                    // moved fields are reported by their old position
+                    if !moved_fields.contains(new_field.name) {
+                        self.changes
+                            .push(format!("Field '{}' was added to struct '{}'", new_field.name, old_name));
+                    }
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    fn compare_field(
+        &mut self,
+        struct_name: &str,
+        (old_parent, old): (&Struct, &StructField),
+        (new_parent, new): (&Struct, &StructField),
+    ) {
+        let target = format!("Field '{}' in struct '{}'", old.name, struct_name);
+        if old.array != new.array || old.optional != new.optional || old.length.is_some() != new.length.is_some() {
+            self.changes
+                .push(format!("{} changed from '{}' to '{}'", target, shape(old), shape(new)));
+            return;
+        }
+        if old.length.and_then(|l| position(old_parent, l)) != new.length.and_then(|l| position(new_parent, l)) {
+            self.changes
+                .push(format!("{} takes its length from another field", target));
+        }
+        let condition = |parent: &Struct, f: &StructField| {
+            f.condition
+                .as_ref()
+                .map(|c| (position(parent, c.field), c.variant.to_string()))
+        };
+        if condition(old_parent, old) != condition(new_parent, new) {
+            self.changes.push(format!("{} changed its condition", target));
+        }
+        if old.range != new.range {
+            self.changes.push(format!("{} changed its range", target));
+        }
+
+        let (old_ty, new_ty) = (&*old.r#type.borrow(), &*new.r#type.borrow());
+        match (&old_ty.1, &new_ty.1) {
+            (ResolvedType::Builtin(a), ResolvedType::Builtin(b)) if a == b => (),
+            (ResolvedType::Enum(a), ResolvedType::Enum(b)) => self.compare_enum((old_ty.0, a), (new_ty.0, b)),
+            (ResolvedType::Struct(a), ResolvedType::Struct(b)) => self.compare_struct((old_ty.0, a), (new_ty.0, b)),
+            _ => self
+                .changes
+                .push(format!("{} changed type from '{}' to '{}'", target, old_ty.0, new_ty.0)),
+        }
+    }
+
+    fn compare_enum(&mut self, (old_name, old): (&str, &Enum), (new_name, new): (&str, &Enum)) {
+        if !self.visit(old_name, new_name) {
+            return;
+        }
+        if old.repr != new.repr {
+            self.changes.push(format!(
+                "Enum '{}' changed its size from {} to {}",
+                old_name, old.repr, new.repr
+            ));
+        }
+        // only values are written, so variants may be renamed freely
+        for variant in old.variants.iter() {
+            if !new.variants.iter().any(|v| v.value == variant.value) {
+                self.changes.push(format!(
+                    "Variant '{}' was removed from enum '{}'",
+                    variant.name, old_name
+                ));
+            }
+        }
+        // readers of the old schema reject values they don't know
+        for variant in new.variants.iter() {
+            if !old.variants.iter().any(|v| v.value == variant.value) {
+                self.changes
+                    .push(format!("Variant '{}' was added to enum '{}'", variant.name, old_name));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn compat(old: &str, new: &str) -> Vec<String> {
+        let old = check::type_check(parser::pkt::schema(old).unwrap()).unwrap();
+        let new = check::type_check(parser::pkt::schema(new).unwrap()).unwrap();
+        compare(&old, &new)
+    }
+
+    #[test]
+    fn compatible() {
+        let old = "Kind: enum { A, B }
+            Stats: struct { hp: uint8, kind: Kind }
+            Test: struct { stats: Stats, names: string[] }
+            export Test = 1";
+        assert_eq!(compat(old, old), Vec::<String>::new());
+        // names aren't part of the wire format
+        let new = "Type: enum { A, B }
+            Numbers: struct { health: uint8, kind: Type }
+            Test: struct { stats: Numbers, names: string[] }
+            Other: struct { a: uint8 }
+            export Test = 1
+            export Other = 2";
+        assert_eq!(compat(old, new), Vec::<String>::new());
+    }
+
+    #[test]
+    fn exports() {
+        let old = "A: struct { a: uint8 }
+            B: struct { b: uint8 }
+            export A = 1
+            export B = 2";
+        let new = "A: struct { a: uint8 }
+            export A = 3";
+        assert_eq!(
+            compat(old, new),
+            vec!["Export 'A' changed its packet id from 1 to 3", "Export 'B' was removed"]
+        );
+    }
+
+    #[test]
+    fn fields() {
+        let old = "Test: struct { a: uint8, b: uint16, c: string, d: uint8[], e: uint8 }
+            export Test";
+        let new = "Test: struct { b: uint16, a: uint8, c: uint32, d?: uint8, e: uint8 in ..10, f: uint8 }
+            export Test";
+        assert_eq!(
+            compat(old, new),
+            vec![
+                "Field 'a' in struct 'Test' moved from position 0 to 1",
+                "Field 'b' in struct 'Test' moved from position 1 to 0",
+                "Field 'c' in struct 'Test' changed type from 'string' to 'uint32'",
+                "Field 'd' in struct 'Test' changed from 'uint8[]' to 'uint8?'",
+                "Field 'e' in struct 'Test' changed its range",
+                "Field 'f' was added to struct 'Test'",
+            ]
+        );
+    }
+
+    #[test]
+    fn enums() {
+        let old = "Kind: enum { A, B, C }
+            Test: struct { a: Kind, b: Kind }
+            export Test";
+        let new = "Kind: enum { A, B }
+            Test: struct { a: Kind, b: Kind }
+            export Test";
+        assert_eq!(compat(old, new), vec!["Variant 'C' was removed from enum 'Kind'"]);
+        let new = "Kind: enum { A, B, C, D }
+            Test: struct { a: Kind, b: Kind }
+            export Test";
+        assert_eq!(compat(old, new), vec!["Variant 'D' was added to enum 'Kind'"]);
+        // variants are matched by value
+        let new = "Kind: enum { A, Bee, C }
+            Test: struct { a: Kind, b: Kind }
+            export Test";
+        assert_eq!(compat(old, new), Vec::<String>::new());
+    }
+
+    #[test]
+    fn renamed_exports() {
+        // exports with a packet id are matched by it
+        let old = "A: struct { a: uint8 }
+            export A = 1";
+        let new = "B: struct { a: uint8 }
+            export B = 1";
+        assert_eq!(compat(old, new), Vec::<String>::new());
+        // without one, nothing on the wire identifies them
+        let old = "A: struct { a: uint8 }
+            export A";
+        let new = "B: struct { a: uint8 }
+            export B";
+        assert_eq!(compat(old, new), vec!["Export 'A' was removed"]);
+    }
+
+    #[test]
+    fn extensible() {
+        let old = "@extensible
+            Test: struct { a @1: uint8, b @2: string, c @3: uint8 }
+            export Test";
+        let new = "@extensible
+            Test: struct { d? @4: uint8, a @1: uint8, c @3: uint16 }
+            export Test";
+        assert_eq!(
+            compat(old, new),
+            vec!["Field 'c' in struct 'Test' changed type from 'uint8' to 'uint16'"]
+        );
+        let new = "Test: struct { a: uint8, b: string, c: uint8 }
+            export Test";
+        assert_eq!(compat(old, new), vec!["Struct 'Test' is no longer @extensible"]);
+    }
+
+    #[test]
+    fn swapped_names() {
+        let old = "Test: struct { a: uint8, b: uint8, c: string }
+            export Test";
+        let new = "Test: struct { b: uint8, a: uint8, c: string }
+            export Test";
+        assert_eq!(compat(old, new), Vec::<String>::new());
+        let new = "Test: struct { c: string, b: uint8, a: uint8 }
+            export Test";
+        assert_eq!(
+            compat(old, new),
+            vec![
+                "Field 'a' in struct 'Test' moved from position 0 to 2",
+                "Field 'c' in struct 'Test' moved from position 2 to 0",
+            ]
+        );
+    }
+}
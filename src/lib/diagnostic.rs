@@ -0,0 +1,626 @@
+//! Structured errors reported by the parser and the type checker
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Byte range into the schema source
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span { Span { start, end } }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Stable identifier for a kind of diagnostic, printed as `E0001` etc. for
+/// errors and `W0001` etc. for warnings
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Code {
+    /// The schema couldn't be parsed
+    Syntax = 1,
+    /// A type, field or variant that doesn't exist is referenced
+    Unresolved,
+    /// Something is declared more than once
+    Duplicate,
+    /// Types or imports which depend on themselves
+    Cycle,
+    /// An attribute is unknown, malformed or used in the wrong place
+    Attribute,
+    /// A range constraint (`in min..max` or `in min..=max`) which is
+    /// unsupported, empty or doesn't fit its type
+    Range,
+    /// A field combines modifiers which can't be used together
+    FieldShape,
+    /// An array length or condition refers to an unsuitable field
+    FieldReference,
+    /// Tags missing, reserved or reused on `@extensible` structs
+    Tag,
+    /// An enum with no variants, or too many of them
+    Enum,
+    /// An export which isn't a struct, or whose packet id is missing or reused
+    Export,
+    /// A struct extending something which isn't a struct
+    Extends,
+    /// An import used where imports can't be resolved, or of a schema which
+    /// can't be read
+    Import,
+
+    /// A declaration which isn't used by any export, so no code is
+    /// generated for it
+    UnusedDeclaration = 1001,
+    /// An enum variant which no condition checks for, while other variants
+    /// of the same enum are
+    UnusedVariant,
+    /// A type name which only differs in case from a builtin or another type
+    ShadowingName,
+    /// Fields of a struct whose names only differ in case
+    FieldCase,
+}
+
+impl Code {
+    pub const ALL: [Code; 17] = [
+        Code::Syntax,
+        Code::Unresolved,
+        Code::Duplicate,
+        Code::Cycle,
+        Code::Attribute,
+        Code::Range,
+        Code::FieldShape,
+        Code::FieldReference,
+        Code::Tag,
+        Code::Enum,
+        Code::Export,
+        Code::Extends,
+        Code::Import,
+        Code::UnusedDeclaration,
+        Code::UnusedVariant,
+        Code::ShadowingName,
+        Code::FieldCase,
+    ];
+
+    pub fn is_warning(self) -> bool { self as u32 > 1000 }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self as u32 {
+            n if self.is_warning() => write!(f, "W{:04}", n - 1000),
+            n => write!(f, "E{:04}", n),
+        }
+    }
+}
+
+impl std::str::FromStr for Code {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Code::ALL
+            .iter()
+            .find(|code| code.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("Unknown diagnostic code '{}'", s))
+    }
+}
+
+/// Secondary location attached to a diagnostic
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    pub message: String,
+    pub primary_span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    /// File the spans point into, if the schema was read from one
+    pub path: Option<PathBuf>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            code,
+            severity: Severity::Error,
+            message: message.into(),
+            primary_span: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            path: None,
+        }
+    }
+
+    pub fn warning(code: Code, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, message)
+        }
+    }
+
+    pub fn with_span(mut self, span: impl Into<Option<Span>>) -> Diagnostic {
+        self.primary_span = span.into();
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Diagnostic {
+        self.path = Some(path.into());
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        for note in self.notes.iter() {
+            write!(f, "\n  = note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Quotes `s` as a JSON string
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A span as a JSON object, with 1-based lines and columns if the source is
+/// known. Columns count characters, and the end is exclusive.
+fn json_span(span: Span, source: Option<&str>) -> String {
+    let mut out = format!("{{\"start\":{},\"end\":{}", span.start, span.end);
+    if let Some(source) = source {
+        let position = |offset: usize| {
+            let offset = offset.min(source.len());
+            let (line, line_start) = line_of(source, offset);
+            (line + 1, source[line_start..offset].chars().count() + 1)
+        };
+        let (line, column) = position(span.start);
+        let (end_line, end_column) = position(span.end);
+        out += &format!(
+            ",\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}",
+            line, column, end_line, end_column
+        );
+    }
+    out.push('}');
+    out
+}
+
+impl Diagnostic {
+    /// The diagnostic as a single line of JSON. Lines and columns are only
+    /// included if `source`, the text of the file the spans point into, is.
+    pub fn to_json(&self, source: Option<&str>) -> String {
+        let file = self
+            .path
+            .as_ref()
+            .map_or_else(|| "null".to_string(), |p| json_string(&p.display().to_string()));
+        let span = self
+            .primary_span
+            .map_or_else(|| "null".to_string(), |span| json_span(span, source));
+        let labels = self
+            .labels
+            .iter()
+            .map(|l| {
+                format!(
+                    "{{\"span\":{},\"message\":{}}}",
+                    json_span(l.span, source),
+                    json_string(&l.message)
+                )
+            })
+            .collect::<Vec<_>>();
+        let notes = self.notes.iter().map(|n| json_string(n)).collect::<Vec<_>>();
+        format!(
+            "{{\"file\":{},\"code\":\"{}\",\"severity\":\"{}\",\"message\":{},\"span\":{},\"labels\":[{}],\"notes\":[{}]}}",
+            file,
+            self.code,
+            self.severity,
+            json_string(&self.message),
+            span,
+            labels.join(","),
+            notes.join(",")
+        )
+    }
+}
+
+/// How a warning is reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// Levels of the warnings, set by code or for all of them at once
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lints {
+    default: Level,
+    levels: HashMap<Code, Level>,
+}
+
+impl Default for Lints {
+    fn default() -> Lints {
+        Lints {
+            default: Level::Warn,
+            levels: HashMap::new(),
+        }
+    }
+}
+
+impl Lints {
+    /// Sets the level of the warning `code`, or of every warning without a
+    /// level of its own if `code` is `None`
+    pub fn set(&mut self, code: Option<Code>, level: Level) {
+        match code {
+            Some(code) => {
+                self.levels.insert(code, level);
+            }
+            None => self.default = level,
+        }
+    }
+
+    pub fn level(&self, code: Code) -> Level { self.levels.get(&code).copied().unwrap_or(self.default) }
+
+    /// Drops the allowed warnings, and turns the denied ones into errors.
+    /// Fails with every denied warning if there are any.
+    pub fn apply(&self, warnings: Vec<Diagnostic>) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let mut kept = Vec::new();
+        let mut denied = Vec::new();
+        for warning in warnings {
+            match self.level(warning.code) {
+                Level::Allow => (),
+                Level::Warn => kept.push(warning),
+                Level::Deny => {
+                    let note = format!("warning {} is denied", warning.code);
+                    denied.push(
+                        Diagnostic {
+                            severity: Severity::Error,
+                            ..warning
+                        }
+                        .with_note(note),
+                    );
+                }
+            }
+        }
+        if denied.is_empty() {
+            Ok(kept)
+        } else {
+            Err(denied)
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Wraps `text` in the ANSI `style`, if colors are enabled
+fn paint(color: bool, style: &str, text: &str) -> String {
+    if color {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Width of `text` in the terminal, counting tabs as four columns
+fn width(text: &str) -> usize { text.chars().map(|ch| if ch == '\t' { 4 } else { 1 }).sum() }
+
+/// `offset`, moved back to the start of the character it points into
+fn char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// Terminal column of `offset` within its line, starting at 0
+fn column(source: &str, offset: usize) -> usize {
+    let offset = char_boundary(source, offset);
+    width(&source[line_of(source, offset).1..offset])
+}
+
+/// Line of `source` containing `offset`, as (index, byte offset of its start)
+fn line_of(source: &str, offset: usize) -> (usize, usize) {
+    let offset = char_boundary(source, offset);
+    let before = &source[..offset];
+    (before.matches('\n').count(), before.rfind('\n').map_or(0, |i| i + 1))
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic for a terminal, quoting the lines of `source`
+    /// which the primary span and labels point into
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let severity_style = match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+        let mut out = format!(
+            "{}{}\n",
+            paint(color, severity_style, &format!("{}[{}]", self.severity, self.code)),
+            paint(color, BOLD, &format!(": {}", self.message))
+        );
+        let path = self
+            .path
+            .as_ref()
+            .map_or_else(|| "<schema>".to_string(), |p| p.display().to_string());
+
+        let primary = match self.primary_span {
+            Some(span) => span,
+            None => {
+                if self.path.is_some() {
+                    out += &format!("{} {}\n", paint(color, BLUE, "  -->"), path);
+                }
+                for note in self.notes.iter() {
+                    out += &format!("{} note: {}\n", paint(color, BLUE, "  ="), note);
+                }
+                return out;
+            }
+        };
+
+        // (span, marker, style, message) for every underline, where a label
+        // on the primary span is shown on its underline
+        let mut annotations = vec![(primary, '^', severity_style, "")];
+        for label in self.labels.iter() {
+            if label.span == primary && annotations[0].3.is_empty() {
+                annotations[0].3 = label.message.as_str();
+            } else {
+                annotations.push((label.span, '-', BLUE, label.message.as_str()));
+            }
+        }
+        let lines = source.split('\n').map(|l| l.trim_end_matches('\r')).collect::<Vec<_>>();
+        let last_line = |span: Span| line_of(source, span.end.max(span.start + 1) - 1).0;
+        let shown = annotations
+            .iter()
+            .flat_map(|(span, ..)| line_of(source, span.start).0..=last_line(*span))
+            .filter(|&i| i < lines.len())
+            .collect::<BTreeSet<_>>();
+        let gutter = shown.iter().next_back().map_or(1, |i| (i + 1).to_string().len());
+        let bar = paint(color, BLUE, &format!("{} |", " ".repeat(gutter)));
+
+        let line = line_of(source, primary.start).0;
+        out += &format!(
+            "{}{} {}:{}:{}\n",
+            " ".repeat(gutter),
+            paint(color, BLUE, "-->"),
+            path,
+            line + 1,
+            column(source, primary.start) + 1
+        );
+        out += &format!("{}\n", bar);
+        let mut previous = None;
+        for &index in shown.iter() {
+            if matches!(previous, Some(p) if index > p + 1) {
+                out += &format!("{}\n", paint(color, BLUE, "..."));
+            }
+            previous = Some(index);
+            let text = lines[index];
+            out += &format!(
+                "{} {}\n",
+                paint(color, BLUE, &format!("{:>width$} |", index + 1, width = gutter)),
+                text.replace('\t', "    ")
+            );
+            for (span, marker, style, message) in annotations.iter() {
+                let first = line_of(source, span.start).0;
+                let last = last_line(*span);
+                if index < first || index > last {
+                    continue;
+                }
+                let from = if index == first { column(source, span.start) } else { 0 };
+                let to = if index == last {
+                    column(source, span.end.max(span.start + 1) - 1) + 1
+                } else {
+                    width(text)
+                };
+                let marks = marker.to_string().repeat(to.saturating_sub(from).max(1));
+                let message = if index == last && !message.is_empty() {
+                    format!(" {}", message)
+                } else {
+                    String::new()
+                };
+                out += &format!(
+                    "{} {}{}\n",
+                    bar,
+                    " ".repeat(from),
+                    paint(color, style, &format!("{}{}", marks, message))
+                );
+            }
+        }
+        for note in self.notes.iter() {
+            out += &format!(
+                "{} note: {}\n",
+                paint(color, BLUE, &format!("{} =", " ".repeat(gutter))),
+                note
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let diagnostic = Diagnostic::error(Code::Duplicate, "Duplicate field 'a' on struct 'A'")
+            .with_path("schema.pkt")
+            .with_note("fields must have unique names");
+        assert_eq!(
+            diagnostic.to_string(),
+            "schema.pkt: error[E0003]: Duplicate field 'a' on struct 'A'\n  = note: fields must have unique names"
+        );
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(Code::Syntax.to_string(), "E0001");
+        assert_eq!(Code::UnusedDeclaration.to_string(), "W0001");
+        assert_eq!("w0004".parse::<Code>(), Ok(Code::FieldCase));
+        assert!("E0999".parse::<Code>().is_err());
+        assert!(Code::ALL
+            .iter()
+            .all(|code| code.to_string().parse::<Code>() == Ok(*code)));
+    }
+
+    #[test]
+    fn lint_levels() {
+        let warnings = vec![
+            Diagnostic::warning(Code::UnusedDeclaration, "unused"),
+            Diagnostic::warning(Code::FieldCase, "case"),
+        ];
+        assert_eq!(Lints::default().apply(warnings.clone()), Ok(warnings.clone()));
+
+        let mut lints = Lints::default();
+        lints.set(Some(Code::UnusedDeclaration), Level::Allow);
+        assert_eq!(lints.apply(warnings.clone()), Ok(vec![warnings[1].clone()]));
+
+        // a level for a single warning overrides the one for all of them
+        lints.set(None, Level::Deny);
+        let denied = lints.apply(warnings).unwrap_err();
+        assert_eq!(denied.len(), 1);
+        assert_eq!(denied[0].severity, Severity::Error);
+        assert_eq!(denied[0].notes, vec!["warning W0004 is denied".to_string()]);
+    }
+
+    #[test]
+    fn json() {
+        let source = "A: struct {\n  a: Missing,\n}\n";
+        let diagnostic = Diagnostic::error(Code::Unresolved, "Declaration for type \"Missing\" does not exist")
+            .with_span(Span::new(17, 24))
+            .with_label(Span::new(0, 1), "in this struct")
+            .with_note("declare it\tfirst")
+            .with_path("dir\\schema.pkt");
+        assert_eq!(
+            diagnostic.to_json(Some(source)),
+            r#"{"file":"dir\\schema.pkt","code":"E0002","severity":"error","message":"Declaration for type \"Missing\" does not exist","span":{"start":17,"end":24,"line":2,"column":6,"end_line":2,"end_column":13},"labels":[{"span":{"start":0,"end":1,"line":1,"column":1,"end_line":1,"end_column":2},"message":"in this struct"}],"notes":["declare it\tfirst"]}"#
+        );
+        assert_eq!(
+            Diagnostic::warning(Code::FieldCase, "case")
+                .with_span(Span::new(1, 2))
+                .to_json(None),
+            r#"{"file":null,"code":"W0004","severity":"warning","message":"case","span":{"start":1,"end":2},"labels":[],"notes":[]}"#
+        );
+    }
+
+    #[test]
+    fn render_labels() {
+        let source = "A: struct { a: uint8 }\nB: enum { X }\nC: enum { Y }\nA: enum { Z }\n";
+        let diagnostic = Diagnostic::error(Code::Duplicate, "Schema has duplicate declaration: A")
+            .with_span(Span::new(51, 52))
+            .with_label(Span::new(0, 1), "first declared here")
+            .with_path("schema.pkt");
+        assert_eq!(
+            diagnostic.render(source, false),
+            "error[E0003]: Schema has duplicate declaration: A
+ --> schema.pkt:4:1
+  |
+1 | A: struct { a: uint8 }
+  | - first declared here
+...
+4 | A: enum { Z }
+  | ^
+"
+        );
+    }
+
+    #[test]
+    fn render_multiline_span() {
+        let source = "A: struct {\n  a: uint8,\n}\n";
+        let diagnostic = Diagnostic::error(Code::Syntax, "Unexpected end of file")
+            .with_span(Span::new(3, 25))
+            .with_label(Span::new(3, 25), "struct started here")
+            .with_note("structs end with '}'");
+        assert_eq!(
+            diagnostic.render(source, false),
+            "error[E0001]: Unexpected end of file
+ --> <schema>:1:4
+  |
+1 | A: struct {
+  |    ^^^^^^^^
+2 |   a: uint8,
+  | ^^^^^^^^^^^
+3 | }
+  | ^ struct started here
+  = note: structs end with '}'
+"
+        );
+    }
+
+    #[test]
+    fn render_multibyte_span() {
+        let source = "A: struct { a: uint8, b: é }\nexport A\n";
+        let diagnostic = Diagnostic::error(Code::Unresolved, "Declaration for type \"é\" does not exist")
+            .with_span(Span::new(25, 27));
+        assert_eq!(
+            diagnostic.render(source, false),
+            "error[E0002]: Declaration for type \"é\" does not exist
+ --> <schema>:1:26
+  |
+1 | A: struct { a: uint8, b: é }
+  |                          ^
+"
+        );
+    }
+
+    #[test]
+    fn render_colors() {
+        let diagnostic = Diagnostic::error(Code::Syntax, "Unexpected token").with_span(Span::new(0, 1));
+        let rendered = diagnostic.render("x", true);
+        assert!(rendered.starts_with("\x1b[1;31merror[E0001]\x1b[0m"));
+        assert!(!diagnostic.render("x", false).contains('\x1b'));
+    }
+}
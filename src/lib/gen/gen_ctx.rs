@@ -2,14 +2,17 @@ pub struct GenCtx<'a> {
     pub indentation: String,
     pub out: &'a mut String,
     pub stack: Vec<String>,
+    /// Whether values are written to a bit stream, for @packed exports
+    pub packed: bool,
 }
 
 impl<'a> GenCtx<'a> {
-    pub fn new(out: &'a mut String) -> GenCtx {
+    pub fn new(out: &'a mut String) -> GenCtx<'a> {
         GenCtx {
             indentation: String::new(),
             out,
             stack: Vec::new(),
+            packed: false,
         }
     }
 
@@ -1,5 +1,5 @@
 pub mod gen_ctx;
-/* pub mod rust; */
+pub mod rust;
 pub mod ts;
 
 use std::fmt::Write;
@@ -13,13 +13,34 @@ const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 
 pub fn generate<Lang>(from: &check::Resolved) -> String
 where
-    Lang: Language + Default + Common + Declaration + Impl,
+    Lang: Language + Default + Common + Declaration + Impl + Message + Handler + Package + Fingerprint,
 {
     let mut gen = Generator::<Lang>::new();
     gen.push_meta();
     gen.push_common();
-    gen.push_decls(&from.types, from.export.name);
-    gen.push_impl(&from.export);
+    gen.push_imports(&from.package, &from.imports);
+
+    // with a package, everything else is generated separately so that it can
+    // be nested inside of it
+    let mut body = Generator::<Lang>::new();
+    body.push_fingerprint(from.fingerprint());
+    body.push_decls(
+        &from.declarations(),
+        &from.exports.iter().map(|e| e.name).collect::<Vec<_>>(),
+    );
+    for export in from.exports.iter() {
+        body.push_impl(export);
+    }
+    if from.exports.iter().any(|e| e.id.is_some()) {
+        body.push_message(&from.exports);
+        body.push_handler(&from.exports);
+    }
+    let body = body.finish();
+    if from.package.is_empty() {
+        gen.buffer += &body;
+    } else {
+        gen.push_package(&from.package, &from.imports, &body);
+    }
     gen.finish()
 }
 
@@ -43,13 +64,60 @@ macro_rules! cat {
 
 pub trait Language {}
 
+/// Writes `code` into `ctx`, with every non-empty line indented to the
+/// current indentation level
+pub fn indent_into(ctx: &mut GenCtx, code: &str) {
+    for line in code.lines() {
+        if line.is_empty() {
+            ctx.out.push('\n');
+        } else {
+            cat!(ctx, "{line}\n");
+        }
+    }
+}
+
+/// Bits written for integers in packed exports, unless their range needs less
+pub fn packed_width(type_info: &check::Builtin) -> u32 {
+    match type_info {
+        check::Builtin::Uint8 | check::Builtin::Int8 => 8,
+        check::Builtin::Uint16 | check::Builtin::Int16 => 16,
+        _ => 32,
+    }
+}
+
+/// The expression `value - min`, which packed exports write instead of
+/// values with both bounds of their range
+pub fn offset_from(value: &str, min: i64) -> String {
+    match min {
+        0 => value.to_string(),
+        min if min < 0 => format!("{} + {}", value, -min),
+        min => format!("{} - {}", value, min),
+    }
+}
+
+/// Converts a type name such as `PlayerState` into `player_state`
+pub fn snake_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for (i, ch) in name.char_indices() {
+        if ch.is_ascii_uppercase() {
+            if i > 0 && !out.ends_with('_') {
+                out.push('_');
+            }
+            out.push(ch.to_ascii_lowercase());
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
 #[derive(Clone, PartialEq, Debug, Default)]
 pub struct Generator<L: Language + Default + Common> {
     state: L,
     buffer: String,
 }
 
-impl<L: Language + Default + Common + Declaration + Impl> Generator<L> {
+impl<L: Language + Default + Common + Declaration + Impl + Message + Handler + Package + Fingerprint> Generator<L> {
     pub fn new() -> Self {
         Generator {
             state: L::default(),
@@ -72,21 +140,66 @@ impl<L: Language + Default + Common + Declaration + Impl> Generator<L> {
         );
     }
     pub fn push_common(&mut self) { self.state.gen_common(&mut self.buffer); }
-    pub fn push_decls<'a>(&mut self, types: &check::TypeMap<'a>, export: &str) {
-        self.state.gen_decls(types, export, &mut self.buffer);
+    pub fn push_imports(&mut self, package: &[&str], imports: &[check::ImportedTypes]) {
+        self.state.gen_imports(package, imports, &mut self.buffer);
+    }
+    pub fn push_package(&mut self, package: &[&str], imports: &[check::ImportedTypes], body: &str) {
+        self.state.gen_package(package, imports, body, &mut self.buffer);
+    }
+    pub fn push_fingerprint(&mut self, fingerprint: u32) { self.state.gen_fingerprint(fingerprint, &mut self.buffer); }
+    pub fn push_decls<'a>(&mut self, types: &[check::Ptr<(&'a str, check::ResolvedType<'a>)>], exports: &[&str]) {
+        self.state.gen_decls(types, exports, &mut self.buffer);
     }
     pub fn push_impl(&mut self, export: &check::Export) { self.state.gen_impl(export, &mut self.buffer); }
+    pub fn push_message(&mut self, exports: &[check::Export]) { self.state.gen_message(exports, &mut self.buffer); }
+    pub fn push_handler(&mut self, exports: &[check::Export]) { self.state.gen_handler(exports, &mut self.buffer); }
     pub fn finish(mut self) -> String { std::mem::take(&mut self.buffer) }
 }
 
 pub trait Common {
     fn gen_common(&self, out: &mut String);
+    /// Brings the types used from other schemas into scope. Each import has
+    /// a module path relative to the generated file, e.g. `./shared`, along
+    /// with the names of the types used from it. `package` is the package of
+    /// the schema being generated.
+    fn gen_imports(&self, package: &[&str], imports: &[check::ImportedTypes], out: &mut String);
+}
+
+/// Nests the generated declarations and implementations inside of the
+/// namespaces/modules of the schema's package, and brings the imported
+/// types into scope there.
+pub trait Package {
+    fn gen_package(&self, package: &[&str], imports: &[check::ImportedTypes], body: &str, out: &mut String);
+}
+
+/// Emits the schema's fingerprint as a constant, so that both sides of a
+/// connection can check that they were generated from compatible schemas.
+pub trait Fingerprint {
+    fn gen_fingerprint(&self, fingerprint: u32, out: &mut String);
 }
 
 pub trait Impl {
     fn gen_impl(&self, export: &check::Export, out: &mut String);
 }
 
+/// Generates the `Message` type which wraps every export that has a packet id,
+/// along with `read_any`/`write_any` which prefix the packet with its id.
+pub trait Message {
+    fn gen_message(&self, exports: &[check::Export], out: &mut String);
+}
+
+/// Generates a `Handler` interface with an `on_<export>` method per export,
+/// and a `dispatch` function which decodes a `Message` and calls into it.
+pub trait Handler {
+    fn gen_handler(&self, exports: &[check::Export], out: &mut String);
+}
+
+/// Generates the declared types, in the order they're given in
 pub trait Declaration {
-    fn gen_decls<'a>(&self, types: &check::TypeMap<'a>, export: &str, out: &mut String);
+    fn gen_decls<'a>(
+        &self,
+        types: &[check::Ptr<(&'a str, check::ResolvedType<'a>)>],
+        exports: &[&str],
+        out: &mut String,
+    );
 }
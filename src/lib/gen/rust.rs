@@ -15,30 +15,127 @@ impl Common for Rust {
         let ctx = GenCtx::new(out);
         cat!(
             ctx,
-            "#![allow(dead_code, non_camel_case_types, unused_imports, unused_assignments, clippy::field_reassign_with_default)]\n"
+            "#![allow(dead_code, deprecated, non_camel_case_types, unused_imports, unused_assignments, clippy::field_reassign_with_default)]\n"
         );
         cat!(ctx, "use std::convert::TryFrom;\n");
     }
+
+    fn gen_imports(&self, package: &[&str], imports: &[check::ImportedTypes], out: &mut String) {
+        // otherwise the imports are declared inside of the package's module
+        if package.is_empty() {
+            gen_uses(&mut GenCtx::new(out), 0, imports);
+        }
+    }
+}
+
+/// Brings the imported types into scope, from a module nested `depth` levels
+/// deep inside of the generated file
+fn gen_uses(ctx: &mut GenCtx, depth: usize, imports: &[check::ImportedTypes]) {
+    for import in imports {
+        let path = module_path(depth, &import.module)
+            .into_iter()
+            .chain(import.package.iter().copied())
+            .collect::<Vec<_>>()
+            .join("::");
+        let names = import.names.join(", ");
+        cat!(ctx, "use {path}::{{{names}}};\n");
+    }
+}
+
+/// Converts a relative module path such as `../common/entity` into a Rust
+/// path such as `super::super::common::entity`, assuming each generated file
+/// is a module nested under the same parent as its schema's directory.
+fn module_path(depth: usize, module: &str) -> Vec<&str> {
+    let mut path = vec!["super"; depth + 1];
+    for part in module.split('/') {
+        match part {
+            "." => (),
+            ".." => path.push("super"),
+            part => path.push(part),
+        }
+    }
+    path
+}
+
+impl Package for Rust {
+    fn gen_package(&self, package: &[&str], imports: &[check::ImportedTypes], body: &str, out: &mut String) {
+        let mut ctx = GenCtx::new(out);
+        for name in package.iter() {
+            cat!(ctx, "pub mod {name} {{\n");
+            cat!(ctx +++);
+        }
+        cat!(ctx, "use std::convert::TryFrom;\n");
+        gen_uses(&mut ctx, package.len(), imports);
+        indent_into(&mut ctx, body);
+        for _ in package.iter() {
+            cat!(ctx ---);
+            cat!(ctx, "}}\n");
+        }
+    }
 }
 
 fn varname(stack: &[String], name: &str) -> String { format!("{}_{}", stack.join("_"), name) }
 fn bindname(stack: &[String]) -> String { stack.join("_") }
 fn fname(stack: &[String]) -> String { stack.join(".") }
 
-fn gen_write_impl_optional(ctx: &mut GenCtx, by_ref: bool, body: impl Fn(&mut GenCtx)) {
+/// When `masked`, the field's presence is already part of the struct's
+/// bitmask, so no presence byte is written
+fn gen_write_impl_optional(ctx: &mut GenCtx, by_ref: bool, masked: bool, body: impl Fn(&mut GenCtx)) {
+    let fname = fname(&ctx.stack);
+    let bind_var = bindname(&ctx.stack);
+    let mut old_stack = Vec::new();
+    ctx.swap_stack(&mut old_stack);
+    ctx.push_fname(bind_var.clone());
+
+    let ref_prefix = if by_ref { "&" } else { "" };
+    if masked {
+        cat!(ctx, "if let Some({bind_var}) = {ref_prefix}{fname} {{\n");
+        cat!(ctx +++);
+        body(ctx);
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        ctx.swap_stack(&mut old_stack);
+        return;
+    }
+    cat!(ctx, "match {ref_prefix}{fname} {{\n");
+    cat!(ctx +++);
+    let (absent, present) = match ctx.packed {
+        true => ("writer.write_bits(0, 1)", "writer.write_bits(1, 1)"),
+        false => ("writer.write_uint8(0u8)", "writer.write_uint8(1u8)"),
+    };
+    cat!(ctx, "None => {absent},\n");
+    cat!(ctx, "Some({bind_var}) => {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "{present};\n");
+
+    body(ctx);
+
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+
+    ctx.swap_stack(&mut old_stack);
+}
+
+/// Conditional fields must be present when `cond` holds, and aren't written
+/// otherwise. Unless `by_ref`, the value is copied out of the field, like
+/// array items.
+fn gen_write_impl_conditional(ctx: &mut GenCtx, by_ref: bool, cond: &str, label: &str, body: impl Fn(&mut GenCtx)) {
     let fname = fname(&ctx.stack);
     let bind_var = bindname(&ctx.stack);
     let mut old_stack = Vec::new();
     ctx.swap_stack(&mut old_stack);
     ctx.push_fname(bind_var.clone());
 
+    cat!(ctx, "if {cond} {{\n");
+    cat!(ctx +++);
     let ref_prefix = if by_ref { "&" } else { "" };
     cat!(ctx, "match {ref_prefix}{fname} {{\n");
     cat!(ctx +++);
-    cat!(ctx, "None => writer.write_uint8(0u8),\n");
+    cat!(ctx, "None => return Err(packet::Error::MissingField(\"{label}\")),\n");
     cat!(ctx, "Some({bind_var}) => {{\n");
     cat!(ctx +++);
-    cat!(ctx, "writer.write_uint8(1u8);\n");
 
     body(ctx);
 
@@ -46,19 +143,52 @@ fn gen_write_impl_optional(ctx: &mut GenCtx, by_ref: bool, body: impl Fn(&mut Ge
     cat!(ctx, "}}\n");
     cat!(ctx ---);
     cat!(ctx, "}}\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
 
     ctx.swap_stack(&mut old_stack);
 }
 
-fn gen_write_impl_array(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
+/// Fields of extensible structs are preceded by their tag and byte length.
+/// The length isn't known until the field is written, so it's patched in
+/// afterwards.
+fn gen_write_impl_tagged(ctx: &mut GenCtx, tag: u16, body: impl Fn(&mut GenCtx)) {
+    let start_var = varname(&ctx.stack, "start");
+    cat!(ctx, "writer.write_uint16({tag}u16);\n");
+    cat!(ctx, "let {start_var} = writer.position();\n");
+    cat!(ctx, "writer.write_uint32(0u32);\n");
+
+    body(ctx);
+
+    cat!(
+        ctx,
+        "writer.write_uint32_at({start_var}, (writer.position() - {start_var} - 4) as u32);\n"
+    );
+}
+
+/// `length` is the field holding the array's length, if it isn't written
+/// before the items. Unless `by_ref`, the items are copied out of the array,
+/// so that they're written by value.
+fn gen_write_impl_array(ctx: &mut GenCtx, by_ref: bool, length: Option<&str>, label: &str, body: impl Fn(&mut GenCtx)) {
     let fname = fname(&ctx.stack);
     let item_var = varname(&ctx.stack, "item");
     let mut old_stack = Vec::new();
     ctx.swap_stack(&mut old_stack);
     ctx.push_fname(item_var.clone());
 
-    cat!(ctx, "writer.write_uint32({fname}.len() as u32);\n");
-    cat!(ctx, "for {item_var} in {fname}.iter() {{\n");
+    match length {
+        Some(length) => {
+            cat!(ctx, "if {fname}.len() != {length} as usize {{\n");
+            cat!(ctx +++);
+            cat!(ctx, "return Err(packet::Error::LengthMismatch(\"{label}\"));\n");
+            cat!(ctx ---);
+            cat!(ctx, "}}\n");
+        }
+        None if ctx.packed => cat!(ctx, "writer.write_bits({fname}.len() as u32, 32);\n"),
+        None => cat!(ctx, "writer.write_uint32({fname}.len() as u32);\n"),
+    }
+    let item_pattern = if by_ref { item_var } else { format!("&{}", item_var) };
+    cat!(ctx, "for {item_pattern} in {fname}.iter() {{\n");
     cat!(ctx +++);
 
     body(ctx);
@@ -69,8 +199,64 @@ fn gen_write_impl_array(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
     ctx.swap_stack(&mut old_stack);
 }
 
-fn gen_write_impl_builtin(ctx: &mut GenCtx, type_info: &check::Builtin, type_name: &str) {
+/// Condition which holds when `value` is within `range`. It's negated by the
+/// callers rather than checking for values outside of it, so that `NaN` fails.
+fn in_range(value: &str, range: &check::Range, type_info: &check::Builtin) -> String {
+    let suffix = match type_info {
+        check::Builtin::String => "usize",
+        check::Builtin::Uint8 => "u8",
+        check::Builtin::Uint16 => "u16",
+        check::Builtin::Uint32 => "u32",
+        check::Builtin::Int8 => "i8",
+        check::Builtin::Int16 => "i16",
+        check::Builtin::Int32 => "i32",
+        check::Builtin::Float => "f32",
+    };
+    // the comparison methods auto-deref, so this also works for references
+    let mut conds = Vec::new();
+    if let Some(min) = range.min {
+        conds.push(format!("{}.ge(&{}_{})", value, min, suffix));
+    }
+    if let Some(max) = range.max {
+        let op = if range.inclusive { "le" } else { "lt" };
+        conds.push(format!("{}.{}(&{}_{})", value, op, max, suffix));
+    }
+    conds.join(" && ")
+}
+
+fn gen_range_check(
+    ctx: &mut GenCtx,
+    value: &str,
+    range: Option<&check::Range>,
+    type_info: &check::Builtin,
+    label: &str,
+) {
+    if let Some(range) = range {
+        let cond = in_range(value, range, type_info);
+        cat!(ctx, "if !({cond}) {{\n");
+        cat!(ctx +++);
+        cat!(ctx, "return Err(packet::Error::OutOfRange(\"{label}\"));\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
+}
+
+fn gen_write_impl_builtin(
+    ctx: &mut GenCtx,
+    type_info: &check::Builtin,
+    type_name: &str,
+    range: Option<&check::Range>,
+    label: &str,
+) {
     let fname = fname(&ctx.stack);
+    let value = match type_info {
+        check::Builtin::String => format!("{}.len()", fname),
+        _ => fname.clone(),
+    };
+    gen_range_check(ctx, &value, range, type_info, label);
+    if ctx.packed {
+        return gen_write_packed_builtin(ctx, type_info, range);
+    }
     match type_info {
         check::Builtin::String => {
             cat!(ctx, "writer.write_uint32({fname}.len() as u32);\n");
@@ -87,72 +273,190 @@ fn gen_write_impl_enum(ctx: &mut GenCtx, type_info: &check::Enum, _: &str) {
         check::EnumRepr::U32 => "uint32",
     };
     let fname = fname(&ctx.stack);
+    if ctx.packed {
+        let bits = type_info.packed_bits();
+        cat!(
+            ctx,
+            "writer.write_bits(({fname}.to_owned() as u32).trailing_zeros(), {bits});\n"
+        );
+        return;
+    }
     cat!(ctx, "writer.write_{repr_name}({fname} as {type_info.repr});\n");
 }
 
-fn gen_write_impl_struct(ctx: &mut GenCtx, ty: &check::Struct, _: &str) {
-    for f in &ty.fields {
-        ctx.push_fname(f.name);
-        let fty = &*f.r#type.borrow();
+/// Writes a value to the bit stream of a packed export. Integers and string
+/// lengths with both bounds of their range are written as their offset from
+/// the lower one, in as many bits as the range needs.
+fn gen_write_packed_builtin(ctx: &mut GenCtx, type_info: &check::Builtin, range: Option<&check::Range>) {
+    let fname = fname(&ctx.stack);
+    let packed = range.and_then(|range| range.packed_bits());
+    // `to_owned` copies the value, whether or not it is behind a reference
+    let (value, width) = match type_info {
+        check::Builtin::Float => {
+            cat!(ctx, "writer.write_bits_float({fname});\n");
+            return;
+        }
+        check::Builtin::String => (format!("{}.len()", fname), 32),
+        _ => (format!("{}.to_owned()", fname), packed_width(type_info)),
+    };
+    match packed {
+        Some((min, bits)) if min != 0 => {
+            let offset = offset_from(&format!("{} as i64", value), min);
+            cat!(ctx, "writer.write_bits(({offset}) as u32, {bits});\n");
+        }
+        Some((_, bits)) => cat!(ctx, "writer.write_bits({value} as u32, {bits});\n"),
+        None => cat!(ctx, "writer.write_bits({value} as u32, {width});\n"),
+    }
+    if let check::Builtin::String = type_info {
+        cat!(ctx, "writer.write_bits_string(&{fname});\n");
+    }
+}
 
-        use check::ResolvedType::*;
-        let mut by_ref = false;
-        let mut generator: Box<dyn Fn(&mut GenCtx)> = match &fty.1 {
-            Builtin(fty_info) => Box::new(move |ctx| gen_write_impl_builtin(ctx, &fty_info, &fty.0)),
-            Enum(fty_info) => Box::new(move |ctx| gen_write_impl_enum(ctx, &fty_info, &fty.0)),
-            Struct(fty_info) => {
-                by_ref = true;
-                Box::new(move |ctx| gen_write_impl_struct(ctx, &fty_info, &fty.0))
-            }
-        };
-        if f.array {
-            generator = Box::new(move |ctx| gen_write_impl_array(ctx, |ctx| generator(ctx)))
+/// The expression a length-referencing array reads its length from
+fn length_field(ctx: &GenCtx, ty: &check::Struct, field: &check::StructField) -> Option<String> {
+    field.length.map(|length| {
+        let length = ty.fields.iter().find(|f| f.name == length).unwrap();
+        format!("{}.{}", fname(&ctx.stack), length.ident())
+    })
+}
+
+/// The expression which holds when a conditional field is present
+fn condition(ctx: &GenCtx, ty: &check::Struct, field: &check::StructField) -> Option<String> {
+    field.condition.as_ref().map(|cond| {
+        let cond_field = ty.fields.iter().find(|f| f.name == cond.field).unwrap();
+        format!(
+            "{}.{} == {}::{}",
+            fname(&ctx.stack),
+            cond_field.ident(),
+            cond.r#enum,
+            cond.variant
+        )
+    })
+}
+
+/// Writes the presence of the struct's optional fields as a bitmask, one byte
+/// per 8 fields
+fn gen_write_impl_bitmask(ctx: &mut GenCtx, ty: &check::Struct) {
+    let parent = fname(&ctx.stack);
+    for chunk in ty.bitmask_fields().chunks(8) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, f)| match i {
+                0 => format!("{}.{}.is_some() as u8", parent, f.ident()),
+                _ => format!("({}.{}.is_some() as u8) << {}", parent, f.ident(), i),
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let len = chunk.len();
+        match ctx.packed {
+            true => cat!(ctx, "writer.write_bits(u32::from({bits}), {len});\n"),
+            false => cat!(ctx, "writer.write_uint8({bits});\n"),
+        }
+    }
+}
+
+/// Writes the field `f` of the struct `ty`, which is called `name`
+fn gen_write_impl_field(ctx: &mut GenCtx, ty: &check::Struct, f: &check::StructField, name: &str, masked: bool) {
+    let length = length_field(ctx, ty, f);
+    let cond = condition(ctx, ty, f);
+    ctx.push_fname(f.ident());
+    let fty = &*f.r#type.borrow();
+    let label = format!("{}.{}", name, f.name);
+
+    use check::ResolvedType::*;
+    let mut by_ref = false;
+    let mut generator: Box<dyn Fn(&mut GenCtx)> = match &fty.1 {
+        Builtin(fty_info) => {
+            // strings can't be moved out of the input
+            by_ref = *fty_info == check::Builtin::String;
+            Box::new(move |ctx| gen_write_impl_builtin(ctx, fty_info, fty.0, f.range.as_ref(), &label))
         }
-        if f.optional {
-            generator = Box::new(move |ctx| gen_write_impl_optional(ctx, by_ref, |ctx| generator(ctx)))
+        Enum(fty_info) => Box::new(move |ctx| gen_write_impl_enum(ctx, fty_info, fty.0)),
+        Struct(fty_info) => {
+            by_ref = true;
+            Box::new(move |ctx| gen_write_impl_struct(ctx, fty_info, fty.0))
         }
-        generator(ctx);
+    };
+    if f.array {
+        let label = format!("{}.{}", name, f.name);
+        generator =
+            Box::new(move |ctx| gen_write_impl_array(ctx, by_ref, length.as_deref(), &label, |ctx| generator(ctx)))
+    }
+    if let Some(tag) = f.tag {
+        generator = Box::new(move |ctx| gen_write_impl_tagged(ctx, tag, |ctx| generator(ctx)))
+    }
+    if f.optional {
+        generator = Box::new(move |ctx| gen_write_impl_optional(ctx, by_ref, masked, |ctx| generator(ctx)))
+    }
+    if let Some(cond) = cond {
+        let label = format!("{}.{}", name, f.name);
+        // arrays can't be moved out of the input either
+        let by_ref = by_ref || f.array;
+        generator = Box::new(move |ctx| gen_write_impl_conditional(ctx, by_ref, &cond, &label, |ctx| generator(ctx)))
+    }
+    generator(ctx);
+
+    ctx.pop_fname();
+}
 
-        ctx.pop_fname();
+fn gen_write_impl_struct(ctx: &mut GenCtx, ty: &check::Struct, name: &str) {
+    gen_write_impl_bitmask(ctx, ty);
+    // extensible structs omit absent fields instead
+    let masked = !ty.bitmask_fields().is_empty() || ty.is_extensible();
+    for f in &ty.fields {
+        gen_write_impl_field(ctx, ty, f, name, masked);
+    }
+    if ty.is_extensible() {
+        cat!(ctx, "writer.write_uint16(0u16);\n");
     }
 }
 
-impl<'a> WriteImpl<Rust> for check::Export<'a> {
-    fn gen_write_impl(&self, _: &mut Rust, name: &str, out: &mut String) {
-        let mut ctx = GenCtx::new(out);
-        ctx.push_fname("input");
-        cat!(
-            ctx,
-            "pub fn write(writer: &mut packet::writer::Writer, input: &{name}) {{\n"
-        );
-        cat!(ctx +++);
-        gen_write_impl_struct(&mut ctx, &self.r#struct, &name);
-        cat!(ctx ---);
-        cat!(ctx, "}}\n");
+fn gen_write_impl(ctx: &mut GenCtx, export: &check::Export) {
+    ctx.push_fname("input");
+    cat!(
+        ctx,
+        "pub fn write(writer: &mut packet::writer::Writer, input: &{export.name}) -> Result<(), packet::Error> {{\n"
+    );
+    cat!(ctx +++);
+    gen_write_impl_struct(ctx, &export.r#struct, export.name);
+    if ctx.packed {
+        // pads the last byte of the bit stream
+        cat!(ctx, "writer.flush_bits();\n");
     }
+    cat!(ctx, "Ok(())\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    ctx.pop_fname();
 }
 
-fn gen_read_impl_optional(ctx: &mut GenCtx, type_name: &str, body: impl Fn(&mut GenCtx)) {
+/// The field is read when `present` holds, which for optional fields is the
+/// presence byte. Without it, the field is known to be present.
+fn gen_read_impl_optional(ctx: &mut GenCtx, type_name: &str, present: Option<&str>, body: impl Fn(&mut GenCtx)) {
     let fname = self::fname(&ctx.stack);
     let bind_var = bindname(&ctx.stack);
     let mut old_stack = Vec::new();
     ctx.swap_stack(&mut old_stack);
     ctx.push_fname(bind_var.clone());
 
-    cat!(ctx, "if reader.read_uint8()? > 0 {{\n");
-    cat!(ctx +++);
+    if let Some(present) = present {
+        cat!(ctx, "if {present} {{\n");
+        cat!(ctx +++);
+    }
     cat!(ctx, "let mut {bind_var} = {type_name}::default();\n");
 
     body(ctx);
 
     cat!(ctx, "{fname} = Some({bind_var});\n");
-    cat!(ctx ---);
-    cat!(ctx, "}}\n");
+    if present.is_some() {
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
 
     ctx.swap_stack(&mut old_stack);
 }
 
-fn gen_read_impl_array(ctx: &mut GenCtx, type_name: &str, body: impl Fn(&mut GenCtx)) {
+fn gen_read_impl_array(ctx: &mut GenCtx, type_name: &str, length: Option<&str>, body: impl Fn(&mut GenCtx)) {
     let len_var = varname(&ctx.stack, "len");
     let fname = fname(&ctx.stack);
     let item_var = varname(&ctx.stack, "item");
@@ -160,7 +464,11 @@ fn gen_read_impl_array(ctx: &mut GenCtx, type_name: &str, body: impl Fn(&mut Gen
     ctx.swap_stack(&mut old_stack);
     ctx.push_fname(item_var.clone());
 
-    cat!(ctx, "let {len_var} = reader.read_uint32()? as usize;\n");
+    match length {
+        Some(length) => cat!(ctx, "let {len_var} = {length} as usize;\n"),
+        None if ctx.packed => cat!(ctx, "let {len_var} = reader.read_bits(32)? as usize;\n"),
+        None => cat!(ctx, "let {len_var} = reader.read_uint32()? as usize;\n"),
+    }
     cat!(ctx, "{fname}.reserve({len_var});\n");
     cat!(ctx, "for _ in 0..{len_var} {{\n");
     cat!(ctx +++);
@@ -175,16 +483,28 @@ fn gen_read_impl_array(ctx: &mut GenCtx, type_name: &str, body: impl Fn(&mut Gen
     ctx.swap_stack(&mut old_stack);
 }
 
-fn gen_read_impl_builtin(ctx: &mut GenCtx, type_info: &check::Builtin, type_name: &str) {
+fn gen_read_impl_builtin(
+    ctx: &mut GenCtx,
+    type_info: &check::Builtin,
+    type_name: &str,
+    range: Option<&check::Range>,
+    label: &str,
+) {
     let fname = fname(&ctx.stack);
+    if ctx.packed {
+        return gen_read_packed_builtin(ctx, type_info, type_name, range, label);
+    }
     match type_info {
         check::Builtin::String => {
             let len_var = varname(&ctx.stack, "len");
             cat!(ctx, "let {len_var} = reader.read_uint32()? as usize;\n");
+            // checked before reading, so that a huge string isn't allocated
+            gen_range_check(ctx, &len_var, range, type_info, label);
             cat!(ctx, "{fname} = reader.read_string({len_var})?;\n");
         }
         _ => {
-            cat!(ctx, "{fname} = reader.read_{type_name}()?;\n")
+            cat!(ctx, "{fname} = reader.read_{type_name}()?;\n");
+            gen_range_check(ctx, &fname, range, type_info, label);
         }
     }
 }
@@ -196,9 +516,65 @@ fn gen_read_impl_enum(ctx: &mut GenCtx, type_info: &check::Enum, type_name: &str
         check::EnumRepr::U32 => "uint32",
     };
     let fname = fname(&ctx.stack);
+    if ctx.packed {
+        // the index of the variant, which can't be out of bounds of the shift
+        let bits = type_info.packed_bits();
+        cat!(
+            ctx,
+            "{fname} = {type_name}::try_from((1u32 << reader.read_bits({bits})?) as {type_info.repr})?;\n"
+        );
+        return;
+    }
     cat!(ctx, "{fname} = {type_name}::try_from(reader.read_{repr_name}()?)?;\n");
 }
 
+/// Reads a value from the bit stream of a packed export. Values which are
+/// out of range after adding the lower bound of it fail the range check.
+fn gen_read_packed_builtin(
+    ctx: &mut GenCtx,
+    type_info: &check::Builtin,
+    type_name: &str,
+    range: Option<&check::Range>,
+    label: &str,
+) {
+    let fname = fname(&ctx.stack);
+    let packed = range.and_then(|range| range.packed_bits());
+    let read = |width| match packed {
+        Some((min, bits)) if min != 0 => {
+            format!(
+                "({})",
+                offset_from(&format!("reader.read_bits({})? as i64", bits), -min)
+            )
+        }
+        Some((_, bits)) => format!("reader.read_bits({})?", bits),
+        None => format!("reader.read_bits({})?", width),
+    };
+    match type_info {
+        check::Builtin::Float => cat!(ctx, "{fname} = reader.read_bits_float()?;\n"),
+        check::Builtin::String => {
+            let len_var = varname(&ctx.stack, "len");
+            let len = read(32);
+            cat!(ctx, "let {len_var} = {len} as usize;\n");
+            // checked before reading, so that a huge string isn't allocated
+            gen_range_check(ctx, &len_var, range, type_info, label);
+            cat!(ctx, "{fname} = reader.read_bits_string({len_var})?;\n");
+        }
+        _ => {
+            let width = packed_width(type_info);
+            let rust_type = resolve_typename(type_name).unwrap();
+            // signed values are sign extended from their width
+            let value = match (packed, type_info) {
+                (None, check::Builtin::Int8) => format!("{} as u8 as i8", read(width)),
+                (None, check::Builtin::Int16) => format!("{} as u16 as i16", read(width)),
+                (None, check::Builtin::Uint32) => read(width),
+                _ => format!("{} as {}", read(width), rust_type),
+            };
+            cat!(ctx, "{fname} = {value};\n");
+            gen_range_check(ctx, &fname, range, type_info, label);
+        }
+    }
+}
+
 fn resolve_typename(type_name: &str) -> Option<&'static str> {
     match type_name {
         "uint8" => Some("u8"),
@@ -213,274 +589,1842 @@ fn resolve_typename(type_name: &str) -> Option<&'static str> {
     }
 }
 
-fn gen_read_impl_struct(ctx: &mut GenCtx, ty: &check::Struct, _name: &str) {
-    for f in &ty.fields {
-        ctx.push_fname(f.name);
-        let fty = &*f.r#type.borrow();
+/// Fields of extensible structs are read in any order, each in the match arm
+/// of its tag
+fn gen_read_impl_tagged(ctx: &mut GenCtx, tag: u16, body: impl Fn(&mut GenCtx)) {
+    cat!(ctx, "{tag} => {{\n");
+    cat!(ctx +++);
 
-        use check::ResolvedType::*;
-        let tyname = resolve_typename(fty.0).unwrap_or(fty.0);
-        let mut generator: Box<dyn Fn(&mut GenCtx)> = match &fty.1 {
-            Builtin(fty_info) => Box::new(move |ctx| gen_read_impl_builtin(ctx, &fty_info, fty.0)),
-            Enum(fty_info) => Box::new(move |ctx| gen_read_impl_enum(ctx, &fty_info, fty.0)),
-            Struct(fty_info) => Box::new(move |ctx| gen_read_impl_struct(ctx, &fty_info, fty.0)),
-        };
-        if f.array {
-            generator = Box::new(move |ctx| gen_read_impl_array(ctx, tyname, |ctx| generator(ctx)))
-        }
-        if f.optional {
-            generator = Box::new(move |ctx| gen_read_impl_optional(ctx, tyname, |ctx| generator(ctx)))
-        }
-        generator(ctx);
-        ctx.pop_fname();
-    }
+    body(ctx);
+
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
 }
 
-impl<'a> ReadImpl<Rust> for check::Export<'a> {
-    fn gen_read_impl(&self, _: &mut Rust, name: &str, out: &mut String) {
-        let mut ctx = GenCtx::new(out);
-        ctx.push_fname("output");
-        cat!(
-            ctx,
-            "pub fn read(reader: &mut packet::reader::Reader, output: &mut {name}) -> Result<(), packet::Error> {{\n"
-        );
-        cat!(ctx +++);
-        gen_read_impl_struct(&mut ctx, &self.r#struct, &name);
-        cat!(ctx, "Ok(())\n");
-        cat!(ctx ---);
-        cat!(ctx, "}}\n");
+/// Reads the bitmask of the struct's optional fields, returning the condition
+/// which holds when each of them is present
+fn gen_read_impl_bitmask(ctx: &mut GenCtx, ty: &check::Struct) -> Vec<String> {
+    let fields = ty.bitmask_fields();
+    if fields.is_empty() {
+        return Vec::new();
     }
+    let mask_var = varname(&ctx.stack, "presence");
+    let bytes = fields
+        .chunks(8)
+        .map(|chunk| match ctx.packed {
+            true => format!("reader.read_bits({})?", chunk.len()),
+            false => "reader.read_uint8()?".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    cat!(ctx, "let {mask_var} = [{bytes}];\n");
+    (0..fields.len())
+        .map(|i| format!("({}[{}] & {}) != 0", mask_var, i / 8, 1 << (i % 8)))
+        .collect()
 }
 
-fn struct_field_typename(base: &str, array: bool, optional: bool) -> String {
-    format_f!(
-        "{preopt}{prearr}{base}{postarr}{postopt}",
-        preopt = if optional { "Option<" } else { "" },
-        prearr = if array { "Vec<" } else { "" },
-        postarr = if array { ">" } else { "" },
-        postopt = if optional { ">" } else { "" }
-    )
-}
+/// Reads the field `f` of the struct `ty`, which is called `name`. The
+/// presence of optional fields is taken from `presence` if it's masked.
+fn gen_read_impl_field(
+    ctx: &mut GenCtx,
+    ty: &check::Struct,
+    f: &check::StructField,
+    name: &str,
+    presence: &mut dyn Iterator<Item = String>,
+) {
+    let length = length_field(ctx, ty, f);
+    let cond = condition(ctx, ty, f);
+    ctx.push_fname(f.ident());
+    let fty = &*f.r#type.borrow();
+    let label = format!("{}.{}", name, f.name);
 
-impl<'a> Definition<Rust> for check::Struct<'a> {
-    fn gen_def(&self, _: &mut Rust, name: &str, out: &mut String) {
-        let mut ctx = GenCtx::new(out);
+    use check::ResolvedType::*;
+    let tyname = resolve_typename(fty.0).unwrap_or(fty.0);
+    let mut generator: Box<dyn Fn(&mut GenCtx)> = match &fty.1 {
+        Builtin(fty_info) => Box::new(move |ctx| gen_read_impl_builtin(ctx, fty_info, fty.0, f.range.as_ref(), &label)),
+        Enum(fty_info) => Box::new(move |ctx| gen_read_impl_enum(ctx, fty_info, fty.0)),
+        Struct(fty_info) => Box::new(move |ctx| gen_read_impl_struct(ctx, fty_info, fty.0)),
+    };
+    if f.array {
+        generator = Box::new(move |ctx| gen_read_impl_array(ctx, tyname, length.as_deref(), |ctx| generator(ctx)))
+    }
+    if f.optional {
+        let present = match f.tag {
+            Some(_) => None,
+            None => Some(presence.next().unwrap_or_else(|| match ctx.packed {
+                true => "reader.read_bits(1)? != 0".to_string(),
+                false => "reader.read_uint8()? > 0".to_string(),
+            })),
+        };
+        generator = Box::new(move |ctx| gen_read_impl_optional(ctx, tyname, present.as_deref(), |ctx| generator(ctx)))
+    }
+    if let Some(cond) = cond {
+        generator = Box::new(move |ctx| gen_read_impl_optional(ctx, tyname, Some(&cond), |ctx| generator(ctx)))
+    }
+    if let Some(tag) = f.tag {
+        generator = Box::new(move |ctx| gen_read_impl_tagged(ctx, tag, |ctx| generator(ctx)))
+    }
+    generator(ctx);
+    ctx.pop_fname();
+}
 
-        cat!(ctx, "#[derive(Clone, PartialEq, Debug, Default)]\n");
-        cat!(ctx, "pub struct {name} {{\n");
+fn gen_read_impl_struct(ctx: &mut GenCtx, ty: &check::Struct, name: &str) {
+    let mut presence = gen_read_impl_bitmask(ctx, ty).into_iter();
+    // fields of extensible structs are read until the terminating tag, and
+    // the ones which aren't known are skipped
+    let tag_var = varname(&ctx.stack, "tag");
+    let len_var = varname(&ctx.stack, "len");
+    if ty.is_extensible() {
+        cat!(ctx, "loop {{\n");
         cat!(ctx +++);
-        for field in self.fields.iter() {
-            let type_info = &*field.r#type.borrow();
-            let mut typename: &str = &type_info.0;
-            if let check::ResolvedType::Builtin(b) = &type_info.1 {
-                typename = match b {
-                    check::Builtin::Uint8 => "u8",
-                    check::Builtin::Uint16 => "u16",
-                    check::Builtin::Uint32 => "u32",
-                    check::Builtin::Int8 => "i8",
-                    check::Builtin::Int16 => "i16",
-                    check::Builtin::Int32 => "i32",
-                    check::Builtin::Float => "f32",
-                    check::Builtin::String => "String",
-                };
-            }
-            let sftyname = struct_field_typename(typename, field.array, field.optional);
-            cat!(ctx, "pub {field.name}: {sftyname},\n");
-        }
+        cat!(ctx, "let {tag_var} = reader.read_uint16()?;\n");
+        cat!(ctx, "if {tag_var} == 0 {{\n");
+        cat!(ctx +++);
+        cat!(ctx, "break;\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        cat!(ctx, "let {len_var} = reader.read_uint32()? as usize;\n");
+        cat!(ctx, "match {tag_var} {{\n");
+        cat!(ctx +++);
+    }
+    for f in &ty.fields {
+        gen_read_impl_field(ctx, ty, f, name, &mut presence);
+    }
+    if ty.is_extensible() {
+        cat!(ctx, "_ => reader.skip({len_var})?,\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
         cat!(ctx ---);
         cat!(ctx, "}}\n");
     }
 }
 
-fn gen_def_enum_default_impl<'a>(ctx: &mut GenCtx, name: &str, ty: &check::Enum<'a>) {
-    let first_variant = ty.variants.first().unwrap().name;
-
-    cat!(ctx, "impl Default for {name} {{\n");
-    cat!(ctx +++);
-    cat!(ctx, "fn default() -> Self {{\n");
+fn gen_read_impl(ctx: &mut GenCtx, export: &check::Export) {
+    ctx.push_fname("output");
+    cat!(
+        ctx,
+        "pub fn read(reader: &mut packet::reader::Reader, output: &mut {export.name}) -> Result<(), packet::Error> {{\n"
+    );
     cat!(ctx +++);
-    cat!(ctx, "{name}::{first_variant}\n");
+    gen_read_impl_struct(ctx, &export.r#struct, export.name);
+    cat!(ctx, "Ok(())\n");
     cat!(ctx ---);
     cat!(ctx, "}}\n");
+    ctx.pop_fname();
+}
+
+/// Fields of a struct which are delta encoded themselves, rather than being
+/// written in full when they change. Extensible structs are always written
+/// in full, as their fields can only be read in a tagged loop.
+fn delta_struct<'a, 'b>(
+    ty: &'b (&str, check::ResolvedType<'a>),
+    f: &check::StructField,
+) -> Option<&'b check::Struct<'a>> {
+    match &ty.1 {
+        check::ResolvedType::Struct(s) if !f.is_optional() && !s.is_extensible() => Some(s),
+        _ => None,
+    }
+}
+
+/// Writes which of the struct's fields differ from `baseline`, as a bitmask
+/// with one byte per 8 fields. Conditional fields also count as changed when
+/// their condition field did, so that they're written whenever they appear.
+/// Returns the condition which holds when each of them changed.
+fn gen_write_delta_mask(ctx: &mut GenCtx, ty: &check::Struct, baseline: &str) -> Vec<String> {
+    let parent = fname(&ctx.stack);
+    let mask_var = varname(&ctx.stack, "changed");
+    cat!(ctx, "let {mask_var} = [\n");
+    cat!(ctx +++);
+    for chunk in ty.fields.chunks(8) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let mut changed = format!("{}.{} != {}.{}", parent, f.ident(), baseline, f.ident());
+                if let Some(cond) = &f.condition {
+                    let cond_field = ty.fields.iter().find(|c| c.name == cond.field).unwrap();
+                    let ident = cond_field.ident();
+                    changed = format!("{} || {}.{} != {}.{}", changed, parent, ident, baseline, ident);
+                }
+                let changed = format!("({}) as u8", changed);
+                match i {
+                    0 => changed,
+                    _ => format!("({}) << {}", changed, i),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+        cat!(ctx, "{bits},\n");
+    }
     cat!(ctx ---);
-    cat!(ctx, "}}\n");
+    cat!(ctx, "];\n");
+    for i in 0..ty.fields.len().div_ceil(8) {
+        cat!(ctx, "writer.write_uint8({mask_var}[{i}]);\n");
+    }
+    (0..ty.fields.len())
+        .map(|i| format!("({}[{}] & {}) != 0", mask_var, i / 8, 1 << (i % 8)))
+        .collect()
 }
 
-fn gen_def_enum_tryfrom_impl<'a>(ctx: &mut GenCtx, name: &str, ty: &check::Enum<'a>) {
-    cat!(ctx, "impl std::convert::TryFrom<{ty.repr}> for {name} {{\n");
+/// Items which have one at the same index in the baseline are delta encoded
+/// against it, and the rest are written in full
+fn gen_write_delta_array(
+    ctx: &mut GenCtx,
+    ty: &check::Struct,
+    f: &check::StructField,
+    (item_name, item_ty): (&str, &check::Struct),
+    label: &str,
+    baseline: &str,
+) {
+    let length = length_field(ctx, ty, f);
+    ctx.push_fname(f.ident());
+    let fname = fname(&ctx.stack);
+    let index_var = varname(&ctx.stack, "index");
+    let item_var = varname(&ctx.stack, "item");
+    let baseline_var = varname(&ctx.stack, "baseline");
+    let mut old_stack = vec![item_var.clone()];
+    ctx.swap_stack(&mut old_stack);
+
+    match length {
+        Some(length) => {
+            cat!(ctx, "if {fname}.len() != {length} as usize {{\n");
+            cat!(ctx +++);
+            cat!(ctx, "return Err(packet::Error::LengthMismatch(\"{label}\"));\n");
+            cat!(ctx ---);
+            cat!(ctx, "}}\n");
+        }
+        None => cat!(ctx, "writer.write_uint32({fname}.len() as u32);\n"),
+    }
+    cat!(ctx, "for ({index_var}, {item_var}) in {fname}.iter().enumerate() {{\n");
     cat!(ctx +++);
-    cat!(ctx, "type Error = packet::Error;\n");
-    cat!(ctx, "fn try_from(value: {ty.repr}) -> Result<Self, Self::Error> {{\n");
+    let ident = f.ident();
+    cat!(ctx, "match {baseline}.{ident}.get({index_var}) {{\n");
     cat!(ctx +++);
-    cat!(ctx, "match value {{\n");
+    cat!(ctx, "Some({baseline_var}) => {{\n");
     cat!(ctx +++);
-    for variant in &ty.variants {
-        let value = 1 << variant.value;
-        cat!(ctx, "{value} => Ok({name}::{variant.name}),\n");
-    }
-    cat!(
-        ctx,
-        "_ => Err(packet::Error::InvalidEnumValue(value as usize, \"{name}\"))\n"
-    );
+    gen_write_delta_struct(ctx, item_ty, item_name, &baseline_var);
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx, "None => {{\n");
+    cat!(ctx +++);
+    gen_write_impl_struct(ctx, item_ty, item_name);
     cat!(ctx ---);
     cat!(ctx, "}}\n");
     cat!(ctx ---);
     cat!(ctx, "}}\n");
     cat!(ctx ---);
     cat!(ctx, "}}\n");
-}
-
-impl<'a> Definition<Rust> for check::Enum<'a> {
-    fn gen_def(&self, _: &mut Rust, name: &str, out: &mut String) {
-        let mut ctx = GenCtx::new(out);
 
-        let repr = &self.repr;
+    ctx.swap_stack(&mut old_stack);
+    ctx.pop_fname();
+}
 
-        cat!(ctx, "#[derive(Clone, Copy, PartialEq, Debug)]\n");
-        cat!(ctx, "#[repr({repr})]\n");
-        cat!(ctx, "pub enum {name} {{\n");
+/// Writes the fields which differ from `baseline`, the expression holding
+/// the struct's previous value
+fn gen_write_delta_struct(ctx: &mut GenCtx, ty: &check::Struct, name: &str, baseline: &str) {
+    if ty.fields.is_empty() {
+        return;
+    }
+    let changed = gen_write_delta_mask(ctx, ty, baseline);
+    for (f, changed) in ty.fields.iter().zip(changed) {
+        cat!(ctx, "if {changed} {{\n");
         cat!(ctx +++);
-        for variant in self.variants.iter() {
-            cat!(ctx, "{variant.name} = 1 << {variant.value},\n");
+        let fty = &*f.r#type.borrow();
+        match delta_struct(fty, f) {
+            Some(s) if f.array => {
+                let label = format!("{}.{}", name, f.name);
+                gen_write_delta_array(ctx, ty, f, (fty.0, s), &label, baseline)
+            }
+            Some(s) => {
+                ctx.push_fname(f.ident());
+                gen_write_delta_struct(ctx, s, fty.0, &format!("{}.{}", baseline, f.ident()));
+                ctx.pop_fname();
+            }
+            None => gen_write_impl_field(ctx, ty, f, name, false),
         }
         cat!(ctx ---);
         cat!(ctx, "}}\n");
-        gen_def_enum_default_impl(&mut ctx, name, &self);
-        gen_def_enum_tryfrom_impl(&mut ctx, name, &self);
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use pretty_assertions::assert_eq;
+fn gen_write_delta_impl(ctx: &mut GenCtx, export: &check::Export) {
+    ctx.push_fname("input");
+    cat!(
+        ctx,
+        "pub fn write_delta(writer: &mut packet::writer::Writer, baseline: &{export.name}, input: &{export.name}) -> Result<(), packet::Error> {{\n"
+    );
+    cat!(ctx +++);
+    gen_write_delta_struct(ctx, &export.r#struct, export.name, "baseline");
+    cat!(ctx, "Ok(())\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    ctx.pop_fname();
+}
 
-    use super::*;
+/// Items which were already in the baseline are read as a delta from it, and
+/// the new ones in full. The length comes from the wire, so the array only
+/// grows by the items which were actually read, rather than being resized to
+/// it up front.
+fn gen_read_delta_array(
+    ctx: &mut GenCtx,
+    ty: &check::Struct,
+    f: &check::StructField,
+    (item_name, item_ty): (&str, &check::Struct),
+) {
+    let length = length_field(ctx, ty, f);
+    ctx.push_fname(f.ident());
+    let fname = fname(&ctx.stack);
+    let len_var = varname(&ctx.stack, "len");
+    let index_var = varname(&ctx.stack, "index");
+    let item_var = varname(&ctx.stack, "item");
+    let mut old_stack = vec![item_var.clone()];
+    ctx.swap_stack(&mut old_stack);
 
-    #[test]
+    match length {
+        Some(length) => cat!(ctx, "let {len_var} = {length} as usize;\n"),
+        None => cat!(ctx, "let {len_var} = reader.read_uint32()? as usize;\n"),
+    }
+    cat!(ctx, "{fname}.truncate({len_var});\n");
+    cat!(ctx, "for {index_var} in 0..{len_var} {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "match {fname}.get_mut({index_var}) {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "Some({item_var}) => {{\n");
+    cat!(ctx +++);
+    gen_read_delta_struct(ctx, item_ty, item_name);
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx, "None => {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "let mut {item_var} = {item_name}::default();\n");
+    gen_read_impl_struct(ctx, item_ty, item_name);
+    cat!(ctx, "{fname}.push({item_var});\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+
+    ctx.swap_stack(&mut old_stack);
+    ctx.pop_fname();
+}
+
+/// Reads the fields which changed into the struct, which holds the baseline
+fn gen_read_delta_struct(ctx: &mut GenCtx, ty: &check::Struct, name: &str) {
+    if ty.fields.is_empty() {
+        return;
+    }
+    let parent = fname(&ctx.stack);
+    let mask_var = varname(&ctx.stack, "changed");
+    let bytes = vec!["reader.read_uint8()?"; ty.fields.len().div_ceil(8)].join(", ");
+    cat!(ctx, "let {mask_var} = [{bytes}];\n");
+    for (i, f) in ty.fields.iter().enumerate() {
+        let (byte, bit) = (i / 8, 1 << (i % 8));
+        cat!(ctx, "if ({mask_var}[{byte}] & {bit}) != 0 {{\n");
+        cat!(ctx +++);
+        let fty = &*f.r#type.borrow();
+        match delta_struct(fty, f) {
+            Some(s) if f.array => gen_read_delta_array(ctx, ty, f, (fty.0, s)),
+            Some(s) => {
+                ctx.push_fname(f.ident());
+                gen_read_delta_struct(ctx, s, fty.0);
+                ctx.pop_fname();
+            }
+            None => {
+                // the field still holds the baseline, which reading appends to,
+                // or which is only overwritten by the fields that are present
+                let ident = f.ident();
+                if f.array {
+                    cat!(ctx, "{parent}.{ident}.clear();\n");
+                } else if f.is_optional() {
+                    cat!(ctx, "{parent}.{ident} = None;\n");
+                } else if let check::ResolvedType::Struct(_) = fty.1 {
+                    cat!(ctx, "{parent}.{ident} = {fty.0}::default();\n");
+                }
+                gen_read_impl_field(ctx, ty, f, name, &mut std::iter::empty());
+            }
+        }
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
+}
+
+fn gen_read_delta_impl(ctx: &mut GenCtx, export: &check::Export) {
+    ctx.push_fname("output");
+    cat!(
+        ctx,
+        "pub fn read_delta(reader: &mut packet::reader::Reader, baseline: &{export.name}, output: &mut {export.name}) -> Result<(), packet::Error> {{\n"
+    );
+    cat!(ctx +++);
+    cat!(ctx, "*output = baseline.clone();\n");
+    gen_read_delta_struct(ctx, &export.r#struct, export.name);
+    cat!(ctx, "Ok(())\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    ctx.pop_fname();
+}
+
+impl Fingerprint for Rust {
+    fn gen_fingerprint(&self, fingerprint: u32, out: &mut String) {
+        let ctx = GenCtx::new(out);
+
+        cat!(ctx, "/// Differs between schemas which aren't wire-compatible\n");
+        cat!(ctx, "pub const SCHEMA_FINGERPRINT: u32 = {fingerprint:#010x};\n");
+    }
+}
+
+impl Impl for Rust {
+    fn gen_impl(&self, export: &check::Export, out: &mut String) {
+        let mut ctx = GenCtx::new(out);
+        ctx.packed = export.r#struct.is_packed();
+
+        cat!(ctx, "impl {export.name} {{\n");
+        cat!(ctx +++);
+        gen_read_impl(&mut ctx, export);
+        gen_write_impl(&mut ctx, export);
+        if export.r#struct.is_delta() {
+            gen_read_delta_impl(&mut ctx, export);
+            gen_write_delta_impl(&mut ctx, export);
+        }
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
+}
+
+impl Message for Rust {
+    fn gen_message(&self, exports: &[check::Export], out: &mut String) {
+        let mut ctx = GenCtx::new(out);
+
+        cat!(ctx, "#[derive(Clone, PartialEq, Debug)]\n");
+        cat!(ctx, "pub enum Message {{\n");
+        cat!(ctx +++);
+        for export in exports.iter() {
+            cat!(ctx, "{export.name}({export.name}),\n");
+        }
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+
+        cat!(ctx, "impl Message {{\n");
+        cat!(ctx +++);
+        cat!(ctx, "pub fn id(&self) -> u8 {{\n");
+        cat!(ctx +++);
+        cat!(ctx, "match self {{\n");
+        cat!(ctx +++);
+        for export in exports.iter() {
+            let id = export.id.unwrap();
+            cat!(ctx, "Message::{export.name}(_) => {id},\n");
+        }
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+
+        cat!(
+            ctx,
+            "pub fn read_any(data: &[u8]) -> Result<Message, packet::Error> {{\n"
+        );
+        cat!(ctx +++);
+        cat!(ctx, "let mut reader = packet::reader::Reader::new(data);\n");
+        cat!(ctx, "match reader.read_uint8()? {{\n");
+        cat!(ctx +++);
+        for export in exports.iter() {
+            let id = export.id.unwrap();
+            cat!(ctx, "{id} => {{\n");
+            cat!(ctx +++);
+            cat!(ctx, "let mut packet = {export.name}::default();\n");
+            cat!(ctx, "{export.name}::read(&mut reader, &mut packet)?;\n");
+            cat!(ctx, "Ok(Message::{export.name}(packet))\n");
+            cat!(ctx ---);
+            cat!(ctx, "}}\n");
+        }
+        cat!(
+            ctx,
+            "id => Err(packet::Error::InvalidEnumValue(id as usize, \"Message\")),\n"
+        );
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+
+        cat!(
+            ctx,
+            "pub fn write_any(writer: &mut packet::writer::Writer, msg: &Message) -> Result<(), packet::Error> {{\n"
+        );
+        cat!(ctx +++);
+        cat!(ctx, "writer.write_uint8(msg.id());\n");
+        cat!(ctx, "match msg {{\n");
+        cat!(ctx +++);
+        for export in exports.iter() {
+            cat!(
+                ctx,
+                "Message::{export.name}(packet) => {export.name}::write(writer, packet),\n"
+            );
+        }
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
+}
+
+impl Handler for Rust {
+    fn gen_handler(&self, exports: &[check::Export], out: &mut String) {
+        let mut ctx = GenCtx::new(out);
+
+        cat!(ctx, "pub trait Handler {{\n");
+        cat!(ctx +++);
+        for export in exports.iter() {
+            let method = snake_case(export.name);
+            cat!(ctx, "fn on_{method}(&mut self, packet: {export.name});\n");
+        }
+        cat!(ctx, "fn on_error(&mut self, error: packet::Error);\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+
+        cat!(ctx, "pub fn dispatch(data: &[u8], handler: &mut impl Handler) {{\n");
+        cat!(ctx +++);
+        cat!(ctx, "match read_any(data) {{\n");
+        cat!(ctx +++);
+        for export in exports.iter() {
+            let method = snake_case(export.name);
+            cat!(
+                ctx,
+                "Ok(Message::{export.name}(packet)) => handler.on_{method}(packet),\n"
+            );
+        }
+        cat!(ctx, "Err(error) => handler.on_error(error),\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
+}
+
+fn struct_field_typename(base: &str, array: bool, optional: bool) -> String {
+    format_f!(
+        "{preopt}{prearr}{base}{postarr}{postopt}",
+        preopt = if optional { "Option<" } else { "" },
+        prearr = if array { "Vec<" } else { "" },
+        postarr = if array { ">" } else { "" },
+        postopt = if optional { ">" } else { "" }
+    )
+}
+
+/// Emits a doc comment, followed by the attributes which have an equivalent
+/// in Rust
+fn gen_doc(ctx: &mut GenCtx, doc: &[&str], attrs: &[check::Attribute]) {
+    for line in doc {
+        cat!(ctx, "/// {line}\n");
+    }
+    for attr in attrs {
+        match attr {
+            check::Attribute::Since(version) => {
+                if !doc.is_empty() {
+                    cat!(ctx, "///\n");
+                }
+                cat!(ctx, "/// Since: {version}\n");
+            }
+            check::Attribute::Deprecated(_)
+            | check::Attribute::Rename(_)
+            | check::Attribute::Bitmask
+            | check::Attribute::Extensible
+            | check::Attribute::Delta
+            | check::Attribute::Packed => (),
+        }
+    }
+    for attr in attrs {
+        match attr {
+            check::Attribute::Deprecated(None) => cat!(ctx, "#[deprecated]\n"),
+            check::Attribute::Deprecated(Some(reason)) => {
+                let reason = reason.replace('\\', "\\\\").replace('"', "\\\"");
+                cat!(ctx, "#[deprecated(note = \"{reason}\")]\n")
+            }
+            check::Attribute::Since(_)
+            | check::Attribute::Rename(_)
+            | check::Attribute::Bitmask
+            | check::Attribute::Extensible
+            | check::Attribute::Delta
+            | check::Attribute::Packed => (),
+        }
+    }
+}
+
+fn gen_struct_decl(ctx: &mut GenCtx, ty: &check::Struct, name: &str) {
+    gen_doc(ctx, &ty.doc, &ty.attrs);
+    cat!(ctx, "#[derive(Clone, PartialEq, Debug, Default)]\n");
+    cat!(ctx, "pub struct {name} {{\n");
+    cat!(ctx +++);
+    for field in ty.fields.iter() {
+        let type_info = &*field.r#type.borrow();
+        let mut typename: &str = type_info.0;
+        if let check::ResolvedType::Builtin(b) = &type_info.1 {
+            typename = match b {
+                check::Builtin::Uint8 => "u8",
+                check::Builtin::Uint16 => "u16",
+                check::Builtin::Uint32 => "u32",
+                check::Builtin::Int8 => "i8",
+                check::Builtin::Int16 => "i16",
+                check::Builtin::Int32 => "i32",
+                check::Builtin::Float => "f32",
+                check::Builtin::String => "String",
+            };
+        }
+        let sftyname = struct_field_typename(typename, field.array, field.is_optional());
+        gen_doc(ctx, &field.doc, &field.attrs);
+        let ident = field.ident();
+        cat!(ctx, "pub {ident}: {sftyname},\n");
+    }
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+}
+
+fn gen_def_enum_default_impl<'a>(ctx: &mut GenCtx, name: &str, ty: &check::Enum<'a>) {
+    let first_variant = ty.variants.first().unwrap().name;
+
+    cat!(ctx, "impl Default for {name} {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "fn default() -> Self {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "{name}::{first_variant}\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+}
+
+fn gen_def_enum_tryfrom_impl<'a>(ctx: &mut GenCtx, name: &str, ty: &check::Enum<'a>) {
+    cat!(ctx, "impl std::convert::TryFrom<{ty.repr}> for {name} {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "type Error = packet::Error;\n");
+    cat!(ctx, "fn try_from(value: {ty.repr}) -> Result<Self, Self::Error> {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "match value {{\n");
+    cat!(ctx +++);
+    for variant in &ty.variants {
+        let value = 1 << variant.value;
+        cat!(ctx, "{value} => Ok({name}::{variant.name}),\n");
+    }
+    cat!(
+        ctx,
+        "_ => Err(packet::Error::InvalidEnumValue(value as usize, \"{name}\"))\n"
+    );
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+}
+
+fn gen_enum_decl(ctx: &mut GenCtx, ty: &check::Enum, name: &str) {
+    let repr = &ty.repr;
+
+    gen_doc(ctx, &ty.doc, &ty.attrs);
+    cat!(ctx, "#[derive(Clone, Copy, PartialEq, Debug)]\n");
+    cat!(ctx, "#[repr({repr})]\n");
+    cat!(ctx, "pub enum {name} {{\n");
+    cat!(ctx +++);
+    for variant in ty.variants.iter() {
+        gen_doc(ctx, &variant.doc, &[]);
+        cat!(ctx, "{variant.name} = 1 << {variant.value},\n");
+    }
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    gen_def_enum_default_impl(ctx, name, ty);
+    gen_def_enum_tryfrom_impl(ctx, name, ty);
+}
+
+impl Declaration for Rust {
+    fn gen_decls<'a>(
+        &self,
+        types: &[check::Ptr<(&'a str, check::ResolvedType<'a>)>],
+        _exports: &[&str],
+        out: &mut String,
+    ) {
+        let mut ctx = GenCtx::new(out);
+
+        for ty in types.iter() {
+            let (name, ty) = &*ty.borrow();
+            match ty {
+                check::ResolvedType::Builtin(_) => (),
+                check::ResolvedType::Enum(ty) => gen_enum_decl(&mut ctx, ty, name),
+                check::ResolvedType::Struct(ty) => gen_struct_decl(&mut ctx, ty, name),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
     fn commmon_gen() {
         let mut gen = Generator::<Rust>::new();
         gen.push_line();
-        gen.push_common();
+        gen.push_common();
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+#![allow(dead_code, deprecated, non_camel_case_types, unused_imports, unused_assignments, clippy::field_reassign_with_default)]
+use std::convert::TryFrom;
+"
+        );
+    }
+
+    #[test]
+    fn fingerprint_gen() {
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_fingerprint(0xbeef);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+/// Differs between schemas which aren't wire-compatible
+pub const SCHEMA_FINGERPRINT: u32 = 0x0000beef;
+"
+        );
+    }
+
+    #[test]
+    fn imports_gen() {
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_imports(
+            &[],
+            &[
+                check::ImportedTypes {
+                    module: "./shared".to_string(),
+                    package: vec![],
+                    names: vec!["Flag", "Position"],
+                },
+                check::ImportedTypes {
+                    module: "../common/entity".to_string(),
+                    package: vec!["game", "common"],
+                    names: vec!["Entity"],
+                },
+            ],
+        );
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+use super::shared::{Flag, Position};
+use super::super::common::entity::game::common::{Entity};
+"
+        );
+    }
+
+    #[test]
+    fn doc_gen() {
+        use check::*;
+        let types = vec![Ptr::new((
+            "Position",
+            ResolvedType::Struct(Struct {
+                fields: vec![StructField {
+                    name: "x",
+                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                    array: false,
+                    optional: false,
+                    doc: vec!["Horizontal position"],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                }],
+                doc: vec!["A position", "in world space"],
+                attrs: vec![],
+                base: None,
+            }),
+        ))];
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_decls(&types, &[]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+/// A position
+/// in world space
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Position {
+    /// Horizontal position
+    pub x: f32,
+}
+"
+        );
+    }
+
+    #[test]
+    fn attributes_gen() {
+        use check::*;
+        let types = vec![Ptr::new((
+            "Test",
+            ResolvedType::Struct(Struct {
+                fields: vec![
+                    StructField {
+                        name: "hp",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: false,
+                        optional: false,
+                        doc: vec!["Health points"],
+                        attrs: vec![Attribute::Rename("health"), Attribute::Since("1.2")],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "old",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![Attribute::Deprecated(None)],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Deprecated(Some("use Other"))],
+                base: None,
+            }),
+        ))];
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_decls(&types, &[]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+#[deprecated(note = \"use Other\")]
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Test {
+    /// Health points
+    ///
+    /// Since: 1.2
+    pub health: u8,
+    #[deprecated]
+    pub old: u8,
+}
+"
+        );
+    }
+
+    #[test]
+    fn package_gen() {
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_package(
+            &["game", "combat"],
+            &[check::ImportedTypes {
+                module: "./shared".to_string(),
+                package: vec!["game", "common"],
+                names: vec!["Position"],
+            }],
+            "// body\n",
+        );
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+pub mod game {
+    pub mod combat {
+        use std::convert::TryFrom;
+        use super::super::super::shared::game::common::{Position};
+        // body
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn simple_struct_gen() {
+        use check::*;
+        let position = Struct {
+            fields: vec![
+                StructField {
+                    name: "x",
+                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                    array: false,
+                    optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                },
+                StructField {
+                    name: "y",
+                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                    array: false,
+                    optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                },
+            ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
+        };
+        let types = vec![Ptr::new(("Position", ResolvedType::Struct(position)))];
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_decls(&types, &["Position"]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+"
+        );
+    }
+
+    #[test]
+    fn struct_with_optional_gen() {
+        use check::*;
+        let test = Struct {
+            fields: vec![
+                StructField {
+                    name: "a",
+                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                    array: false,
+                    optional: true,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                },
+                StructField {
+                    name: "b",
+                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                    array: true,
+                    optional: true,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                },
+                StructField {
+                    name: "c",
+                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                    array: false,
+                    optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                },
+            ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
+        };
+        let types = vec![Ptr::new(("Test", ResolvedType::Struct(test)))];
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_decls(&types, &["Test"]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Test {
+    pub a: Option<f32>,
+    pub b: Option<Vec<f32>>,
+    pub c: f32,
+}
+"
+        );
+    }
+
+    #[test]
+    fn enum_gen() {
+        use check::*;
+        let flag = Enum {
+            repr: EnumRepr::U8,
+            variants: vec![
+                EnumVariant {
+                    name: "A",
+                    value: 0,
+                    doc: vec![],
+                },
+                EnumVariant {
+                    name: "B",
+                    value: 1,
+                    doc: vec![],
+                },
+            ],
+            doc: vec![],
+            attrs: vec![],
+        };
+        let types = vec![Ptr::new(("Flag", ResolvedType::Enum(flag)))];
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_decls(&types, &["Flag"]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(u8)]
+pub enum Flag {
+    A = 1 << 0,
+    B = 1 << 1,
+}
+impl Default for Flag {
+    fn default() -> Self {
+        Flag::A
+    }
+}
+impl std::convert::TryFrom<u8> for Flag {
+    type Error = packet::Error;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Flag::A),
+            2 => Ok(Flag::B),
+            _ => Err(packet::Error::InvalidEnumValue(value as usize, \"Flag\"))
+        }
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn complex_struct_gen() {
+        use check::*;
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    StructField {
+                        name: "builtin_scalar",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "builtin_array",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "string_scalar",
+                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "string_array",
+                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "enum_scalar",
+                        r#type: Ptr::new((
+                            "Flag",
+                            ResolvedType::Enum(Enum {
+                                repr: EnumRepr::U8,
+                                variants: vec![],
+                                doc: vec![],
+                                attrs: vec![],
+                            }),
+                        )),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "enum_array",
+                        r#type: Ptr::new((
+                            "Flag",
+                            ResolvedType::Enum(Enum {
+                                repr: EnumRepr::U8,
+                                variants: vec![],
+                                doc: vec![],
+                                attrs: vec![],
+                            }),
+                        )),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "struct_scalar",
+                        r#type: Ptr::new((
+                            "Position",
+                            ResolvedType::Struct(Struct {
+                                fields: vec![],
+                                doc: vec![],
+                                attrs: vec![],
+                                base: None,
+                            }),
+                        )),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "struct_array",
+                        r#type: Ptr::new((
+                            "Position",
+                            ResolvedType::Struct(Struct {
+                                fields: vec![],
+                                doc: vec![],
+                                attrs: vec![],
+                                base: None,
+                            }),
+                        )),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            },
+        };
+        let types = vec![Ptr::new(("Test", ResolvedType::Struct(test.r#struct)))];
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_decls(&types, &["Test"]);
         let actual = gen.finish();
         assert_eq!(
             actual,
             "
-#![allow(dead_code, non_camel_case_types, unused_imports, unused_assignments, clippy::field_reassign_with_default)]
-use std::convert::TryFrom;
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Test {
+    pub builtin_scalar: u8,
+    pub builtin_array: Vec<u8>,
+    pub string_scalar: String,
+    pub string_array: Vec<String>,
+    pub enum_scalar: Flag,
+    pub enum_array: Vec<Flag>,
+    pub struct_scalar: Position,
+    pub struct_array: Vec<Position>,
+}
 "
         );
     }
 
     #[test]
-    fn simple_struct_gen() {
+    fn length_impl_gen() {
         use check::*;
-        let position = Struct {
-            fields: vec![
-                StructField {
-                    name: "x",
-                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                    array: false,
-                    optional: false,
-                },
-                StructField {
-                    name: "y",
-                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                    array: false,
-                    optional: false,
-                },
-            ],
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    StructField {
+                        name: "count",
+                        r#type: Ptr::new(("uint16", ResolvedType::Builtin(Builtin::Uint16))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "ids",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: Some("count"),
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "names",
+                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: Some("count"),
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            },
         };
         let mut gen = Generator::<Rust>::new();
         gen.push_line();
-        gen.push_def("Position", &position);
+        gen.push_impl(&test);
         let actual = gen.finish();
         assert_eq!(
             actual,
             "
-#[derive(Clone, PartialEq, Debug, Default)]
-pub struct Position {
-    pub x: f32,
-    pub y: f32,
+impl Test {
+    pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<(), packet::Error> {
+        output.count = reader.read_uint16()?;
+        let output_ids_len = output.count as usize;
+        output.ids.reserve(output_ids_len);
+        for _ in 0..output_ids_len {
+            let mut output_ids_item = u8::default();
+            output_ids_item = reader.read_uint8()?;
+            output.ids.push(output_ids_item);
+        }
+        let output_names_len = output.count as usize;
+        output.names.reserve(output_names_len);
+        for _ in 0..output_names_len {
+            let mut output_names_item = String::default();
+            let output_names_item_len = reader.read_uint32()? as usize;
+            output_names_item = reader.read_string(output_names_item_len)?;
+            output.names.push(output_names_item);
+        }
+        Ok(())
+    }
+    pub fn write(writer: &mut packet::writer::Writer, input: &Test) -> Result<(), packet::Error> {
+        writer.write_uint16(input.count);
+        if input.ids.len() != input.count as usize {
+            return Err(packet::Error::LengthMismatch(\"Test.ids\"));
+        }
+        for &input_ids_item in input.ids.iter() {
+            writer.write_uint8(input_ids_item);
+        }
+        if input.names.len() != input.count as usize {
+            return Err(packet::Error::LengthMismatch(\"Test.names\"));
+        }
+        for input_names_item in input.names.iter() {
+            writer.write_uint32(input_names_item.len() as u32);
+            writer.write_string(&input_names_item);
+        }
+        Ok(())
+    }
 }
 "
         );
     }
 
     #[test]
-    fn struct_with_optional_gen() {
+    fn condition_impl_gen() {
         use check::*;
-        let test = Struct {
-            fields: vec![
-                StructField {
-                    name: "a",
-                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                    array: false,
-                    optional: true,
-                },
-                StructField {
-                    name: "b",
-                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                    array: true,
-                    optional: true,
-                },
-                StructField {
-                    name: "c",
-                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                    array: false,
-                    optional: false,
-                },
-            ],
+        let kind = Ptr::new((
+            "Kind",
+            ResolvedType::Enum(Enum {
+                repr: EnumRepr::U8,
+                variants: vec![
+                    EnumVariant {
+                        name: "Player",
+                        value: 0,
+                        doc: vec![],
+                    },
+                    EnumVariant {
+                        name: "Monster",
+                        value: 1,
+                        doc: vec![],
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+            }),
+        ));
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    StructField {
+                        name: "kind",
+                        r#type: kind,
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "name",
+                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: Some(Condition {
+                            field: "kind",
+                            r#enum: "Kind",
+                            variant: "Player",
+                        }),
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            },
         };
         let mut gen = Generator::<Rust>::new();
         gen.push_line();
-        gen.push_def("Test", &test);
+        gen.push_impl(&test);
         let actual = gen.finish();
         assert_eq!(
             actual,
             "
-#[derive(Clone, PartialEq, Debug, Default)]
-pub struct Test {
-    pub a: Option<f32>,
-    pub b: Option<Vec<f32>>,
-    pub c: f32,
+impl Test {
+    pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<(), packet::Error> {
+        output.kind = Kind::try_from(reader.read_uint8()?)?;
+        if output.kind == Kind::Player {
+            let mut output_name = String::default();
+            let output_name_len = reader.read_uint32()? as usize;
+            output_name = reader.read_string(output_name_len)?;
+            output.name = Some(output_name);
+        }
+        Ok(())
+    }
+    pub fn write(writer: &mut packet::writer::Writer, input: &Test) -> Result<(), packet::Error> {
+        writer.write_uint8(input.kind as u8);
+        if input.kind == Kind::Player {
+            match &input.name {
+                None => return Err(packet::Error::MissingField(\"Test.name\")),
+                Some(input_name) => {
+                    writer.write_uint32(input_name.len() as u32);
+                    writer.write_string(&input_name);
+                }
+            }
+        }
+        Ok(())
+    }
 }
 "
         );
     }
 
     #[test]
-    fn enum_gen() {
+    fn bitmask_impl_gen() {
         use check::*;
-        let flag = Enum {
-            repr: EnumRepr::U8,
-            variants: vec![EnumVariant { name: "A", value: 0 }, EnumVariant { name: "B", value: 1 }],
+        let field = |name, optional| StructField {
+            name,
+            r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+            array: false,
+            optional,
+            doc: vec![],
+            attrs: vec![],
+            range: None,
+            length: None,
+            condition: None,
+            tag: None,
+        };
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    field("a", true),
+                    field("b", false),
+                    field("c", true),
+                    field("d", true),
+                    field("e", true),
+                    field("f", true),
+                    field("g", true),
+                    field("h", true),
+                    field("i", true),
+                    field("j", true),
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Bitmask],
+                base: None,
+            },
         };
         let mut gen = Generator::<Rust>::new();
         gen.push_line();
-        gen.push_def("Flag", &flag);
+        gen.push_impl(&test);
         let actual = gen.finish();
         assert_eq!(
             actual,
             "
-#[derive(Clone, Copy, PartialEq, Debug)]
-#[repr(u8)]
-pub enum Flag {
-    A = 1 << 0,
-    B = 1 << 1,
+impl Test {
+    pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<(), packet::Error> {
+        let output_presence = [reader.read_uint8()?, reader.read_uint8()?];
+        if (output_presence[0] & 1) != 0 {
+            let mut output_a = u8::default();
+            output_a = reader.read_uint8()?;
+            output.a = Some(output_a);
+        }
+        output.b = reader.read_uint8()?;
+        if (output_presence[0] & 2) != 0 {
+            let mut output_c = u8::default();
+            output_c = reader.read_uint8()?;
+            output.c = Some(output_c);
+        }
+        if (output_presence[0] & 4) != 0 {
+            let mut output_d = u8::default();
+            output_d = reader.read_uint8()?;
+            output.d = Some(output_d);
+        }
+        if (output_presence[0] & 8) != 0 {
+            let mut output_e = u8::default();
+            output_e = reader.read_uint8()?;
+            output.e = Some(output_e);
+        }
+        if (output_presence[0] & 16) != 0 {
+            let mut output_f = u8::default();
+            output_f = reader.read_uint8()?;
+            output.f = Some(output_f);
+        }
+        if (output_presence[0] & 32) != 0 {
+            let mut output_g = u8::default();
+            output_g = reader.read_uint8()?;
+            output.g = Some(output_g);
+        }
+        if (output_presence[0] & 64) != 0 {
+            let mut output_h = u8::default();
+            output_h = reader.read_uint8()?;
+            output.h = Some(output_h);
+        }
+        if (output_presence[0] & 128) != 0 {
+            let mut output_i = u8::default();
+            output_i = reader.read_uint8()?;
+            output.i = Some(output_i);
+        }
+        if (output_presence[1] & 1) != 0 {
+            let mut output_j = u8::default();
+            output_j = reader.read_uint8()?;
+            output.j = Some(output_j);
+        }
+        Ok(())
+    }
+    pub fn write(writer: &mut packet::writer::Writer, input: &Test) -> Result<(), packet::Error> {
+        writer.write_uint8(input.a.is_some() as u8 | (input.c.is_some() as u8) << 1 | (input.d.is_some() as u8) << 2 | (input.e.is_some() as u8) << 3 | (input.f.is_some() as u8) << 4 | (input.g.is_some() as u8) << 5 | (input.h.is_some() as u8) << 6 | (input.i.is_some() as u8) << 7);
+        writer.write_uint8(input.j.is_some() as u8);
+        if let Some(input_a) = input.a {
+            writer.write_uint8(input_a);
+        }
+        writer.write_uint8(input.b);
+        if let Some(input_c) = input.c {
+            writer.write_uint8(input_c);
+        }
+        if let Some(input_d) = input.d {
+            writer.write_uint8(input_d);
+        }
+        if let Some(input_e) = input.e {
+            writer.write_uint8(input_e);
+        }
+        if let Some(input_f) = input.f {
+            writer.write_uint8(input_f);
+        }
+        if let Some(input_g) = input.g {
+            writer.write_uint8(input_g);
+        }
+        if let Some(input_h) = input.h {
+            writer.write_uint8(input_h);
+        }
+        if let Some(input_i) = input.i {
+            writer.write_uint8(input_i);
+        }
+        if let Some(input_j) = input.j {
+            writer.write_uint8(input_j);
+        }
+        Ok(())
+    }
 }
-impl Default for Flag {
-    fn default() -> Self {
-        Flag::A
+"
+        );
+    }
+
+    #[test]
+    fn extensible_impl_gen() {
+        use check::*;
+        let field = |name, ty, optional, tag| StructField {
+            name,
+            r#type: ty,
+            array: false,
+            optional,
+            doc: vec![],
+            attrs: vec![],
+            range: None,
+            length: None,
+            condition: None,
+            tag: Some(tag),
+        };
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    field(
+                        "id",
+                        Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        false,
+                        1,
+                    ),
+                    field(
+                        "name",
+                        Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        true,
+                        2,
+                    ),
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Extensible],
+                base: None,
+            },
+        };
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_impl(&test);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+impl Test {
+    pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<(), packet::Error> {
+        loop {
+            let output_tag = reader.read_uint16()?;
+            if output_tag == 0 {
+                break;
+            }
+            let output_len = reader.read_uint32()? as usize;
+            match output_tag {
+                1 => {
+                    output.id = reader.read_uint8()?;
+                }
+                2 => {
+                    let mut output_name = String::default();
+                    let output_name_len = reader.read_uint32()? as usize;
+                    output_name = reader.read_string(output_name_len)?;
+                    output.name = Some(output_name);
+                }
+                _ => reader.skip(output_len)?,
+            }
+        }
+        Ok(())
+    }
+    pub fn write(writer: &mut packet::writer::Writer, input: &Test) -> Result<(), packet::Error> {
+        writer.write_uint16(1u16);
+        let input_id_start = writer.position();
+        writer.write_uint32(0u32);
+        writer.write_uint8(input.id);
+        writer.write_uint32_at(input_id_start, (writer.position() - input_id_start - 4) as u32);
+        if let Some(input_name) = &input.name {
+            writer.write_uint16(2u16);
+            let input_name_start = writer.position();
+            writer.write_uint32(0u32);
+            writer.write_uint32(input_name.len() as u32);
+            writer.write_string(&input_name);
+            writer.write_uint32_at(input_name_start, (writer.position() - input_name_start - 4) as u32);
+        }
+        writer.write_uint16(0u16);
+        Ok(())
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn delta_impl_gen() {
+        use check::*;
+        let field = |name, ty, optional| StructField {
+            name,
+            r#type: ty,
+            array: false,
+            optional,
+            doc: vec![],
+            attrs: vec![],
+            range: None,
+            length: None,
+            condition: None,
+            tag: None,
+        };
+        let position = Struct {
+            fields: vec![field(
+                "x",
+                Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                false,
+            )],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
+        };
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    field("hp", Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))), false),
+                    field("pos", Ptr::new(("Position", ResolvedType::Struct(position))), false),
+                    field(
+                        "name",
+                        Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        true,
+                    ),
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Delta],
+                base: None,
+            },
+        };
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_impl(&test);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+impl Test {
+    pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<(), packet::Error> {
+        output.hp = reader.read_uint8()?;
+        output.pos.x = reader.read_float()?;
+        if reader.read_uint8()? > 0 {
+            let mut output_name = String::default();
+            let output_name_len = reader.read_uint32()? as usize;
+            output_name = reader.read_string(output_name_len)?;
+            output.name = Some(output_name);
+        }
+        Ok(())
+    }
+    pub fn write(writer: &mut packet::writer::Writer, input: &Test) -> Result<(), packet::Error> {
+        writer.write_uint8(input.hp);
+        writer.write_float(input.pos.x);
+        match &input.name {
+            None => writer.write_uint8(0u8),
+            Some(input_name) => {
+                writer.write_uint8(1u8);
+                writer.write_uint32(input_name.len() as u32);
+                writer.write_string(&input_name);
+            }
+        }
+        Ok(())
+    }
+    pub fn read_delta(reader: &mut packet::reader::Reader, baseline: &Test, output: &mut Test) -> Result<(), packet::Error> {
+        *output = baseline.clone();
+        let output_changed = [reader.read_uint8()?];
+        if (output_changed[0] & 1) != 0 {
+            output.hp = reader.read_uint8()?;
+        }
+        if (output_changed[0] & 2) != 0 {
+            let output_pos_changed = [reader.read_uint8()?];
+            if (output_pos_changed[0] & 1) != 0 {
+                output.pos.x = reader.read_float()?;
+            }
+        }
+        if (output_changed[0] & 4) != 0 {
+            output.name = None;
+            if reader.read_uint8()? > 0 {
+                let mut output_name = String::default();
+                let output_name_len = reader.read_uint32()? as usize;
+                output_name = reader.read_string(output_name_len)?;
+                output.name = Some(output_name);
+            }
+        }
+        Ok(())
+    }
+    pub fn write_delta(writer: &mut packet::writer::Writer, baseline: &Test, input: &Test) -> Result<(), packet::Error> {
+        let input_changed = [
+            (input.hp != baseline.hp) as u8 | ((input.pos != baseline.pos) as u8) << 1 | ((input.name != baseline.name) as u8) << 2,
+        ];
+        writer.write_uint8(input_changed[0]);
+        if (input_changed[0] & 1) != 0 {
+            writer.write_uint8(input.hp);
+        }
+        if (input_changed[0] & 2) != 0 {
+            let input_pos_changed = [
+                (input.pos.x != baseline.pos.x) as u8,
+            ];
+            writer.write_uint8(input_pos_changed[0]);
+            if (input_pos_changed[0] & 1) != 0 {
+                writer.write_float(input.pos.x);
+            }
+        }
+        if (input_changed[0] & 4) != 0 {
+            match &input.name {
+                None => writer.write_uint8(0u8),
+                Some(input_name) => {
+                    writer.write_uint8(1u8);
+                    writer.write_uint32(input_name.len() as u32);
+                    writer.write_string(&input_name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn packed_impl_gen() {
+        use check::*;
+        let field = |name, ty, optional, range| StructField {
+            name,
+            r#type: ty,
+            array: false,
+            optional,
+            doc: vec![],
+            attrs: vec![],
+            range,
+            length: None,
+            condition: None,
+            tag: None,
+        };
+        let variant = |name, value| EnumVariant {
+            name,
+            value,
+            doc: vec![],
+        };
+        let kind = Enum {
+            repr: EnumRepr::U8,
+            variants: vec![variant("A", 0), variant("B", 1), variant("C", 2)],
+            doc: vec![],
+            attrs: vec![],
+        };
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    field(
+                        "level",
+                        Ptr::new(("int8", ResolvedType::Builtin(Builtin::Int8))),
+                        false,
+                        Some(Range {
+                            min: Some("-10"),
+                            max: Some("10"),
+                            inclusive: false,
+                        }),
+                    ),
+                    field("kind", Ptr::new(("Kind", ResolvedType::Enum(kind))), false, None),
+                    field(
+                        "name",
+                        Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        true,
+                        None,
+                    ),
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Packed],
+                base: None,
+            },
+        };
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_impl(&test);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+impl Test {
+    pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<(), packet::Error> {
+        output.level = (reader.read_bits(5)? as i64 - 10) as i8;
+        if !(output.level.ge(&-10_i8) && output.level.lt(&10_i8)) {
+            return Err(packet::Error::OutOfRange(\"Test.level\"));
+        }
+        output.kind = Kind::try_from((1u32 << reader.read_bits(2)?) as u8)?;
+        if reader.read_bits(1)? != 0 {
+            let mut output_name = String::default();
+            let output_name_len = reader.read_bits(32)? as usize;
+            output_name = reader.read_bits_string(output_name_len)?;
+            output.name = Some(output_name);
+        }
+        Ok(())
     }
-}
-impl std::convert::TryFrom<u8> for Flag {
-    type Error = packet::Error;
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            1 => Ok(Flag::A),
-            2 => Ok(Flag::B),
-            _ => Err(packet::Error::InvalidEnumValue(value as usize, \"Flag\"))
+    pub fn write(writer: &mut packet::writer::Writer, input: &Test) -> Result<(), packet::Error> {
+        if !(input.level.ge(&-10_i8) && input.level.lt(&10_i8)) {
+            return Err(packet::Error::OutOfRange(\"Test.level\"));
+        }
+        writer.write_bits((input.level.to_owned() as i64 + 10) as u32, 5);
+        writer.write_bits((input.kind.to_owned() as u32).trailing_zeros(), 2);
+        match &input.name {
+            None => writer.write_bits(0, 1),
+            Some(input_name) => {
+                writer.write_bits(1, 1);
+                writer.write_bits(input_name.len() as u32, 32);
+                writer.write_bits_string(&input_name);
+            }
         }
+        writer.flush_bits();
+        Ok(())
     }
 }
 "
@@ -488,92 +2432,116 @@ impl std::convert::TryFrom<u8> for Flag {
     }
 
     #[test]
-    fn complex_struct_gen() {
+    fn range_impl_gen() {
         use check::*;
         let test = Export {
             name: "Test",
+            id: None,
             r#struct: Struct {
                 fields: vec![
                     StructField {
-                        name: "builtin_scalar",
-                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
-                        array: false,
-                        optional: false,
-                    },
-                    StructField {
-                        name: "builtin_array",
+                        name: "hp",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
-                        array: true,
-                        optional: false,
-                    },
-                    StructField {
-                        name: "string_scalar",
-                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: Some(Range {
+                            min: None,
+                            max: Some("100"),
+                            inclusive: true,
+                        }),
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
-                        name: "string_array",
+                        name: "name",
                         r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
-                        array: true,
-                        optional: false,
-                    },
-                    StructField {
-                        name: "enum_scalar",
-                        r#type: Ptr::new((
-                            "Flag",
-                            ResolvedType::Enum(Enum {
-                                repr: EnumRepr::U8,
-                                variants: vec![],
-                            }),
-                        )),
-                        array: false,
-                        optional: false,
-                    },
-                    StructField {
-                        name: "enum_array",
-                        r#type: Ptr::new((
-                            "Flag",
-                            ResolvedType::Enum(Enum {
-                                repr: EnumRepr::U8,
-                                variants: vec![],
-                            }),
-                        )),
-                        array: true,
-                        optional: false,
-                    },
-                    StructField {
-                        name: "struct_scalar",
-                        r#type: Ptr::new(("Position", ResolvedType::Struct(Struct { fields: vec![] }))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: Some(Range {
+                            min: Some("3"),
+                            max: Some("16"),
+                            inclusive: false,
+                        }),
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
-                        name: "struct_array",
-                        r#type: Ptr::new(("Position", ResolvedType::Struct(Struct { fields: vec![] }))),
+                        name: "scale",
+                        r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: Some(Range {
+                            min: Some("-1.5"),
+                            max: None,
+                            inclusive: false,
+                        }),
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                 ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
         let mut gen = Generator::<Rust>::new();
         gen.push_line();
-        gen.push_def("Test", &test.r#struct);
+        gen.push_impl(&test);
         let actual = gen.finish();
         assert_eq!(
             actual,
             "
-#[derive(Clone, PartialEq, Debug, Default)]
-pub struct Test {
-    pub builtin_scalar: u8,
-    pub builtin_array: Vec<u8>,
-    pub string_scalar: String,
-    pub string_array: Vec<String>,
-    pub enum_scalar: Flag,
-    pub enum_array: Vec<Flag>,
-    pub struct_scalar: Position,
-    pub struct_array: Vec<Position>,
+impl Test {
+    pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<(), packet::Error> {
+        output.hp = reader.read_uint8()?;
+        if !(output.hp.le(&100_u8)) {
+            return Err(packet::Error::OutOfRange(\"Test.hp\"));
+        }
+        let output_name_len = reader.read_uint32()? as usize;
+        if !(output_name_len.ge(&3_usize) && output_name_len.lt(&16_usize)) {
+            return Err(packet::Error::OutOfRange(\"Test.name\"));
+        }
+        output.name = reader.read_string(output_name_len)?;
+        let output_scale_len = reader.read_uint32()? as usize;
+        output.scale.reserve(output_scale_len);
+        for _ in 0..output_scale_len {
+            let mut output_scale_item = f32::default();
+            output_scale_item = reader.read_float()?;
+            if !(output_scale_item.ge(&-1.5_f32)) {
+                return Err(packet::Error::OutOfRange(\"Test.scale\"));
+            }
+            output.scale.push(output_scale_item);
+        }
+        Ok(())
+    }
+    pub fn write(writer: &mut packet::writer::Writer, input: &Test) -> Result<(), packet::Error> {
+        if !(input.hp.le(&100_u8)) {
+            return Err(packet::Error::OutOfRange(\"Test.hp\"));
+        }
+        writer.write_uint8(input.hp);
+        if !(input.name.len().ge(&3_usize) && input.name.len().lt(&16_usize)) {
+            return Err(packet::Error::OutOfRange(\"Test.name\"));
+        }
+        writer.write_uint32(input.name.len() as u32);
+        writer.write_string(&input.name);
+        writer.write_uint32(input.scale.len() as u32);
+        for &input_scale_item in input.scale.iter() {
+            if !(input_scale_item.ge(&-1.5_f32)) {
+                return Err(packet::Error::OutOfRange(\"Test.scale\"));
+            }
+            writer.write_float(input_scale_item);
+        }
+        Ok(())
+    }
 }
 "
         );
@@ -584,6 +2552,7 @@ pub struct Test {
         use check::*;
         let test = Export {
             name: "Test",
+            id: None,
             r#struct: Struct {
                 fields: vec![
                     StructField {
@@ -591,30 +2560,49 @@ pub struct Test {
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "b",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: true,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "c",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                 ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
-        let mut gen = Generator::<Rust>::new();
-        gen.push_line();
-        gen.push_write_impl("Test", &test);
-        let actual = gen.finish();
+        let mut actual = String::from("\n");
+        gen_write_impl(&mut GenCtx::new(&mut actual), &test);
         assert_eq!(
             actual,
             "
-pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
+pub fn write(writer: &mut packet::writer::Writer, input: &Test) -> Result<(), packet::Error> {
     match input.a {
         None => writer.write_uint8(0u8),
         Some(input_a) => {
@@ -627,12 +2615,13 @@ pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
         Some(input_b) => {
             writer.write_uint8(1u8);
             writer.write_uint32(input_b.len() as u32);
-            for input_b_item in input_b.iter() {
+            for &input_b_item in input_b.iter() {
                 writer.write_uint8(input_b_item);
             }
         }
     }
     writer.write_uint8(input.c);
+    Ok(())
 }
 "
         );
@@ -643,6 +2632,7 @@ pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
         use check::*;
         let test = Export {
             name: "Test",
+            id: None,
             r#struct: Struct {
                 fields: vec![
                     StructField {
@@ -650,20 +2640,45 @@ pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "b",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: true,
+                        optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "c",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                 ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
-        let mut gen = Generator::<Rust>::new();
-        gen.push_line();
-        gen.push_read_impl("Test", &test);
-        let actual = gen.finish();
+        let mut actual = String::from("\n");
+        gen_read_impl(&mut GenCtx::new(&mut actual), &test);
         assert_eq!(
             actual,
             "
@@ -673,7 +2688,18 @@ pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<()
         output_a = reader.read_uint8()?;
         output.a = Some(output_a);
     }
-    output.b = reader.read_uint8()?;
+    if reader.read_uint8()? > 0 {
+        let mut output_b = u8::default();
+        let output_b_len = reader.read_uint32()? as usize;
+        output_b.reserve(output_b_len);
+        for _ in 0..output_b_len {
+            let mut output_b_item = u8::default();
+            output_b_item = reader.read_uint8()?;
+            output_b.push(output_b_item);
+        }
+        output.b = Some(output_b);
+    }
+    output.c = reader.read_uint8()?;
     Ok(())
 }
 "
@@ -690,45 +2716,69 @@ pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<()
                     r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                     array: true,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "second",
                     r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                     array: true,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let test_b = Export {
             name: "TestB",
+            id: None,
             r#struct: Struct {
                 fields: vec![StructField {
                     name: "test_a",
                     r#type: Ptr::new(("TestA", ResolvedType::Struct(test_a))),
                     array: true,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 }],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
-        let mut gen = Generator::<Rust>::new();
-        gen.push_line();
-        gen.push_write_impl("TestB", &test_b);
-        let actual = gen.finish();
+        let mut actual = String::from("\n");
+        gen_write_impl(&mut GenCtx::new(&mut actual), &test_b);
         assert_eq!(
             actual,
             "
-pub fn write(writer: &mut packet::writer::Writer, input: &TestB) {
+pub fn write(writer: &mut packet::writer::Writer, input: &TestB) -> Result<(), packet::Error> {
     writer.write_uint32(input.test_a.len() as u32);
     for input_test_a_item in input.test_a.iter() {
         writer.write_uint32(input_test_a_item.first.len() as u32);
-        for input_test_a_item_first_item in input_test_a_item.first.iter() {
+        for &input_test_a_item_first_item in input_test_a_item.first.iter() {
             writer.write_uint8(input_test_a_item_first_item);
         }
         writer.write_uint32(input_test_a_item.second.len() as u32);
-        for input_test_a_item_second_item in input_test_a_item.second.iter() {
+        for &input_test_a_item_second_item in input_test_a_item.second.iter() {
             writer.write_uint8(input_test_a_item_second_item);
         }
     }
+    Ok(())
 }
 "
         );
@@ -744,30 +2794,53 @@ pub fn write(writer: &mut packet::writer::Writer, input: &TestB) {
                     r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                     array: true,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "second",
                     r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                     array: true,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let test_b = Export {
             name: "TestB",
+            id: None,
             r#struct: Struct {
                 fields: vec![StructField {
                     name: "test_a",
                     r#type: Ptr::new(("TestA", ResolvedType::Struct(test_a))),
                     array: true,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 }],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
-        let mut gen = Generator::<Rust>::new();
-        gen.push_line();
-        gen.push_read_impl("TestB", &test_b);
-        let actual = gen.finish();
+        let mut actual = String::from("\n");
+        gen_read_impl(&mut GenCtx::new(&mut actual), &test_b);
         assert_eq!(
             actual,
             "
@@ -803,7 +2876,20 @@ pub fn read(reader: &mut packet::reader::Reader, output: &mut TestB) -> Result<(
         use check::*;
         let flag = Enum {
             repr: EnumRepr::U8,
-            variants: vec![EnumVariant { name: "A", value: 0 }, EnumVariant { name: "B", value: 1 }],
+            variants: vec![
+                EnumVariant {
+                    name: "A",
+                    value: 0,
+                    doc: vec![],
+                },
+                EnumVariant {
+                    name: "B",
+                    value: 1,
+                    doc: vec![],
+                },
+            ],
+            doc: vec![],
+            attrs: vec![],
         };
         let position = Struct {
             fields: vec![
@@ -812,17 +2898,33 @@ pub fn read(reader: &mut packet::reader::Reader, output: &mut TestB) -> Result<(
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "y",
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let test = Export {
             name: "Test",
+            id: None,
             r#struct: Struct {
                 fields: vec![
                     StructField {
@@ -830,81 +2932,148 @@ pub fn read(reader: &mut packet::reader::Reader, output: &mut TestB) -> Result<(
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "builtin_array",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "string_scalar",
                         r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "string_array",
                         r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "enum_scalar",
                         r#type: Ptr::new(("Flag", ResolvedType::Enum(flag.clone()))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "enum_array",
                         r#type: Ptr::new(("Flag", ResolvedType::Enum(flag.clone()))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "struct_scalar",
                         r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "struct_array",
                         r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "opt_scalar",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "opt_enum",
                         r#type: Ptr::new(("Flag", ResolvedType::Enum(flag.clone()))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "opt_struct",
                         r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                 ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
-        let mut gen = Generator::<Rust>::new();
-        gen.push_line();
-        gen.push_write_impl("Test", &test);
-        let actual = gen.finish();
+        let mut actual = String::from("\n");
+        gen_write_impl(&mut GenCtx::new(&mut actual), &test);
         assert_eq!(
             actual,
             "
-pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
+pub fn write(writer: &mut packet::writer::Writer, input: &Test) -> Result<(), packet::Error> {
     writer.write_uint8(input.builtin_scalar);
     writer.write_uint32(input.builtin_array.len() as u32);
-    for input_builtin_array_item in input.builtin_array.iter() {
+    for &input_builtin_array_item in input.builtin_array.iter() {
         writer.write_uint8(input_builtin_array_item);
     }
     writer.write_uint32(input.string_scalar.len() as u32);
@@ -916,7 +3085,7 @@ pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
     }
     writer.write_uint8(input.enum_scalar as u8);
     writer.write_uint32(input.enum_array.len() as u32);
-    for input_enum_array_item in input.enum_array.iter() {
+    for &input_enum_array_item in input.enum_array.iter() {
         writer.write_uint8(input_enum_array_item as u8);
     }
     writer.write_float(input.struct_scalar.x);
@@ -948,6 +3117,7 @@ pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
             writer.write_float(input_opt_struct.y);
         }
     }
+    Ok(())
 }
 "
         );
@@ -958,7 +3128,20 @@ pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
         use check::*;
         let flag = Enum {
             repr: EnumRepr::U8,
-            variants: vec![EnumVariant { name: "A", value: 0 }, EnumVariant { name: "B", value: 1 }],
+            variants: vec![
+                EnumVariant {
+                    name: "A",
+                    value: 0,
+                    doc: vec![],
+                },
+                EnumVariant {
+                    name: "B",
+                    value: 1,
+                    doc: vec![],
+                },
+            ],
+            doc: vec![],
+            attrs: vec![],
         };
         let position = Struct {
             fields: vec![
@@ -967,17 +3150,33 @@ pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "y",
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let test = Export {
             name: "Test",
+            id: None,
             r#struct: Struct {
                 fields: vec![
                     StructField {
@@ -985,74 +3184,141 @@ pub fn write(writer: &mut packet::writer::Writer, input: &Test) {
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "builtin_array",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "string_scalar",
                         r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "string_array",
                         r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "enum_scalar",
                         r#type: Ptr::new(("Flag", ResolvedType::Enum(flag.clone()))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "enum_array",
                         r#type: Ptr::new(("Flag", ResolvedType::Enum(flag.clone()))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "struct_scalar",
                         r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "struct_array",
                         r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "opt_scalar",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "opt_enum",
                         r#type: Ptr::new(("Flag", ResolvedType::Enum(flag.clone()))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "opt_struct",
                         r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                 ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
-        let mut gen = Generator::<Rust>::new();
-        gen.push_line();
-        gen.push_read_impl("Test", &test);
-        let actual = gen.finish();
+        let mut actual = String::from("\n");
+        gen_read_impl(&mut GenCtx::new(&mut actual), &test);
         assert_eq!(
             actual,
             "
@@ -1125,14 +3391,29 @@ pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<()
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "y",
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let entity = Struct {
             fields: vec![
@@ -1141,17 +3422,33 @@ pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<()
                     r#type: Ptr::new(("uint32", ResolvedType::Builtin(Builtin::Uint32))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "pos",
                     r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                     array: false,
                     optional: true,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let state = Export {
             name: "State",
+            id: None,
             r#struct: Struct {
                 fields: vec![
                     StructField {
@@ -1159,24 +3456,37 @@ pub fn read(reader: &mut packet::reader::Reader, output: &mut Test) -> Result<()
                         r#type: Ptr::new(("uint32", ResolvedType::Builtin(Builtin::Uint32))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "entities",
                         r#type: Ptr::new(("Entity", ResolvedType::Struct(entity.clone()))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                 ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
-        let mut gen = Generator::<Rust>::new();
-        gen.push_line();
-        gen.push_write_impl("State", &state);
-        let actual = gen.finish();
+        let mut actual = String::from("\n");
+        gen_write_impl(&mut GenCtx::new(&mut actual), &state);
         assert_eq!(
             actual,
             "
-pub fn write(writer: &mut packet::writer::Writer, input: &State) {
+pub fn write(writer: &mut packet::writer::Writer, input: &State) -> Result<(), packet::Error> {
     writer.write_uint32(input.id);
     writer.write_uint32(input.entities.len() as u32);
     for input_entities_item in input.entities.iter() {
@@ -1190,8 +3500,346 @@ pub fn write(writer: &mut packet::writer::Writer, input: &State) {
             }
         }
     }
+    Ok(())
+}
+"
+        );
+    }
+
+    #[test]
+    fn nested_read_opt_gen() {
+        use check::*;
+        let position = Struct {
+            fields: vec![
+                StructField {
+                    name: "x",
+                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                    array: false,
+                    optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                },
+                StructField {
+                    name: "y",
+                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                    array: false,
+                    optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                },
+            ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
+        };
+        let entity = Struct {
+            fields: vec![
+                StructField {
+                    name: "uid",
+                    r#type: Ptr::new(("uint32", ResolvedType::Builtin(Builtin::Uint32))),
+                    array: false,
+                    optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                },
+                StructField {
+                    name: "pos",
+                    r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
+                    array: false,
+                    optional: true,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                },
+            ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
+        };
+        let state = Export {
+            name: "State",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    StructField {
+                        name: "id",
+                        r#type: Ptr::new(("uint32", ResolvedType::Builtin(Builtin::Uint32))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "entities",
+                        r#type: Ptr::new(("Entity", ResolvedType::Struct(entity.clone()))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            },
+        };
+        let mut actual = String::from("\n");
+        gen_read_impl(&mut GenCtx::new(&mut actual), &state);
+        assert_eq!(
+            actual,
+            "
+pub fn read(reader: &mut packet::reader::Reader, output: &mut State) -> Result<(), packet::Error> {
+    output.id = reader.read_uint32()?;
+    let output_entities_len = reader.read_uint32()? as usize;
+    output.entities.reserve(output_entities_len);
+    for _ in 0..output_entities_len {
+        let mut output_entities_item = Entity::default();
+        output_entities_item.uid = reader.read_uint32()?;
+        if reader.read_uint8()? > 0 {
+            let mut output_entities_item_pos = Position::default();
+            output_entities_item_pos.x = reader.read_float()?;
+            output_entities_item_pos.y = reader.read_float()?;
+            output_entities_item.pos = Some(output_entities_item_pos);
+        }
+        output.entities.push(output_entities_item);
+    }
+    Ok(())
+}
+"
+        );
+    }
+
+    #[test]
+    fn message_gen() {
+        use check::*;
+        let exports = vec![
+            Export {
+                name: "Move",
+                id: Some(1),
+                r#struct: Struct {
+                    fields: vec![],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: None,
+                },
+            },
+            Export {
+                name: "Spawn",
+                id: Some(2),
+                r#struct: Struct {
+                    fields: vec![],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: None,
+                },
+            },
+        ];
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_message(&exports);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    Move(Move),
+    Spawn(Spawn),
+}
+impl Message {
+    pub fn id(&self) -> u8 {
+        match self {
+            Message::Move(_) => 1,
+            Message::Spawn(_) => 2,
+        }
+    }
+}
+pub fn read_any(data: &[u8]) -> Result<Message, packet::Error> {
+    let mut reader = packet::reader::Reader::new(data);
+    match reader.read_uint8()? {
+        1 => {
+            let mut packet = Move::default();
+            Move::read(&mut reader, &mut packet)?;
+            Ok(Message::Move(packet))
+        }
+        2 => {
+            let mut packet = Spawn::default();
+            Spawn::read(&mut reader, &mut packet)?;
+            Ok(Message::Spawn(packet))
+        }
+        id => Err(packet::Error::InvalidEnumValue(id as usize, \"Message\")),
+    }
+}
+pub fn write_any(writer: &mut packet::writer::Writer, msg: &Message) -> Result<(), packet::Error> {
+    writer.write_uint8(msg.id());
+    match msg {
+        Message::Move(packet) => Move::write(writer, packet),
+        Message::Spawn(packet) => Spawn::write(writer, packet),
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn handler_gen() {
+        use check::*;
+        let exports = vec![
+            Export {
+                name: "Move",
+                id: Some(1),
+                r#struct: Struct {
+                    fields: vec![],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: None,
+                },
+            },
+            Export {
+                name: "PlayerState",
+                id: Some(2),
+                r#struct: Struct {
+                    fields: vec![],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: None,
+                },
+            },
+        ];
+        let mut gen = Generator::<Rust>::new();
+        gen.push_line();
+        gen.push_handler(&exports);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+pub trait Handler {
+    fn on_move(&mut self, packet: Move);
+    fn on_player_state(&mut self, packet: PlayerState);
+    fn on_error(&mut self, error: packet::Error);
+}
+pub fn dispatch(data: &[u8], handler: &mut impl Handler) {
+    match read_any(data) {
+        Ok(Message::Move(packet)) => handler.on_move(packet),
+        Ok(Message::PlayerState(packet)) => handler.on_player_state(packet),
+        Err(error) => handler.on_error(error),
+    }
 }
 "
         );
     }
+
+    #[test]
+    fn array_items_written_by_value() {
+        let schema = "Kind: enum { A, B }\n\
+                      Test: struct { ids: uint8[], kinds: Kind[], names: string[] }\n\
+                      export Test";
+        let out = crate::compile::<Rust>(schema).unwrap();
+        assert!(out.contains(
+            "        for &input_ids_item in input.ids.iter() {
+            writer.write_uint8(input_ids_item);
+        }
+"
+        ));
+        assert!(out.contains(
+            "        for &input_kinds_item in input.kinds.iter() {
+            writer.write_uint8(input_kinds_item as u8);
+        }
+"
+        ));
+        // strings can't be copied out of the array
+        assert!(out.contains(
+            "        for input_names_item in input.names.iter() {
+            writer.write_uint32(input_names_item.len() as u32);
+            writer.write_string(&input_names_item);
+        }
+"
+        ));
+    }
+
+    #[test]
+    fn conditional_written_by_value() {
+        let schema = "Kind: enum { A, B }\n\
+                      Test: struct { kind: Kind, level: uint8 if kind == Kind.A, other: Kind if kind == Kind.B }\n\
+                      export Test";
+        let out = crate::compile::<Rust>(schema).unwrap();
+        assert!(out.contains(
+            "            match input.level {
+                None => return Err(packet::Error::MissingField(\"Test.level\")),
+                Some(input_level) => {
+                    writer.write_uint8(input_level);
+"
+        ));
+        assert!(out.contains(
+            "            match input.other {
+                None => return Err(packet::Error::MissingField(\"Test.other\")),
+                Some(input_other) => {
+                    writer.write_uint8(input_other as u8);
+"
+        ));
+    }
+
+    #[test]
+    fn delta_array_read_incrementally() {
+        let schema = "Pos: struct { x: float }\n\
+                      @delta\n\
+                      Test: struct { items: Pos[] }\n\
+                      export Test";
+        let out = crate::compile::<Rust>(schema).unwrap();
+        assert!(out.contains(
+            "            let output_items_len = reader.read_uint32()? as usize;
+            output.items.truncate(output_items_len);
+            for output_items_index in 0..output_items_len {
+                match output.items.get_mut(output_items_index) {
+                    Some(output_items_item) => {
+                        let output_items_item_changed = [reader.read_uint8()?];
+                        if (output_items_item_changed[0] & 1) != 0 {
+                            output_items_item.x = reader.read_float()?;
+                        }
+                    }
+                    None => {
+                        let mut output_items_item = Pos::default();
+                        output_items_item.x = reader.read_float()?;
+                        output.items.push(output_items_item);
+                    }
+                }
+            }
+"
+        ));
+    }
+
+    #[test]
+    fn delta_conditional_follows_condition() {
+        let schema = "Kind: enum { A, B }\n\
+                      @delta\n\
+                      Test: struct { kind: Kind, hp: uint8 if kind == Kind.A, other: uint8 if kind == Kind.B }\n\
+                      export Test";
+        let out = crate::compile::<Rust>(schema).unwrap();
+        assert!(out.contains(
+            "(input.kind != baseline.kind) as u8 | ((input.hp != baseline.hp || input.kind != baseline.kind) as u8) << 1"
+        ));
+    }
 }
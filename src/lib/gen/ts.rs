@@ -15,13 +15,65 @@ impl Common for TypeScript {
     fn gen_common(&self, out: &mut String) {
         append!(out, "import {{ Reader, Writer }} from \"packet\";\n");
     }
+
+    fn gen_imports(&self, package: &[&str], imports: &[check::ImportedTypes], out: &mut String) {
+        for import in imports {
+            let module = &import.module;
+            if import.package.is_empty() {
+                let names = import.names.join(", ");
+                append!(out, "import {{ {names} }} from \"{module}\";\n");
+            } else {
+                let alias = module_alias(module);
+                append!(out, "import * as {alias} from \"{module}\";\n");
+            }
+        }
+        // otherwise the aliases are declared inside of the namespace
+        if package.is_empty() {
+            gen_import_aliases(&mut GenCtx::new(out), imports);
+        }
+    }
+}
+
+/// Name that a module which declares a package is imported as, e.g.
+/// `common_shared` for `../common/shared`
+fn module_alias(module: &str) -> String {
+    module
+        .split('/')
+        .filter(|part| *part != "." && *part != "..")
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Refers to the types of packaged imports by their unqualified names
+fn gen_import_aliases(ctx: &mut GenCtx, imports: &[check::ImportedTypes]) {
+    for import in imports.iter().filter(|i| !i.package.is_empty()) {
+        let path = format!("{}.{}", module_alias(&import.module), import.package.join("."));
+        for name in import.names.iter() {
+            cat!(ctx, "import {name} = {path}.{name};\n");
+        }
+    }
+}
+
+impl Package for TypeScript {
+    fn gen_package(&self, package: &[&str], imports: &[check::ImportedTypes], body: &str, out: &mut String) {
+        let mut ctx = GenCtx::new(out);
+        let package = package.join(".");
+        cat!(ctx, "export namespace {package} {{\n");
+        cat!(ctx +++);
+        gen_import_aliases(&mut ctx, imports);
+        indent_into(&mut ctx, body);
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
 }
 
 fn varname(stack: &[String], name: &str) -> String { format!("{}_{}", stack.join("_"), name) }
 fn bindname(stack: &[String]) -> String { stack.join("_") }
 fn fname(stack: &[String]) -> String { stack.join(".") }
 
-fn gen_write_impl_optional(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
+/// When `masked`, the field's presence is already part of the struct's
+/// bitmask, so no presence byte is written
+fn gen_write_impl_optional(ctx: &mut GenCtx, masked: bool, body: impl Fn(&mut GenCtx)) {
     let fname = self::fname(&ctx.stack);
     let bind_var = bindname(&ctx.stack);
     let mut old_stack = Vec::new();
@@ -29,12 +81,25 @@ fn gen_write_impl_optional(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
     ctx.push_fname(bind_var.clone());
 
     cat!(ctx, "let {bind_var} = {fname};\n");
+    if masked {
+        cat!(ctx, "if ({bind_var} != null) {{\n");
+        cat!(ctx +++);
+        body(ctx);
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        ctx.swap_stack(&mut old_stack);
+        return;
+    }
     cat!(ctx, "switch ({bind_var}) {{\n");
     cat!(ctx +++);
-    cat!(ctx, "case undefined: case null: writer.write_uint8(0); break;\n");
+    let (absent, present) = match ctx.packed {
+        true => ("writer.write_bits(0, 1)", "writer.write_bits(1, 1)"),
+        false => ("writer.write_uint8(0)", "writer.write_uint8(1)"),
+    };
+    cat!(ctx, "case undefined: case null: {absent}; break;\n");
     cat!(ctx, "default: {{\n");
     cat!(ctx +++);
-    cat!(ctx, "writer.write_uint8(1);\n");
+    cat!(ctx, "{present};\n");
 
     body(ctx);
 
@@ -46,7 +111,51 @@ fn gen_write_impl_optional(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
     ctx.swap_stack(&mut old_stack);
 }
 
-fn gen_write_impl_array(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
+/// Conditional fields must be present when `cond` holds, and aren't written
+/// otherwise
+fn gen_write_impl_conditional(ctx: &mut GenCtx, cond: &str, label: &str, body: impl Fn(&mut GenCtx)) {
+    let fname = self::fname(&ctx.stack);
+    let bind_var = bindname(&ctx.stack);
+    let mut old_stack = Vec::new();
+    ctx.swap_stack(&mut old_stack);
+    ctx.push_fname(bind_var.clone());
+
+    cat!(ctx, "if ({cond}) {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "let {bind_var} = {fname};\n");
+    cat!(
+        ctx,
+        "if ({bind_var} === undefined || {bind_var} === null) throw new TypeError(\"{label} is missing\");\n"
+    );
+
+    body(ctx);
+
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+
+    ctx.swap_stack(&mut old_stack);
+}
+
+/// Fields of extensible structs are preceded by their tag and byte length.
+/// The length isn't known until the field is written, so it's patched in
+/// afterwards.
+fn gen_write_impl_tagged(ctx: &mut GenCtx, tag: u16, body: impl Fn(&mut GenCtx)) {
+    let start_var = varname(&ctx.stack, "start");
+    cat!(ctx, "writer.write_uint16({tag});\n");
+    cat!(ctx, "let {start_var} = writer.position();\n");
+    cat!(ctx, "writer.write_uint32(0);\n");
+
+    body(ctx);
+
+    cat!(
+        ctx,
+        "writer.write_uint32_at({start_var}, writer.position() - {start_var} - 4);\n"
+    );
+}
+
+/// `length` is the field holding the array's length, along with the message
+/// thrown when they don't match, if it isn't written before the items
+fn gen_write_impl_array(ctx: &mut GenCtx, length: Option<&(String, String)>, body: impl Fn(&mut GenCtx)) {
     let fname = self::fname(&ctx.stack);
     let item = varname(&ctx.stack, "item");
     let index = varname(&ctx.stack, "index");
@@ -54,7 +163,14 @@ fn gen_write_impl_array(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
     ctx.swap_stack(&mut old_stack);
     ctx.push_fname(item.clone());
 
-    cat!(ctx, "writer.write_uint32({fname}.length);\n");
+    match length {
+        Some((length, message)) => cat!(
+            ctx,
+            "if ({fname}.length !== {length}) throw new RangeError(\"{message}\");\n"
+        ),
+        None if ctx.packed => cat!(ctx, "writer.write_bits({fname}.length, 32);\n"),
+        None => cat!(ctx, "writer.write_uint32({fname}.length);\n"),
+    }
     cat!(ctx, "for (let {index} = 0; {index} < {fname}.length; ++{index}) {{\n");
     //cat!(ctx, "for (let {item} of {fname}) {{\n");
     cat!(ctx +++);
@@ -68,8 +184,42 @@ fn gen_write_impl_array(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
     ctx.swap_stack(&mut old_stack);
 }
 
-fn gen_write_impl_builtin(ctx: &mut GenCtx, ty: &check::Builtin, name: &str) {
+/// Condition which holds when `value` is within `range`. It's negated by the
+/// callers rather than checking for values outside of it, so that `NaN` fails.
+fn in_range(value: &str, range: &check::Range) -> String {
+    let mut conds = Vec::new();
+    if let Some(min) = range.min {
+        conds.push(format!("{} <= {}", min, value));
+    }
+    if let Some(max) = range.max {
+        let op = if range.inclusive { "<=" } else { "<" };
+        conds.push(format!("{} {} {}", value, op, max));
+    }
+    conds.join(" && ")
+}
+
+fn gen_write_impl_builtin(
+    ctx: &mut GenCtx,
+    ty: &check::Builtin,
+    name: &str,
+    range: Option<&check::Range>,
+    label: &str,
+) {
     let fname = self::fname(&ctx.stack);
+    if let Some(range) = range {
+        let value = match ty {
+            check::Builtin::String => format!("{}.length", fname),
+            _ => fname.clone(),
+        };
+        let cond = in_range(&value, range);
+        cat!(
+            ctx,
+            "if (!({cond})) throw new RangeError(\"{label} is out of range\");\n"
+        );
+    }
+    if ctx.packed {
+        return gen_write_packed_builtin(ctx, ty, range);
+    }
     match ty {
         check::Builtin::String => {
             cat!(ctx, "writer.write_uint32({fname}.length);\n");
@@ -87,36 +237,146 @@ fn gen_write_impl_enum(ctx: &mut GenCtx, type_info: &check::Enum, _name: &str) {
         check::EnumRepr::U32 => "uint32",
     };
 
+    if ctx.packed {
+        // the index of the variant's bit
+        let bits = type_info.packed_bits();
+        cat!(ctx, "writer.write_bits(31 - Math.clz32({fname}), {bits});\n");
+        return;
+    }
     cat!(ctx, "writer.write_{repr_name}({fname} as number);\n");
 }
 
-fn gen_write_impl_struct(ctx: &mut GenCtx, ty: &check::Struct, _name: &str) {
-    for f in &ty.fields {
-        ctx.push_fname(f.name);
-        let fty = &*f.r#type.borrow();
+/// Writes a value to the bit stream of a packed export. Integers and string
+/// lengths with both bounds of their range are written as their offset from
+/// the lower one, in as many bits as the range needs.
+fn gen_write_packed_builtin(ctx: &mut GenCtx, ty: &check::Builtin, range: Option<&check::Range>) {
+    let fname = self::fname(&ctx.stack);
+    let (value, width) = match ty {
+        check::Builtin::Float => {
+            cat!(ctx, "writer.write_bits_float({fname});\n");
+            return;
+        }
+        check::Builtin::String => (format!("{}.length", fname), 32),
+        _ => (fname.clone(), packed_width(ty)),
+    };
+    match range.and_then(|range| range.packed_bits()) {
+        Some((min, bits)) => {
+            let offset = offset_from(&value, min);
+            cat!(ctx, "writer.write_bits({offset}, {bits});\n");
+        }
+        None => cat!(ctx, "writer.write_bits({value}, {width});\n"),
+    }
+    if let check::Builtin::String = ty {
+        cat!(ctx, "writer.write_bits_string({fname});\n");
+    }
+}
 
-        use check::ResolvedType::*;
-        let mut generator: Box<dyn Fn(&mut GenCtx)> = match &fty.1 {
-            Builtin(fty_info) => Box::new(move |ctx| gen_write_impl_builtin(ctx, &fty_info, &fty.0)),
-            Enum(fty_info) => Box::new(move |ctx| gen_write_impl_enum(ctx, &fty_info, &fty.0)),
-            Struct(fty_info) => Box::new(move |ctx| gen_write_impl_struct(ctx, &fty_info, &fty.0)),
-        };
-        if f.array {
-            generator = Box::new(move |ctx| gen_write_impl_array(ctx, |ctx| generator(ctx)))
+/// The expression a length-referencing array reads its length from
+fn length_field(ctx: &GenCtx, ty: &check::Struct, field: &check::StructField) -> Option<String> {
+    field.length.map(|length| {
+        let length = ty.fields.iter().find(|f| f.name == length).unwrap();
+        format!("{}.{}", fname(&ctx.stack), length.ident())
+    })
+}
+
+/// The expression which holds when a conditional field is present
+fn condition(ctx: &GenCtx, ty: &check::Struct, field: &check::StructField) -> Option<String> {
+    field.condition.as_ref().map(|cond| {
+        let cond_field = ty.fields.iter().find(|f| f.name == cond.field).unwrap();
+        format!(
+            "{}.{} === {}.{}",
+            fname(&ctx.stack),
+            cond_field.ident(),
+            cond.r#enum,
+            cond.variant
+        )
+    })
+}
+
+/// Writes the presence of the struct's optional fields as a bitmask, one byte
+/// per 8 fields
+fn gen_write_impl_bitmask(ctx: &mut GenCtx, ty: &check::Struct) {
+    let parent = self::fname(&ctx.stack);
+    for chunk in ty.bitmask_fields().chunks(8) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, f)| format!("({}.{} != null ? {} : 0)", parent, f.ident(), 1 << i))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let len = chunk.len();
+        match ctx.packed {
+            true => cat!(ctx, "writer.write_bits({bits}, {len});\n"),
+            false => cat!(ctx, "writer.write_uint8({bits});\n"),
         }
-        if f.optional {
-            generator = Box::new(move |ctx| gen_write_impl_optional(ctx, |ctx| generator(ctx)))
+    }
+}
+
+/// Writes the field `f` of the struct `ty`, which is called `name`
+fn gen_write_impl_field(ctx: &mut GenCtx, ty: &check::Struct, f: &check::StructField, name: &str, masked: bool) {
+    let length = length_field(ctx, ty, f).map(|length| {
+        let message = format!(
+            "{}.{} length does not match {}.{}",
+            name,
+            f.name,
+            name,
+            f.length.unwrap()
+        );
+        (length, message)
+    });
+    let cond = condition(ctx, ty, f);
+    ctx.push_fname(f.ident());
+    let fty = &*f.r#type.borrow();
+    let label = format!("{}.{}", name, f.name);
+
+    use check::ResolvedType::*;
+    let mut generator: Box<dyn Fn(&mut GenCtx)> = match &fty.1 {
+        Builtin(fty_info) => {
+            Box::new(move |ctx| gen_write_impl_builtin(ctx, fty_info, fty.0, f.range.as_ref(), &label))
         }
-        generator(ctx);
+        Enum(fty_info) => Box::new(move |ctx| gen_write_impl_enum(ctx, fty_info, fty.0)),
+        Struct(fty_info) => Box::new(move |ctx| gen_write_impl_struct(ctx, fty_info, fty.0)),
+    };
+    if f.array {
+        generator = Box::new(move |ctx| gen_write_impl_array(ctx, length.as_ref(), |ctx| generator(ctx)))
+    }
+    if let Some(tag) = f.tag {
+        generator = Box::new(move |ctx| gen_write_impl_tagged(ctx, tag, |ctx| generator(ctx)))
+    }
+    if f.optional {
+        generator = Box::new(move |ctx| gen_write_impl_optional(ctx, masked, |ctx| generator(ctx)))
+    }
+    if let Some(cond) = cond {
+        let label = format!("{}.{}", name, f.name);
+        generator = Box::new(move |ctx| gen_write_impl_conditional(ctx, &cond, &label, |ctx| generator(ctx)))
+    }
+    generator(ctx);
 
-        ctx.pop_fname();
+    ctx.pop_fname();
+}
+
+fn gen_write_impl_struct(ctx: &mut GenCtx, ty: &check::Struct, name: &str) {
+    gen_write_impl_bitmask(ctx, ty);
+    // extensible structs omit absent fields instead
+    let masked = !ty.bitmask_fields().is_empty() || ty.is_extensible();
+    for f in &ty.fields {
+        gen_write_impl_field(ctx, ty, f, name, masked);
+    }
+    if ty.is_extensible() {
+        cat!(ctx, "writer.write_uint16(0);\n");
     }
 }
 
-fn gen_read_impl_optional(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
+/// The field is read when `present` holds, which for optional fields is the
+/// presence byte. Without it, the field is known to be present.
+fn gen_read_impl_optional(ctx: &mut GenCtx, present: Option<&str>, body: impl Fn(&mut GenCtx)) {
     let fname = self::fname(&ctx.stack);
+    let present = match present {
+        Some(present) => present,
+        None => return body(ctx),
+    };
 
-    cat!(ctx, "if (reader.read_uint8() > 0) {{\n");
+    cat!(ctx, "if ({present}) {{\n");
     cat!(ctx +++);
     body(ctx);
     cat!(ctx ---);
@@ -127,7 +387,7 @@ fn gen_read_impl_optional(ctx: &mut GenCtx, body: impl Fn(&mut GenCtx)) {
     cat!(ctx, "}}\n");
 }
 
-fn gen_read_impl_array(ctx: &mut GenCtx, init_struct: bool, body: impl Fn(&mut GenCtx)) {
+fn gen_read_impl_array(ctx: &mut GenCtx, init_struct: bool, length: Option<&str>, body: impl Fn(&mut GenCtx)) {
     let len_var = varname(&ctx.stack, "len");
     let fname = self::fname(&ctx.stack);
     let idx_var = varname(&ctx.stack, "index");
@@ -136,7 +396,11 @@ fn gen_read_impl_array(ctx: &mut GenCtx, init_struct: bool, body: impl Fn(&mut G
     ctx.swap_stack(&mut old_stack);
     ctx.push_fname(item_var.clone());
 
-    cat!(ctx, "let {len_var} = reader.read_uint32();\n");
+    match length {
+        Some(length) => cat!(ctx, "let {len_var} = {length};\n"),
+        None if ctx.packed => cat!(ctx, "let {len_var} = reader.read_bits(32);\n"),
+        None => cat!(ctx, "let {len_var} = reader.read_uint32();\n"),
+    }
     cat!(ctx, "{fname} = new Array({len_var});\n");
     cat!(ctx, "for (let {idx_var} = 0; {idx_var} < {len_var}; ++{idx_var}) {{\n");
     cat!(ctx +++);
@@ -153,17 +417,30 @@ fn gen_read_impl_array(ctx: &mut GenCtx, init_struct: bool, body: impl Fn(&mut G
     ctx.swap_stack(&mut old_stack);
 }
 
-fn gen_read_impl_builtin(ctx: &mut GenCtx, type_info: &check::Builtin, type_name: &str) {
+fn gen_read_impl_builtin(ctx: &mut GenCtx, type_info: &check::Builtin, type_name: &str, range: Option<&check::Range>) {
+    let fname = self::fname(&ctx.stack);
+    if ctx.packed {
+        return gen_read_packed_builtin(ctx, type_info, range);
+    }
     match type_info {
         check::Builtin::String => {
             let len_var = varname(&ctx.stack, "len");
             cat!(ctx, "let {len_var} = reader.read_uint32();\n");
-            let fname = self::fname(&ctx.stack);
-            cat!(ctx, "{fname} = reader.read_string({len_var});\n");
+            if let Some(range) = range {
+                // checked before reading, so that a huge string isn't allocated
+                let cond = in_range(&len_var, range);
+                cat!(ctx, "if (!({cond})) reader.failed = true;\n");
+                cat!(ctx, "else {fname} = reader.read_string({len_var});\n");
+            } else {
+                cat!(ctx, "{fname} = reader.read_string({len_var});\n");
+            }
         }
         _ => {
-            let fname = self::fname(&ctx.stack);
-            cat!(ctx, "{fname} = reader.read_{type_name}();\n")
+            cat!(ctx, "{fname} = reader.read_{type_name}();\n");
+            if let Some(range) = range {
+                let cond = in_range(&fname, range);
+                cat!(ctx, "if (!({cond})) reader.failed = true;\n");
+            }
         }
     }
 }
@@ -180,11 +457,171 @@ fn gen_read_impl_enum(ctx: &mut GenCtx, type_info: &check::Enum, _name: &str) {
     );
     let fname = self::fname(&ctx.stack);
     let temp = self::varname(&ctx.stack, "temp");
-    cat!(ctx, "let {temp} = reader.read_{repr_name}();\n");
+    if ctx.packed {
+        let bits = type_info.packed_bits();
+        cat!(ctx, "let {temp} = 1 << reader.read_bits({bits});\n");
+    } else {
+        cat!(ctx, "let {temp} = reader.read_{repr_name}();\n");
+    }
     cat!(ctx, "if ({min} <= {temp} && {temp} <= {max}) {fname} = {temp};\n");
     cat!(ctx, "else reader.failed = true;\n");
 }
 
+/// Reads a value from the bit stream of a packed export. Values which are
+/// out of range after adding the lower bound of it fail the range check.
+fn gen_read_packed_builtin(ctx: &mut GenCtx, type_info: &check::Builtin, range: Option<&check::Range>) {
+    let fname = self::fname(&ctx.stack);
+    let packed = range.and_then(|range| range.packed_bits());
+    let read = |width| match packed {
+        Some((min, bits)) => offset_from(&format!("reader.read_bits({})", bits), -min),
+        None => format!("reader.read_bits({})", width),
+    };
+    let check = |ctx: &mut GenCtx, value: &str| {
+        if let Some(range) = range {
+            let cond = in_range(value, range);
+            cat!(ctx, "if (!({cond})) reader.failed = true;\n");
+        }
+    };
+    match type_info {
+        check::Builtin::Float => cat!(ctx, "{fname} = reader.read_bits_float();\n"),
+        check::Builtin::String => {
+            let len_var = varname(&ctx.stack, "len");
+            let len = read(32);
+            cat!(ctx, "let {len_var} = {len};\n");
+            // checked before reading, so that a huge string isn't allocated
+            if range.is_some() {
+                check(ctx, &len_var);
+                cat!(ctx, "else {fname} = reader.read_bits_string({len_var});\n");
+            } else {
+                cat!(ctx, "{fname} = reader.read_bits_string({len_var});\n");
+            }
+        }
+        _ => {
+            let width = packed_width(type_info);
+            // signed values are sign extended from their width
+            let value = match (packed, type_info) {
+                (None, check::Builtin::Int8) => format!("{} << 24 >> 24", read(width)),
+                (None, check::Builtin::Int16) => format!("{} << 16 >> 16", read(width)),
+                (None, check::Builtin::Int32) => format!("{} | 0", read(width)),
+                _ => read(width),
+            };
+            cat!(ctx, "{fname} = {value};\n");
+            check(ctx, &fname);
+        }
+    }
+}
+
+/// Fields of extensible structs are read in any order, each in the case of
+/// its tag
+fn gen_read_impl_tagged(ctx: &mut GenCtx, tag: u16, body: impl Fn(&mut GenCtx)) {
+    cat!(ctx, "case {tag}: {{\n");
+    cat!(ctx +++);
+
+    body(ctx);
+
+    cat!(ctx, "break;\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+}
+
+/// The value of a field of an extensible struct which wasn't written
+fn default_value(field: &check::StructField) -> String {
+    if field.is_optional() {
+        return "undefined".to_string();
+    }
+    if field.array {
+        return "[]".to_string();
+    }
+    let ty = &*field.r#type.borrow();
+    match &ty.1 {
+        check::ResolvedType::Builtin(check::Builtin::String) => "\"\"".to_string(),
+        check::ResolvedType::Builtin(_) => "0".to_string(),
+        check::ResolvedType::Enum(e) => format!("{}.{}", ty.0, e.variants[0].name),
+        check::ResolvedType::Struct(s) => {
+            let fields = s
+                .fields
+                .iter()
+                .map(|f| format!("{}: {}", f.ident(), default_value(f)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", fields.join(", "))
+        }
+    }
+}
+
+/// Reads the bitmask of the struct's optional fields, returning the condition
+/// which holds when each of them is present
+fn gen_read_impl_bitmask(ctx: &mut GenCtx, ty: &check::Struct) -> Vec<String> {
+    let fields = ty.bitmask_fields();
+    if fields.is_empty() {
+        return Vec::new();
+    }
+    let mask_var = varname(&ctx.stack, "presence");
+    let bytes = fields
+        .chunks(8)
+        .map(|chunk| match ctx.packed {
+            true => format!("reader.read_bits({})", chunk.len()),
+            false => "reader.read_uint8()".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    cat!(ctx, "let {mask_var} = [{bytes}];\n");
+    (0..fields.len())
+        .map(|i| format!("({}[{}] & {}) !== 0", mask_var, i / 8, 1 << (i % 8)))
+        .collect()
+}
+
+/// Reads the field `f` of the struct `ty`. The presence of optional fields
+/// is taken from `presence` if it's masked.
+fn gen_read_impl_field(
+    ctx: &mut GenCtx,
+    ty: &check::Struct,
+    f: &check::StructField,
+    presence: &mut dyn Iterator<Item = String>,
+) {
+    let length = length_field(ctx, ty, f);
+    let cond = condition(ctx, ty, f);
+    ctx.push_fname(f.ident());
+    let fty = &*f.r#type.borrow();
+
+    use check::ResolvedType::*;
+    let mut init_struct = false;
+    let mut generator: Rc<dyn Fn(&mut GenCtx)> = match &fty.1 {
+        Builtin(fty_info) => Rc::new(move |ctx| gen_read_impl_builtin(ctx, fty_info, fty.0, f.range.as_ref())),
+        Enum(fty_info) => Rc::new(move |ctx| gen_read_impl_enum(ctx, fty_info, fty.0)),
+        Struct(fty_info) => {
+            init_struct = true;
+            Rc::new(move |ctx| gen_read_impl_struct(ctx, fty_info, fty.0, !f.array))
+        }
+    };
+    if f.array {
+        let current_generator = generator.clone();
+        let length = length.clone();
+        generator =
+            Rc::new(move |ctx| gen_read_impl_array(ctx, init_struct, length.as_deref(), |ctx| current_generator(ctx)))
+    }
+    if f.optional {
+        let current_generator = generator.clone();
+        let present = match f.tag {
+            Some(_) => None,
+            None => Some(presence.next().unwrap_or_else(|| match ctx.packed {
+                true => "reader.read_bits(1) !== 0".to_string(),
+                false => "reader.read_uint8() > 0".to_string(),
+            })),
+        };
+        generator = Rc::new(move |ctx| gen_read_impl_optional(ctx, present.as_deref(), |ctx| current_generator(ctx)))
+    }
+    if let Some(cond) = cond {
+        let current_generator = generator.clone();
+        generator = Rc::new(move |ctx| gen_read_impl_optional(ctx, Some(&cond), |ctx| current_generator(ctx)))
+    }
+    if let Some(tag) = f.tag {
+        let current_generator = generator.clone();
+        generator = Rc::new(move |ctx| gen_read_impl_tagged(ctx, tag, |ctx| current_generator(ctx)))
+    }
+    generator(ctx);
+    ctx.pop_fname();
+}
+
 fn gen_read_impl_struct(ctx: &mut GenCtx, ty: &check::Struct, _name: &str, init: bool) {
     let fname = self::fname(&ctx.stack);
     let bind_var = bindname(&ctx.stack);
@@ -200,30 +637,34 @@ fn gen_read_impl_struct(ctx: &mut GenCtx, ty: &check::Struct, _name: &str, init:
         None
     };
 
-    for f in &ty.fields {
-        ctx.push_fname(f.name);
-        let fty = &*f.r#type.borrow();
-
-        use check::ResolvedType::*;
-        let mut init_struct = false;
-        let mut generator: Rc<dyn Fn(&mut GenCtx)> = match &fty.1 {
-            Builtin(fty_info) => Rc::new(move |ctx| gen_read_impl_builtin(ctx, &fty_info, &fty.0)),
-            Enum(fty_info) => Rc::new(move |ctx| gen_read_impl_enum(ctx, &fty_info, &fty.0)),
-            Struct(fty_info) => {
-                init_struct = true;
-                Rc::new(move |ctx| gen_read_impl_struct(ctx, &fty_info, &fty.0, !f.array))
-            }
-        };
-        if f.array {
-            let current_generator = generator.clone();
-            generator = Rc::new(move |ctx| gen_read_impl_array(ctx, init_struct, |ctx| current_generator(ctx)))
-        }
-        if f.optional {
-            let current_generator = generator.clone();
-            generator = Rc::new(move |ctx| gen_read_impl_optional(ctx, |ctx| current_generator(ctx)))
+    let mut presence = gen_read_impl_bitmask(ctx, ty).into_iter();
+    // fields of extensible structs are read until the terminating tag, and
+    // the ones which aren't known are skipped. The missing ones are defaulted.
+    let tag_var = varname(&ctx.stack, "tag");
+    let len_var = varname(&ctx.stack, "len");
+    if ty.is_extensible() {
+        let parent = self::fname(&ctx.stack);
+        for f in &ty.fields {
+            let (ident, value) = (f.ident(), default_value(f));
+            cat!(ctx, "{parent}.{ident} = {value};\n");
         }
-        generator(ctx);
-        ctx.pop_fname();
+        cat!(ctx, "for (;;) {{\n");
+        cat!(ctx +++);
+        cat!(ctx, "let {tag_var} = reader.read_uint16();\n");
+        cat!(ctx, "if ({tag_var} === 0 || reader.failed) break;\n");
+        cat!(ctx, "let {len_var} = reader.read_uint32();\n");
+        cat!(ctx, "switch ({tag_var}) {{\n");
+        cat!(ctx +++);
+    }
+    for f in &ty.fields {
+        gen_read_impl_field(ctx, ty, f, &mut presence);
+    }
+    if ty.is_extensible() {
+        cat!(ctx, "default: reader.skip({len_var});\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
     }
 
     if let Some(mut old_stack) = old_stack {
@@ -233,34 +674,312 @@ fn gen_read_impl_struct(ctx: &mut GenCtx, ty: &check::Struct, _name: &str, init:
     }
 }
 
-fn field_ctor_type(ty: &(&str, check::ResolvedType), export: &str, array: bool, optional: bool) -> String {
+/// Fields of a struct which are delta encoded themselves, rather than being
+/// written in full when they change. Extensible structs are always written
+/// in full, as their fields can only be read in a tagged loop.
+fn delta_struct<'a, 'b>(
+    ty: &'b (&str, check::ResolvedType<'a>),
+    f: &check::StructField,
+) -> Option<&'b check::Struct<'a>> {
+    match &ty.1 {
+        check::ResolvedType::Struct(s) if !f.is_optional() && !s.is_extensible() => Some(s),
+        _ => None,
+    }
+}
+
+/// Expression which holds when `a` and `b`, the values of the field `f`, are
+/// deeply equal. `depth` keeps the names of nested closures' parameters apart.
+fn field_equals(a: &str, b: &str, f: &check::StructField, depth: usize) -> String {
+    let ty = &*f.r#type.borrow();
+    let value_equals = |a: &str, b: &str| match &ty.1 {
+        check::ResolvedType::Struct(s) => struct_equals(a, b, s, depth + 1),
+        _ => format!("{} === {}", a, b),
+    };
+    let equals = if f.array {
+        let (item, index) = (format!("item{}", depth), format!("index{}", depth));
+        let items = value_equals(&item, &format!("{}[{}]", b, index));
+        format!(
+            "{}.length === {}.length && {}.every(({}, {}) => {})",
+            a, b, a, item, index, items
+        )
+    } else {
+        value_equals(a, b)
+    };
+    if f.is_optional() {
+        format!("({} == null ? {} == null : {} != null && {})", a, b, b, equals)
+    } else {
+        equals
+    }
+}
+
+fn struct_equals(a: &str, b: &str, ty: &check::Struct, depth: usize) -> String {
+    if ty.fields.is_empty() {
+        return "true".to_string();
+    }
+    let fields = ty
+        .fields
+        .iter()
+        .map(|f| {
+            let ident = f.ident();
+            field_equals(&format!("{}.{}", a, ident), &format!("{}.{}", b, ident), f, depth)
+        })
+        .collect::<Vec<_>>();
+    format!("({})", fields.join(" && "))
+}
+
+/// Writes which of the struct's fields differ from `baseline`, as a bitmask
+/// with one byte per 8 fields. Conditional fields also count as changed when
+/// their condition field did, so that they're written whenever they appear.
+/// Returns the condition which holds when each of them changed.
+fn gen_write_delta_mask(ctx: &mut GenCtx, ty: &check::Struct, baseline: &str) -> Vec<String> {
+    let parent = self::fname(&ctx.stack);
+    let mask_var = varname(&ctx.stack, "changed");
+    cat!(ctx, "let {mask_var} = [\n");
+    cat!(ctx +++);
+    for chunk in ty.fields.chunks(8) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let ident = f.ident();
+                let a = format!("{}.{}", parent, ident);
+                let b = format!("{}.{}", baseline, ident);
+                let mut equals = field_equals(&a, &b, f, 0);
+                if let Some(cond) = &f.condition {
+                    let cond_field = ty.fields.iter().find(|c| c.name == cond.field).unwrap();
+                    let ident = cond_field.ident();
+                    equals = format!("{} && {}.{} === {}.{}", equals, parent, ident, baseline, ident);
+                }
+                format!("(!({}) ? {} : 0)", equals, 1 << i)
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+        cat!(ctx, "{bits},\n");
+    }
+    cat!(ctx ---);
+    cat!(ctx, "];\n");
+    for i in 0..ty.fields.len().div_ceil(8) {
+        cat!(ctx, "writer.write_uint8({mask_var}[{i}]);\n");
+    }
+    (0..ty.fields.len())
+        .map(|i| format!("({}[{}] & {}) !== 0", mask_var, i / 8, 1 << (i % 8)))
+        .collect()
+}
+
+/// Items which have one at the same index in the baseline are delta encoded
+/// against it, and the rest are written in full
+fn gen_write_delta_array(
+    ctx: &mut GenCtx,
+    ty: &check::Struct,
+    f: &check::StructField,
+    (item_name, item_ty): (&str, &check::Struct),
+    name: &str,
+    baseline: &str,
+) {
+    let length = length_field(ctx, ty, f);
+    ctx.push_fname(f.ident());
+    let fname = self::fname(&ctx.stack);
+    let index_var = varname(&ctx.stack, "index");
+    let item_var = varname(&ctx.stack, "item");
+    let baseline_var = varname(&ctx.stack, "baseline");
+    let mut old_stack = vec![item_var.clone()];
+    ctx.swap_stack(&mut old_stack);
+
+    match length {
+        Some(length) => {
+            let message = format!(
+                "{}.{} length does not match {}.{}",
+                name,
+                f.name,
+                name,
+                f.length.unwrap()
+            );
+            cat!(
+                ctx,
+                "if ({fname}.length !== {length}) throw new RangeError(\"{message}\");\n"
+            );
+        }
+        None => cat!(ctx, "writer.write_uint32({fname}.length);\n"),
+    }
+    let ident = f.ident();
+    cat!(
+        ctx,
+        "for (let {index_var} = 0; {index_var} < {fname}.length; ++{index_var}) {{\n"
+    );
+    cat!(ctx +++);
+    cat!(ctx, "let {item_var} = {fname}[{index_var}];\n");
+    cat!(ctx, "if ({index_var} < {baseline}.{ident}.length) {{\n");
+    cat!(ctx +++);
+    cat!(ctx, "let {baseline_var} = {baseline}.{ident}[{index_var}];\n");
+    gen_write_delta_struct(ctx, item_ty, item_name, &baseline_var);
+    cat!(ctx ---);
+    cat!(ctx, "}} else {{\n");
+    cat!(ctx +++);
+    gen_write_impl_struct(ctx, item_ty, item_name);
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+
+    ctx.swap_stack(&mut old_stack);
+    ctx.pop_fname();
+}
+
+/// Writes the fields which differ from `baseline`, the expression holding
+/// the struct's previous value
+fn gen_write_delta_struct(ctx: &mut GenCtx, ty: &check::Struct, name: &str, baseline: &str) {
+    if ty.fields.is_empty() {
+        return;
+    }
+    let changed = gen_write_delta_mask(ctx, ty, baseline);
+    for (f, changed) in ty.fields.iter().zip(changed) {
+        cat!(ctx, "if ({changed}) {{\n");
+        cat!(ctx +++);
+        let fty = &*f.r#type.borrow();
+        match delta_struct(fty, f) {
+            Some(s) if f.array => gen_write_delta_array(ctx, ty, f, (fty.0, s), name, baseline),
+            Some(s) => {
+                ctx.push_fname(f.ident());
+                gen_write_delta_struct(ctx, s, fty.0, &format!("{}.{}", baseline, f.ident()));
+                ctx.pop_fname();
+            }
+            None => gen_write_impl_field(ctx, ty, f, name, false),
+        }
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
+}
+
+/// Items which were already in the baseline are read as a delta from it, and
+/// the new ones in full
+fn gen_read_delta_array(
+    ctx: &mut GenCtx,
+    ty: &check::Struct,
+    f: &check::StructField,
+    (item_name, item_ty): (&str, &check::Struct),
+    baseline: &str,
+) {
+    let length = length_field(ctx, ty, f);
+    ctx.push_fname(f.ident());
+    let fname = self::fname(&ctx.stack);
+    let len_var = varname(&ctx.stack, "len");
+    let index_var = varname(&ctx.stack, "index");
+    let item_var = varname(&ctx.stack, "item");
+    let mut old_stack = vec![item_var.clone()];
+    ctx.swap_stack(&mut old_stack);
+
+    match length {
+        Some(length) => cat!(ctx, "let {len_var} = {length};\n"),
+        None => cat!(ctx, "let {len_var} = reader.read_uint32();\n"),
+    }
+    let ident = f.ident();
+    cat!(ctx, "{fname} = new Array({len_var});\n");
+    cat!(
+        ctx,
+        "for (let {index_var} = 0; {index_var} < {len_var}; ++{index_var}) {{\n"
+    );
+    cat!(ctx +++);
+    cat!(ctx, "let {item_var}: any = {{}};\n");
+    cat!(ctx, "if ({index_var} < {baseline}.{ident}.length) {{\n");
+    cat!(ctx +++);
+    gen_read_delta_struct(ctx, item_ty, &format!("{}.{}[{}]", baseline, ident, index_var));
+    cat!(ctx ---);
+    cat!(ctx, "}} else {{\n");
+    cat!(ctx +++);
+    gen_read_impl_struct(ctx, item_ty, item_name, false);
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+    cat!(ctx, "{fname}[{index_var}] = {item_var};\n");
+    cat!(ctx ---);
+    cat!(ctx, "}}\n");
+
+    ctx.swap_stack(&mut old_stack);
+    ctx.pop_fname();
+}
+
+/// Reads the struct as the fields which changed from `baseline`, the
+/// expression holding its previous value. The others are taken from it.
+fn gen_read_delta_struct(ctx: &mut GenCtx, ty: &check::Struct, baseline: &str) {
+    if ty.fields.is_empty() {
+        return;
+    }
+    let parent = self::fname(&ctx.stack);
+    let mask_var = varname(&ctx.stack, "changed");
+    let bytes = vec!["reader.read_uint8()"; ty.fields.len().div_ceil(8)].join(", ");
+    cat!(ctx, "let {mask_var} = [{bytes}];\n");
+    for (i, f) in ty.fields.iter().enumerate() {
+        let (byte, bit) = (i / 8, 1 << (i % 8));
+        let ident = f.ident();
+        cat!(ctx, "if (({mask_var}[{byte}] & {bit}) !== 0) {{\n");
+        cat!(ctx +++);
+        let fty = &*f.r#type.borrow();
+        match delta_struct(fty, f) {
+            Some(s) if f.array => gen_read_delta_array(ctx, ty, f, (fty.0, s), baseline),
+            Some(s) => {
+                ctx.push_fname(ident);
+                let bind_var = bindname(&ctx.stack);
+                let mut old_stack = vec![bind_var.clone()];
+                ctx.swap_stack(&mut old_stack);
+                cat!(ctx, "let {bind_var}: any = {{}};\n");
+                gen_read_delta_struct(ctx, s, &format!("{}.{}", baseline, ident));
+                cat!(ctx, "{parent}.{ident} = {bind_var};\n");
+                ctx.swap_stack(&mut old_stack);
+                ctx.pop_fname();
+            }
+            None => gen_read_impl_field(ctx, ty, f, &mut std::iter::empty()),
+        }
+        cat!(ctx ---);
+        cat!(ctx, "}} else {{\n");
+        cat!(ctx +++);
+        cat!(ctx, "{parent}.{ident} = {baseline}.{ident};\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
+}
+
+fn field_ctor_type(ty: &(&str, check::ResolvedType), array: bool, optional: bool) -> String {
     let (mut name, rty) = ty;
-    let mut needs_export_prefix = false;
     match *rty {
         check::ResolvedType::Builtin(check::Builtin::String) => name = "string",
         check::ResolvedType::Builtin(_) => name = "number",
-        _ => needs_export_prefix = true,
+        _ => (),
     }
     format_f!(
-        "{prefix}{dot}{name}{arr}{opt}",
-        prefix = if needs_export_prefix { export } else { "" },
-        dot = if needs_export_prefix { "." } else { "" },
+        "{name}{arr}{opt}",
         arr = if array { "[]" } else { "" },
         opt = if optional { " | undefined" } else { "" }
     )
 }
 
+impl Fingerprint for TypeScript {
+    fn gen_fingerprint(&self, fingerprint: u32, out: &mut String) {
+        let ctx = GenCtx::new(out);
+
+        cat!(ctx, "/** Differs between schemas which aren't wire-compatible */\n");
+        cat!(ctx, "export const SCHEMA_FINGERPRINT = {fingerprint:#010x};\n");
+    }
+}
+
 impl Impl for TypeScript {
     fn gen_impl<'a>(&self, export: &check::Export, out: &mut String) {
         let mut ctx = GenCtx::new(out);
+        ctx.packed = export.r#struct.is_packed();
 
-        cat!(ctx, "export class {export.name} {{\n");
+        gen_doc(&mut ctx, &export.r#struct.doc, &export.r#struct.attrs);
+        // the constructor takes every field, so the base is only implemented
+        let implements = match &export.r#struct.base {
+            Some(base) => format!(" implements {}", base.borrow().0),
+            None => String::new(),
+        };
+        cat!(ctx, "export class {export.name}{implements} {{\n");
         cat!(ctx +++);
         cat!(ctx, "constructor(\n");
         cat!(ctx +++);
         for field in export.r#struct.fields.iter() {
-            let field_type = field_ctor_type(&(*field.r#type.borrow()), export.name, field.array, field.optional);
-            cat!(ctx, "public {field.name}: {field_type},\n");
+            let field_type = field_ctor_type(&field.r#type.borrow(), field.array, field.is_optional());
+            gen_doc(&mut ctx, &field.doc, &field.attrs);
+            let ident = field.ident();
+            cat!(ctx, "public {ident}: {field_type},\n");
         }
         cat!(ctx ---);
         cat!(ctx, ") {{}}\n");
@@ -269,8 +988,8 @@ impl Impl for TypeScript {
         cat!(ctx, "static read(data: ArrayBuffer): {export.name} | null {{\n");
         cat!(ctx +++);
         cat!(ctx, "let reader = new Reader(data);\n");
-        cat!(ctx, "let output = Object.create({export.name});\n");
-        gen_read_impl_struct(&mut ctx, &export.r#struct, &export.name, false);
+        cat!(ctx, "let output = Object.create({export.name}.prototype);\n");
+        gen_read_impl_struct(&mut ctx, &export.r#struct, export.name, false);
         cat!(ctx, "if (reader.failed) return null;\n");
         cat!(ctx, "return output;\n");
         cat!(ctx ---);
@@ -281,42 +1000,200 @@ impl Impl for TypeScript {
         cat!(ctx, "write(buffer?: ArrayBuffer): ArrayBuffer {{\n");
         cat!(ctx +++);
         cat!(ctx, "let writer = buffer ? new Writer(buffer) : new Writer();\n");
-        gen_write_impl_struct(&mut ctx, &export.r#struct, &export.name);
+        gen_write_impl_struct(&mut ctx, &export.r#struct, export.name);
+        if ctx.packed {
+            // pads the last byte of the bit stream
+            cat!(ctx, "writer.flush_bits();\n");
+        }
         cat!(ctx, "return writer.finish();\n");
         cat!(ctx ---);
         cat!(ctx, "}}\n");
         ctx.pop_fname();
 
+        if export.r#struct.is_delta() {
+            ctx.push_fname("output");
+            cat!(
+                ctx,
+                "static read_delta(data: ArrayBuffer, baseline: {export.name}): {export.name} | null {{\n"
+            );
+            cat!(ctx +++);
+            cat!(ctx, "let reader = new Reader(data);\n");
+            cat!(ctx, "let output = Object.create({export.name}.prototype);\n");
+            gen_read_delta_struct(&mut ctx, &export.r#struct, "baseline");
+            cat!(ctx, "if (reader.failed) return null;\n");
+            cat!(ctx, "return output;\n");
+            cat!(ctx ---);
+            cat!(ctx, "}}\n");
+            ctx.pop_fname();
+
+            ctx.push_fname("this");
+            cat!(
+                ctx,
+                "write_delta(baseline: {export.name}, buffer?: ArrayBuffer): ArrayBuffer {{\n"
+            );
+            cat!(ctx +++);
+            cat!(ctx, "let writer = buffer ? new Writer(buffer) : new Writer();\n");
+            gen_write_delta_struct(&mut ctx, &export.r#struct, export.name, "baseline");
+            cat!(ctx, "return writer.finish();\n");
+            cat!(ctx ---);
+            cat!(ctx, "}}\n");
+            ctx.pop_fname();
+        }
+
         cat!(ctx ---);
         cat!(ctx, "}}\n");
     }
 }
 
+impl Message for TypeScript {
+    fn gen_message(&self, exports: &[check::Export], out: &mut String) {
+        let mut ctx = GenCtx::new(out);
+
+        cat!(ctx, "export type Message =\n");
+        cat!(ctx +++);
+        for (i, export) in exports.iter().enumerate() {
+            let id = export.id.unwrap();
+            let end = if i + 1 == exports.len() { ";" } else { "" };
+            cat!(ctx, "| [{id}, {export.name}]{end}\n");
+        }
+        cat!(ctx ---);
+
+        cat!(ctx, "export function read_any(data: ArrayBuffer): Message | null {{\n");
+        cat!(ctx +++);
+        cat!(ctx, "let reader = new Reader(data);\n");
+        cat!(ctx, "let id = reader.read_uint8();\n");
+        cat!(ctx, "if (reader.failed) return null;\n");
+        cat!(ctx, "switch (id) {{\n");
+        cat!(ctx +++);
+        for export in exports.iter() {
+            let id = export.id.unwrap();
+            cat!(ctx, "case {id}: {{\n");
+            cat!(ctx +++);
+            cat!(ctx, "let packet = {export.name}.read(data.slice(1));\n");
+            cat!(ctx, "return packet ? [{id}, packet] : null;\n");
+            cat!(ctx ---);
+            cat!(ctx, "}}\n");
+        }
+        cat!(ctx, "default: return null;\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+
+        cat!(ctx, "export function write_any(msg: Message): ArrayBuffer {{\n");
+        cat!(ctx +++);
+        cat!(ctx, "let packet = new Uint8Array(msg[1].write());\n");
+        cat!(ctx, "let output = new Uint8Array(packet.length + 1);\n");
+        cat!(ctx, "output[0] = msg[0];\n");
+        cat!(ctx, "output.set(packet, 1);\n");
+        cat!(ctx, "return output.buffer;\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
+}
+
+impl Handler for TypeScript {
+    fn gen_handler(&self, exports: &[check::Export], out: &mut String) {
+        let mut ctx = GenCtx::new(out);
+
+        cat!(ctx, "export interface Handler {{\n");
+        cat!(ctx +++);
+        for export in exports.iter() {
+            let method = snake_case(export.name);
+            cat!(ctx, "on_{method}(packet: {export.name}): void;\n");
+        }
+        cat!(ctx, "on_error(data: ArrayBuffer): void;\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+
+        cat!(
+            ctx,
+            "export function dispatch(data: ArrayBuffer, handler: Handler) {{\n"
+        );
+        cat!(ctx +++);
+        cat!(ctx, "let msg = read_any(data);\n");
+        cat!(ctx, "if (msg === null) return handler.on_error(data);\n");
+        cat!(ctx, "switch (msg[0]) {{\n");
+        cat!(ctx +++);
+        for export in exports.iter() {
+            let id = export.id.unwrap();
+            let method = snake_case(export.name);
+            cat!(ctx, "case {id}: return handler.on_{method}(msg[1]);\n");
+        }
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+        cat!(ctx ---);
+        cat!(ctx, "}}\n");
+    }
+}
+
+/// Emits a doc comment as JSDoc, so that it shows up in IDE hovers.
+/// Attributes are emitted as JSDoc tags.
+fn gen_doc(ctx: &mut GenCtx, doc: &[&str], attrs: &[check::Attribute]) {
+    // a `*/` inside of the comment would end it early
+    let escape = |line: &str| line.replace("*/", "*\\/");
+    let tags = attrs.iter().filter_map(|attr| match attr {
+        check::Attribute::Deprecated(None) => Some("@deprecated".to_string()),
+        check::Attribute::Deprecated(Some(reason)) => Some(format!("@deprecated {}", reason)),
+        check::Attribute::Since(version) => Some(format!("@since {}", version)),
+        check::Attribute::Rename(_)
+        | check::Attribute::Bitmask
+        | check::Attribute::Extensible
+        | check::Attribute::Delta
+        | check::Attribute::Packed => None,
+    });
+    let lines = doc.iter().map(|line| line.to_string()).chain(tags).collect::<Vec<_>>();
+    match &lines[..] {
+        [] => (),
+        [line] => {
+            let line = escape(line);
+            cat!(ctx, "/** {line} */\n")
+        }
+        lines => {
+            cat!(ctx, "/**\n");
+            for line in lines {
+                let line = escape(line);
+                cat!(ctx, " * {line}\n");
+            }
+            cat!(ctx, " */\n");
+        }
+    }
+}
+
 fn gen_struct_decl(ctx: &mut GenCtx, ty: &check::Struct, name: &str) {
-    cat!(ctx, "export interface {name} {{\n");
+    gen_doc(ctx, &ty.doc, &ty.attrs);
+    let extends = match &ty.base {
+        Some(base) => format!(" extends {}", base.borrow().0),
+        None => String::new(),
+    };
+    cat!(ctx, "export interface {name}{extends} {{\n");
     cat!(ctx +++);
-    for field in ty.fields.iter() {
+    for field in ty.own_fields() {
         let type_info = &*field.r#type.borrow();
         let typename: &str = match &type_info.1 {
             check::ResolvedType::Builtin(b) => match b {
                 check::Builtin::String => "string",
                 _ => "number",
             },
-            _ => &type_info.0,
+            _ => type_info.0,
         };
-        let opt = if field.optional { "?" } else { "" };
+        let opt = if field.is_optional() { "?" } else { "" };
         let arr = if field.array { "[]" } else { "" };
 
-        cat!(ctx, "{field.name}{opt}: {typename}{arr},\n");
+        gen_doc(ctx, &field.doc, &field.attrs);
+        let ident = field.ident();
+        cat!(ctx, "{ident}{opt}: {typename}{arr},\n");
     }
     cat!(ctx ---);
     cat!(ctx, "}}\n");
 }
 
 fn gen_enum_decl(ctx: &mut GenCtx, ty: &check::Enum, name: &str) {
+    gen_doc(ctx, &ty.doc, &ty.attrs);
     cat!(ctx, "export const enum {name} {{\n");
     cat!(ctx +++);
     for variant in ty.variants.iter() {
+        gen_doc(ctx, &variant.doc, &[]);
         cat!(ctx, "{variant.name} = 1 << {variant.value},\n");
     }
     cat!(ctx ---);
@@ -324,84 +1201,1137 @@ fn gen_enum_decl(ctx: &mut GenCtx, ty: &check::Enum, name: &str) {
 }
 
 impl Declaration for TypeScript {
-    fn gen_decls<'a>(&self, types: &check::TypeMap<'a>, export: &str, out: &mut String) {
+    fn gen_decls<'a>(
+        &self,
+        types: &[check::Ptr<(&'a str, check::ResolvedType<'a>)>],
+        exports: &[&str],
+        out: &mut String,
+    ) {
         let mut ctx = GenCtx::new(out);
 
-        cat!(ctx, "export namespace {export} {{\n");
-        cat!(ctx +++);
-        for (name, ty) in types.iter() {
-            if *name == export {
+        for ty in types.iter() {
+            let (name, ty) = &*ty.borrow();
+            // exported types are declared by their class
+            if exports.contains(name) {
                 continue;
             }
 
-            match &(*ty.borrow()).1 {
+            match ty {
                 check::ResolvedType::Builtin(_) => (),
                 check::ResolvedType::Enum(ty) => gen_enum_decl(&mut ctx, ty, name),
                 check::ResolvedType::Struct(ty) => gen_struct_decl(&mut ctx, ty, name),
             }
         }
-        cat!(ctx ---);
-        cat!(ctx, "}}\n");
     }
 }
-
-#[cfg(test)]
-mod tests {
-    use pretty_assertions::assert_eq;
-
-    use super::*;
-
-    #[test]
-    fn commmon_gen() {
-        let mut gen = Generator::<TypeScript>::new();
-        gen.push_line();
-        gen.push_common();
-        let actual = gen.finish();
-        assert_eq!(
-            actual,
-            "
-import { Reader, Writer } from \"packet\";
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn commmon_gen() {
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_common();
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+import { Reader, Writer } from \"packet\";
+"
+        );
+    }
+
+    #[test]
+    fn fingerprint_gen() {
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_fingerprint(0xbeef);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+/** Differs between schemas which aren't wire-compatible */
+export const SCHEMA_FINGERPRINT = 0x0000beef;
+"
+        );
+    }
+
+    #[test]
+    fn imports_gen() {
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_imports(
+            &[],
+            &[
+                check::ImportedTypes {
+                    module: "./shared".to_string(),
+                    package: vec![],
+                    names: vec!["Flag", "Position"],
+                },
+                check::ImportedTypes {
+                    module: "../common/entity".to_string(),
+                    package: vec!["game", "common"],
+                    names: vec!["Entity"],
+                },
+            ],
+        );
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+import { Flag, Position } from \"./shared\";
+import * as common_entity from \"../common/entity\";
+import Entity = common_entity.game.common.Entity;
+"
+        );
+    }
+
+    #[test]
+    fn attributes_gen() {
+        use check::*;
+        let types = vec![Ptr::new((
+            "Test",
+            ResolvedType::Struct(Struct {
+                fields: vec![
+                    StructField {
+                        name: "hp",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: false,
+                        optional: false,
+                        doc: vec!["Health points"],
+                        attrs: vec![Attribute::Rename("health"), Attribute::Since("1.2")],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "old",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![Attribute::Deprecated(None)],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Deprecated(Some("use Other"))],
+                base: None,
+            }),
+        ))];
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_decls(&types, &[]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+/** @deprecated use Other */
+export interface Test {
+    /**
+     * Health points
+     * @since 1.2
+     */
+    health: number,
+    /** @deprecated */
+    old: number,
+}
+"
+        );
+    }
+
+    #[test]
+    fn package_gen() {
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_package(
+            &["game", "combat"],
+            &[check::ImportedTypes {
+                module: "./shared".to_string(),
+                package: vec!["game", "common"],
+                names: vec!["Position"],
+            }],
+            "// body\n",
+        );
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export namespace game.combat {
+    import Position = shared.game.common.Position;
+    // body
+}
+"
+        );
+    }
+
+    #[test]
+    fn doc_gen() {
+        use check::*;
+        let types = vec![
+            Ptr::new((
+                "Flag",
+                ResolvedType::Enum(Enum {
+                    repr: EnumRepr::U8,
+                    variants: vec![EnumVariant {
+                        name: "A",
+                        value: 0,
+                        doc: vec!["The first */ flag"],
+                    }],
+                    doc: vec!["Some flags", "used for testing"],
+                    attrs: vec![],
+                }),
+            )),
+            Ptr::new((
+                "Position",
+                ResolvedType::Struct(Struct {
+                    fields: vec![StructField {
+                        name: "x",
+                        r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                        array: false,
+                        optional: false,
+                        doc: vec!["Horizontal position"],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    }],
+                    doc: vec!["A position"],
+                    attrs: vec![],
+                    base: None,
+                }),
+            )),
+        ];
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_decls(&types, &[]);
+        let actual = gen.finish();
+        assert!(actual.contains(
+            "
+/**
+ * Some flags
+ * used for testing
+ */
+export const enum Flag {
+    /** The first *\\/ flag */
+    A = 1 << 0,
+}
+"
+        ));
+        assert!(actual.contains(
+            "
+/** A position */
+export interface Position {
+    /** Horizontal position */
+    x: number,
+}
+"
+        ));
+    }
+
+    #[test]
+    fn extends_gen() {
+        use check::*;
+        let field = |name| StructField {
+            name,
+            r#type: Ptr::new(("uint32", ResolvedType::Builtin(Builtin::Uint32))),
+            array: false,
+            optional: false,
+            doc: vec![],
+            attrs: vec![],
+            range: None,
+            length: None,
+            condition: None,
+            tag: None,
+        };
+        let entity = Ptr::new((
+            "Entity",
+            ResolvedType::Struct(Struct {
+                fields: vec![field("id")],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            }),
+        ));
+        let types = vec![
+            entity.clone(),
+            Ptr::new((
+                "Player",
+                ResolvedType::Struct(Struct {
+                    fields: vec![field("id"), field("score")],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: Some(entity),
+                }),
+            )),
+        ];
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_decls(&types, &[]);
+        let actual = gen.finish();
+        assert!(actual.contains(
+            "
+export interface Player extends Entity {
+    score: number,
+}
+"
+        ));
+        assert!(actual.contains(
+            "
+export interface Entity {
+    id: number,
+}
+"
+        ));
+    }
+
+    #[test]
+    fn simple_struct_gen() {
+        use check::*;
+        let types = vec![Ptr::new((
+            "Position",
+            ResolvedType::Struct(Struct {
+                fields: vec![
+                    StructField {
+                        name: "x",
+                        r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "y",
+                        r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            }),
+        ))];
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_decls(&types, &["Test"]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export interface Position {
+    x: number,
+    y: number,
+}
+"
+        );
+    }
+
+    #[test]
+    fn struct_with_optional_gen() {
+        use check::*;
+        let types = vec![Ptr::new((
+            "A",
+            ResolvedType::Struct(Struct {
+                fields: vec![
+                    StructField {
+                        name: "a",
+                        r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                        array: false,
+                        optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "b",
+                        r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                        array: true,
+                        optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "c",
+                        r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            }),
+        ))];
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_decls(&types, &["Test"]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export interface A {
+    a?: number,
+    b?: number[],
+    c: number,
+}
+"
+        );
+    }
+
+    #[test]
+    fn enum_gen() {
+        use check::*;
+        let types = vec![Ptr::new((
+            "Flag",
+            ResolvedType::Enum(Enum {
+                repr: EnumRepr::U8,
+                variants: vec![
+                    EnumVariant {
+                        name: "A",
+                        value: 0,
+                        doc: vec![],
+                    },
+                    EnumVariant {
+                        name: "B",
+                        value: 1,
+                        doc: vec![],
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+            }),
+        ))];
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_decls(&types, &["Test"]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export const enum Flag {
+    A = 1 << 0,
+    B = 1 << 1,
+}
+"
+        );
+    }
+
+    #[test]
+    fn complex_struct_gen() {
+        use check::*;
+        let types = vec![Ptr::new((
+            "A",
+            ResolvedType::Struct(Struct {
+                fields: vec![
+                    StructField {
+                        name: "builtin_scalar",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "builtin_array",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "string_scalar",
+                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "string_array",
+                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "enum_scalar",
+                        r#type: Ptr::new((
+                            "Flag",
+                            ResolvedType::Enum(Enum {
+                                repr: EnumRepr::U8,
+                                variants: vec![],
+                                doc: vec![],
+                                attrs: vec![],
+                            }),
+                        )),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "enum_array",
+                        r#type: Ptr::new((
+                            "Flag",
+                            ResolvedType::Enum(Enum {
+                                repr: EnumRepr::U8,
+                                variants: vec![],
+                                doc: vec![],
+                                attrs: vec![],
+                            }),
+                        )),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "struct_scalar",
+                        r#type: Ptr::new((
+                            "Position",
+                            ResolvedType::Struct(Struct {
+                                fields: vec![],
+                                doc: vec![],
+                                attrs: vec![],
+                                base: None,
+                            }),
+                        )),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "struct_array",
+                        r#type: Ptr::new((
+                            "Position",
+                            ResolvedType::Struct(Struct {
+                                fields: vec![],
+                                doc: vec![],
+                                attrs: vec![],
+                                base: None,
+                            }),
+                        )),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            }),
+        ))];
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_decls(&types, &["Test"]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export interface A {
+    builtin_scalar: number,
+    builtin_array: number[],
+    string_scalar: string,
+    string_array: string[],
+    enum_scalar: Flag,
+    enum_array: Flag[],
+    struct_scalar: Position,
+    struct_array: Position[],
+}
+"
+        );
+    }
+
+    #[test]
+    fn exported_decls_skipped_gen() {
+        use check::*;
+        let position = Ptr::new((
+            "Position",
+            ResolvedType::Struct(Struct {
+                fields: vec![StructField {
+                    name: "x",
+                    r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                    array: false,
+                    optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
+                }],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            }),
+        ));
+        let mut types = vec![position.clone()];
+        for name in ["Move", "Spawn"].iter() {
+            types.push(Ptr::new((
+                name,
+                ResolvedType::Struct(Struct {
+                    fields: vec![StructField {
+                        name: "pos",
+                        r#type: position.clone(),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    }],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: None,
+                }),
+            )));
+        }
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_decls(&types, &["Move", "Spawn"]);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export interface Position {
+    x: number,
+}
+"
+        );
+    }
+
+    #[test]
+    fn length_impl_gen() {
+        use check::*;
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    StructField {
+                        name: "count",
+                        r#type: Ptr::new(("uint16", ResolvedType::Builtin(Builtin::Uint16))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "ids",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: Some("count"),
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "names",
+                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: Some("count"),
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            },
+        };
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_impl(&test);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export class Test {
+    constructor(
+        public count: number,
+        public ids: number[],
+        public names: string[],
+    ) {}
+    static read(data: ArrayBuffer): Test | null {
+        let reader = new Reader(data);
+        let output = Object.create(Test.prototype);
+        output.count = reader.read_uint16();
+        let output_ids_len = output.count;
+        output.ids = new Array(output_ids_len);
+        for (let output_ids_index = 0; output_ids_index < output_ids_len; ++output_ids_index) {
+            let output_ids_item: any = undefined;
+            output_ids_item = reader.read_uint8();
+            output.ids[output_ids_index] = output_ids_item;
+        }
+        let output_names_len = output.count;
+        output.names = new Array(output_names_len);
+        for (let output_names_index = 0; output_names_index < output_names_len; ++output_names_index) {
+            let output_names_item: any = undefined;
+            let output_names_item_len = reader.read_uint32();
+            output_names_item = reader.read_string(output_names_item_len);
+            output.names[output_names_index] = output_names_item;
+        }
+        if (reader.failed) return null;
+        return output;
+    }
+    write(buffer?: ArrayBuffer): ArrayBuffer {
+        let writer = buffer ? new Writer(buffer) : new Writer();
+        writer.write_uint16(this.count);
+        if (this.ids.length !== this.count) throw new RangeError(\"Test.ids length does not match Test.count\");
+        for (let this_ids_index = 0; this_ids_index < this.ids.length; ++this_ids_index) {
+            let this_ids_item = this.ids[this_ids_index];
+            writer.write_uint8(this_ids_item);
+        }
+        if (this.names.length !== this.count) throw new RangeError(\"Test.names length does not match Test.count\");
+        for (let this_names_index = 0; this_names_index < this.names.length; ++this_names_index) {
+            let this_names_item = this.names[this_names_index];
+            writer.write_uint32(this_names_item.length);
+            writer.write_string(this_names_item);
+        }
+        return writer.finish();
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn condition_impl_gen() {
+        use check::*;
+        let kind = Ptr::new((
+            "Kind",
+            ResolvedType::Enum(Enum {
+                repr: EnumRepr::U8,
+                variants: vec![
+                    EnumVariant {
+                        name: "Player",
+                        value: 0,
+                        doc: vec![],
+                    },
+                    EnumVariant {
+                        name: "Monster",
+                        value: 1,
+                        doc: vec![],
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+            }),
+        ));
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    StructField {
+                        name: "kind",
+                        r#type: kind,
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "name",
+                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: Some(Condition {
+                            field: "kind",
+                            r#enum: "Kind",
+                            variant: "Player",
+                        }),
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            },
+        };
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_impl(&test);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export class Test {
+    constructor(
+        public kind: Kind,
+        public name: string | undefined,
+    ) {}
+    static read(data: ArrayBuffer): Test | null {
+        let reader = new Reader(data);
+        let output = Object.create(Test.prototype);
+        let output_kind_temp = reader.read_uint8();
+        if (1 <= output_kind_temp && output_kind_temp <= 2) output.kind = output_kind_temp;
+        else reader.failed = true;
+        if (output.kind === Kind.Player) {
+            let output_name_len = reader.read_uint32();
+            output.name = reader.read_string(output_name_len);
+        } else {
+            output.name = undefined;
+        }
+        if (reader.failed) return null;
+        return output;
+    }
+    write(buffer?: ArrayBuffer): ArrayBuffer {
+        let writer = buffer ? new Writer(buffer) : new Writer();
+        writer.write_uint8(this.kind as number);
+        if (this.kind === Kind.Player) {
+            let this_name = this.name;
+            if (this_name === undefined || this_name === null) throw new TypeError(\"Test.name is missing\");
+            writer.write_uint32(this_name.length);
+            writer.write_string(this_name);
+        }
+        return writer.finish();
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn bitmask_impl_gen() {
+        use check::*;
+        let field = |name, optional| StructField {
+            name,
+            r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+            array: false,
+            optional,
+            doc: vec![],
+            attrs: vec![],
+            range: None,
+            length: None,
+            condition: None,
+            tag: None,
+        };
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    field("a", true),
+                    field("b", false),
+                    field("c", true),
+                    field("d", true),
+                    field("e", true),
+                    field("f", true),
+                    field("g", true),
+                    field("h", true),
+                    field("i", true),
+                    field("j", true),
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Bitmask],
+                base: None,
+            },
+        };
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_impl(&test);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export class Test {
+    constructor(
+        public a: number | undefined,
+        public b: number,
+        public c: number | undefined,
+        public d: number | undefined,
+        public e: number | undefined,
+        public f: number | undefined,
+        public g: number | undefined,
+        public h: number | undefined,
+        public i: number | undefined,
+        public j: number | undefined,
+    ) {}
+    static read(data: ArrayBuffer): Test | null {
+        let reader = new Reader(data);
+        let output = Object.create(Test.prototype);
+        let output_presence = [reader.read_uint8(), reader.read_uint8()];
+        if ((output_presence[0] & 1) !== 0) {
+            output.a = reader.read_uint8();
+        } else {
+            output.a = undefined;
+        }
+        output.b = reader.read_uint8();
+        if ((output_presence[0] & 2) !== 0) {
+            output.c = reader.read_uint8();
+        } else {
+            output.c = undefined;
+        }
+        if ((output_presence[0] & 4) !== 0) {
+            output.d = reader.read_uint8();
+        } else {
+            output.d = undefined;
+        }
+        if ((output_presence[0] & 8) !== 0) {
+            output.e = reader.read_uint8();
+        } else {
+            output.e = undefined;
+        }
+        if ((output_presence[0] & 16) !== 0) {
+            output.f = reader.read_uint8();
+        } else {
+            output.f = undefined;
+        }
+        if ((output_presence[0] & 32) !== 0) {
+            output.g = reader.read_uint8();
+        } else {
+            output.g = undefined;
+        }
+        if ((output_presence[0] & 64) !== 0) {
+            output.h = reader.read_uint8();
+        } else {
+            output.h = undefined;
+        }
+        if ((output_presence[0] & 128) !== 0) {
+            output.i = reader.read_uint8();
+        } else {
+            output.i = undefined;
+        }
+        if ((output_presence[1] & 1) !== 0) {
+            output.j = reader.read_uint8();
+        } else {
+            output.j = undefined;
+        }
+        if (reader.failed) return null;
+        return output;
+    }
+    write(buffer?: ArrayBuffer): ArrayBuffer {
+        let writer = buffer ? new Writer(buffer) : new Writer();
+        writer.write_uint8((this.a != null ? 1 : 0) | (this.c != null ? 2 : 0) | (this.d != null ? 4 : 0) | (this.e != null ? 8 : 0) | (this.f != null ? 16 : 0) | (this.g != null ? 32 : 0) | (this.h != null ? 64 : 0) | (this.i != null ? 128 : 0));
+        writer.write_uint8((this.j != null ? 1 : 0));
+        let this_a = this.a;
+        if (this_a != null) {
+            writer.write_uint8(this_a);
+        }
+        writer.write_uint8(this.b);
+        let this_c = this.c;
+        if (this_c != null) {
+            writer.write_uint8(this_c);
+        }
+        let this_d = this.d;
+        if (this_d != null) {
+            writer.write_uint8(this_d);
+        }
+        let this_e = this.e;
+        if (this_e != null) {
+            writer.write_uint8(this_e);
+        }
+        let this_f = this.f;
+        if (this_f != null) {
+            writer.write_uint8(this_f);
+        }
+        let this_g = this.g;
+        if (this_g != null) {
+            writer.write_uint8(this_g);
+        }
+        let this_h = this.h;
+        if (this_h != null) {
+            writer.write_uint8(this_h);
+        }
+        let this_i = this.i;
+        if (this_i != null) {
+            writer.write_uint8(this_i);
+        }
+        let this_j = this.j;
+        if (this_j != null) {
+            writer.write_uint8(this_j);
+        }
+        return writer.finish();
+    }
+}
 "
         );
     }
 
     #[test]
-    fn simple_struct_gen() {
+    fn extensible_impl_gen() {
         use check::*;
-        let mut types = TypeMap::new();
-        types.insert(
-            "Position",
-            Ptr::new((
-                "Position",
-                ResolvedType::Struct(Struct {
-                    fields: vec![
-                        StructField {
-                            name: "x",
-                            r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                            array: false,
-                            optional: false,
-                        },
-                        StructField {
-                            name: "y",
-                            r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                            array: false,
-                            optional: false,
-                        },
-                    ],
-                }),
-            )),
-        );
+        let field = |name, ty, optional, tag| StructField {
+            name,
+            r#type: ty,
+            array: false,
+            optional,
+            doc: vec![],
+            attrs: vec![],
+            range: None,
+            length: None,
+            condition: None,
+            tag: Some(tag),
+        };
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    field(
+                        "id",
+                        Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        false,
+                        1,
+                    ),
+                    field(
+                        "name",
+                        Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        true,
+                        2,
+                    ),
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Extensible],
+                base: None,
+            },
+        };
         let mut gen = Generator::<TypeScript>::new();
         gen.push_line();
-        gen.push_decls(&types, "Test");
+        gen.push_impl(&test);
         let actual = gen.finish();
         assert_eq!(
             actual,
             "
-export namespace Test {
-    export interface Position {
-        x: number,
-        y: number,
+export class Test {
+    constructor(
+        public id: number,
+        public name: string | undefined,
+    ) {}
+    static read(data: ArrayBuffer): Test | null {
+        let reader = new Reader(data);
+        let output = Object.create(Test.prototype);
+        output.id = 0;
+        output.name = undefined;
+        for (;;) {
+            let output_tag = reader.read_uint16();
+            if (output_tag === 0 || reader.failed) break;
+            let output_len = reader.read_uint32();
+            switch (output_tag) {
+                case 1: {
+                    output.id = reader.read_uint8();
+                    break;
+                }
+                case 2: {
+                    let output_name_len = reader.read_uint32();
+                    output.name = reader.read_string(output_name_len);
+                    break;
+                }
+                default: reader.skip(output_len);
+            }
+        }
+        if (reader.failed) return null;
+        return output;
+    }
+    write(buffer?: ArrayBuffer): ArrayBuffer {
+        let writer = buffer ? new Writer(buffer) : new Writer();
+        writer.write_uint16(1);
+        let this_id_start = writer.position();
+        writer.write_uint32(0);
+        writer.write_uint8(this.id);
+        writer.write_uint32_at(this_id_start, writer.position() - this_id_start - 4);
+        let this_name = this.name;
+        if (this_name != null) {
+            writer.write_uint16(2);
+            let this_name_start = writer.position();
+            writer.write_uint32(0);
+            writer.write_uint32(this_name.length);
+            writer.write_string(this_name);
+            writer.write_uint32_at(this_name_start, writer.position() - this_name_start - 4);
+        }
+        writer.write_uint16(0);
+        return writer.finish();
     }
 }
 "
@@ -409,49 +2339,156 @@ export namespace Test {
     }
 
     #[test]
-    fn struct_with_optional_gen() {
+    fn delta_impl_gen() {
         use check::*;
-        let mut types = TypeMap::new();
-        types.insert(
-            "A",
-            Ptr::new((
-                "A",
-                ResolvedType::Struct(Struct {
-                    fields: vec![
-                        StructField {
-                            name: "a",
-                            r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                            array: false,
-                            optional: true,
-                        },
-                        StructField {
-                            name: "b",
-                            r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                            array: true,
-                            optional: true,
-                        },
-                        StructField {
-                            name: "c",
-                            r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
-                            array: false,
-                            optional: false,
-                        },
-                    ],
-                }),
-            )),
-        );
+        let field = |name, ty, optional| StructField {
+            name,
+            r#type: ty,
+            array: false,
+            optional,
+            doc: vec![],
+            attrs: vec![],
+            range: None,
+            length: None,
+            condition: None,
+            tag: None,
+        };
+        let position = Struct {
+            fields: vec![field(
+                "x",
+                Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                false,
+            )],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
+        };
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    field("hp", Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))), false),
+                    field("pos", Ptr::new(("Position", ResolvedType::Struct(position))), false),
+                    field(
+                        "name",
+                        Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        true,
+                    ),
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Delta],
+                base: None,
+            },
+        };
         let mut gen = Generator::<TypeScript>::new();
         gen.push_line();
-        gen.push_decls(&types, "Test");
+        gen.push_impl(&test);
         let actual = gen.finish();
         assert_eq!(
             actual,
             "
-export namespace Test {
-    export interface A {
-        a?: number,
-        b?: number[],
-        c: number,
+export class Test {
+    constructor(
+        public hp: number,
+        public pos: Position,
+        public name: string | undefined,
+    ) {}
+    static read(data: ArrayBuffer): Test | null {
+        let reader = new Reader(data);
+        let output = Object.create(Test.prototype);
+        output.hp = reader.read_uint8();
+        let output_pos: any = {};
+        output_pos.x = reader.read_float();
+        output.pos = output_pos;
+        if (reader.read_uint8() > 0) {
+            let output_name_len = reader.read_uint32();
+            output.name = reader.read_string(output_name_len);
+        } else {
+            output.name = undefined;
+        }
+        if (reader.failed) return null;
+        return output;
+    }
+    write(buffer?: ArrayBuffer): ArrayBuffer {
+        let writer = buffer ? new Writer(buffer) : new Writer();
+        writer.write_uint8(this.hp);
+        writer.write_float(this.pos.x);
+        let this_name = this.name;
+        switch (this_name) {
+            case undefined: case null: writer.write_uint8(0); break;
+            default: {
+                writer.write_uint8(1);
+                writer.write_uint32(this_name.length);
+                writer.write_string(this_name);
+            }
+        }
+        return writer.finish();
+    }
+    static read_delta(data: ArrayBuffer, baseline: Test): Test | null {
+        let reader = new Reader(data);
+        let output = Object.create(Test.prototype);
+        let output_changed = [reader.read_uint8()];
+        if ((output_changed[0] & 1) !== 0) {
+            output.hp = reader.read_uint8();
+        } else {
+            output.hp = baseline.hp;
+        }
+        if ((output_changed[0] & 2) !== 0) {
+            let output_pos: any = {};
+            let output_pos_changed = [reader.read_uint8()];
+            if ((output_pos_changed[0] & 1) !== 0) {
+                output_pos.x = reader.read_float();
+            } else {
+                output_pos.x = baseline.pos.x;
+            }
+            output.pos = output_pos;
+        } else {
+            output.pos = baseline.pos;
+        }
+        if ((output_changed[0] & 4) !== 0) {
+            if (reader.read_uint8() > 0) {
+                let output_name_len = reader.read_uint32();
+                output.name = reader.read_string(output_name_len);
+            } else {
+                output.name = undefined;
+            }
+        } else {
+            output.name = baseline.name;
+        }
+        if (reader.failed) return null;
+        return output;
+    }
+    write_delta(baseline: Test, buffer?: ArrayBuffer): ArrayBuffer {
+        let writer = buffer ? new Writer(buffer) : new Writer();
+        let this_changed = [
+            (!(this.hp === baseline.hp) ? 1 : 0) | (!((this.pos.x === baseline.pos.x)) ? 2 : 0) | (!((this.name == null ? baseline.name == null : baseline.name != null && this.name === baseline.name)) ? 4 : 0),
+        ];
+        writer.write_uint8(this_changed[0]);
+        if ((this_changed[0] & 1) !== 0) {
+            writer.write_uint8(this.hp);
+        }
+        if ((this_changed[0] & 2) !== 0) {
+            let this_pos_changed = [
+                (!(this.pos.x === baseline.pos.x) ? 1 : 0),
+            ];
+            writer.write_uint8(this_pos_changed[0]);
+            if ((this_pos_changed[0] & 1) !== 0) {
+                writer.write_float(this.pos.x);
+            }
+        }
+        if ((this_changed[0] & 4) !== 0) {
+            let this_name = this.name;
+            switch (this_name) {
+                case undefined: case null: writer.write_uint8(0); break;
+                default: {
+                    writer.write_uint8(1);
+                    writer.write_uint32(this_name.length);
+                    writer.write_string(this_name);
+                }
+            }
+        }
+        return writer.finish();
     }
 }
 "
@@ -459,30 +2496,105 @@ export namespace Test {
     }
 
     #[test]
-    fn enum_gen() {
+    fn packed_impl_gen() {
         use check::*;
-        let mut types = TypeMap::new();
-        types.insert(
-            "Flag",
-            Ptr::new((
-                "Flag",
-                ResolvedType::Enum(Enum {
-                    repr: EnumRepr::U8,
-                    variants: vec![EnumVariant { name: "A", value: 0 }, EnumVariant { name: "B", value: 1 }],
-                }),
-            )),
-        );
+        let field = |name, ty, optional, range| StructField {
+            name,
+            r#type: ty,
+            array: false,
+            optional,
+            doc: vec![],
+            attrs: vec![],
+            range,
+            length: None,
+            condition: None,
+            tag: None,
+        };
+        let variant = |name, value| EnumVariant {
+            name,
+            value,
+            doc: vec![],
+        };
+        let kind = Enum {
+            repr: EnumRepr::U8,
+            variants: vec![variant("A", 0), variant("B", 1), variant("C", 2)],
+            doc: vec![],
+            attrs: vec![],
+        };
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    field(
+                        "level",
+                        Ptr::new(("int8", ResolvedType::Builtin(Builtin::Int8))),
+                        false,
+                        Some(Range {
+                            min: Some("-10"),
+                            max: Some("10"),
+                            inclusive: false,
+                        }),
+                    ),
+                    field("kind", Ptr::new(("Kind", ResolvedType::Enum(kind))), false, None),
+                    field(
+                        "name",
+                        Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        true,
+                        None,
+                    ),
+                ],
+                doc: vec![],
+                attrs: vec![Attribute::Packed],
+                base: None,
+            },
+        };
         let mut gen = Generator::<TypeScript>::new();
         gen.push_line();
-        gen.push_decls(&types, "Test");
+        gen.push_impl(&test);
         let actual = gen.finish();
         assert_eq!(
             actual,
             "
-export namespace Test {
-    export const enum Flag {
-        A = 1 << 0,
-        B = 1 << 1,
+export class Test {
+    constructor(
+        public level: number,
+        public kind: Kind,
+        public name: string | undefined,
+    ) {}
+    static read(data: ArrayBuffer): Test | null {
+        let reader = new Reader(data);
+        let output = Object.create(Test.prototype);
+        output.level = reader.read_bits(5) - 10;
+        if (!(-10 <= output.level && output.level < 10)) reader.failed = true;
+        let output_kind_temp = 1 << reader.read_bits(2);
+        if (1 <= output_kind_temp && output_kind_temp <= 4) output.kind = output_kind_temp;
+        else reader.failed = true;
+        if (reader.read_bits(1) !== 0) {
+            let output_name_len = reader.read_bits(32);
+            output.name = reader.read_bits_string(output_name_len);
+        } else {
+            output.name = undefined;
+        }
+        if (reader.failed) return null;
+        return output;
+    }
+    write(buffer?: ArrayBuffer): ArrayBuffer {
+        let writer = buffer ? new Writer(buffer) : new Writer();
+        if (!(-10 <= this.level && this.level < 10)) throw new RangeError(\"Test.level is out of range\");
+        writer.write_bits(this.level + 10, 5);
+        writer.write_bits(31 - Math.clz32(this.kind), 2);
+        let this_name = this.name;
+        switch (this_name) {
+            case undefined: case null: writer.write_bits(0, 1); break;
+            default: {
+                writer.write_bits(1, 1);
+                writer.write_bits(this_name.length, 32);
+                writer.write_bits_string(this_name);
+            }
+        }
+        writer.flush_bits();
+        return writer.finish();
     }
 }
 "
@@ -490,96 +2602,113 @@ export namespace Test {
     }
 
     #[test]
-    fn complex_struct_gen() {
+    fn range_impl_gen() {
         use check::*;
-        let mut types = TypeMap::new();
-        types.insert(
-            "A",
-            Ptr::new((
-                "A",
-                ResolvedType::Struct(Struct {
-                    fields: vec![
-                        StructField {
-                            name: "builtin_scalar",
-                            r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
-                            array: false,
-                            optional: false,
-                        },
-                        StructField {
-                            name: "builtin_array",
-                            r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
-                            array: true,
-                            optional: false,
-                        },
-                        StructField {
-                            name: "string_scalar",
-                            r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
-                            array: false,
-                            optional: false,
-                        },
-                        StructField {
-                            name: "string_array",
-                            r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
-                            array: true,
-                            optional: false,
-                        },
-                        StructField {
-                            name: "enum_scalar",
-                            r#type: Ptr::new((
-                                "Flag",
-                                ResolvedType::Enum(Enum {
-                                    repr: EnumRepr::U8,
-                                    variants: vec![],
-                                }),
-                            )),
-                            array: false,
-                            optional: false,
-                        },
-                        StructField {
-                            name: "enum_array",
-                            r#type: Ptr::new((
-                                "Flag",
-                                ResolvedType::Enum(Enum {
-                                    repr: EnumRepr::U8,
-                                    variants: vec![],
-                                }),
-                            )),
-                            array: true,
-                            optional: false,
-                        },
-                        StructField {
-                            name: "struct_scalar",
-                            r#type: Ptr::new(("Position", ResolvedType::Struct(Struct { fields: vec![] }))),
-                            array: false,
-                            optional: false,
-                        },
-                        StructField {
-                            name: "struct_array",
-                            r#type: Ptr::new(("Position", ResolvedType::Struct(Struct { fields: vec![] }))),
-                            array: true,
-                            optional: false,
-                        },
-                    ],
-                }),
-            )),
-        );
+        let test = Export {
+            name: "Test",
+            id: None,
+            r#struct: Struct {
+                fields: vec![
+                    StructField {
+                        name: "hp",
+                        r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: Some(Range {
+                            min: None,
+                            max: Some("100"),
+                            inclusive: true,
+                        }),
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "name",
+                        r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
+                        array: false,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: Some(Range {
+                            min: Some("3"),
+                            max: Some("16"),
+                            inclusive: false,
+                        }),
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                    StructField {
+                        name: "scale",
+                        r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
+                        array: true,
+                        optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: Some(Range {
+                            min: Some("-1.5"),
+                            max: None,
+                            inclusive: false,
+                        }),
+                        length: None,
+                        condition: None,
+                        tag: None,
+                    },
+                ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
+            },
+        };
         let mut gen = Generator::<TypeScript>::new();
         gen.push_line();
-        gen.push_decls(&types, "Test");
+        gen.push_impl(&test);
         let actual = gen.finish();
         assert_eq!(
             actual,
             "
-export namespace Test {
-    export interface A {
-        builtin_scalar: number,
-        builtin_array: number[],
-        string_scalar: string,
-        string_array: string[],
-        enum_scalar: Flag,
-        enum_array: Flag[],
-        struct_scalar: Position,
-        struct_array: Position[],
+export class Test {
+    constructor(
+        public hp: number,
+        public name: string,
+        public scale: number[],
+    ) {}
+    static read(data: ArrayBuffer): Test | null {
+        let reader = new Reader(data);
+        let output = Object.create(Test.prototype);
+        output.hp = reader.read_uint8();
+        if (!(output.hp <= 100)) reader.failed = true;
+        let output_name_len = reader.read_uint32();
+        if (!(3 <= output_name_len && output_name_len < 16)) reader.failed = true;
+        else output.name = reader.read_string(output_name_len);
+        let output_scale_len = reader.read_uint32();
+        output.scale = new Array(output_scale_len);
+        for (let output_scale_index = 0; output_scale_index < output_scale_len; ++output_scale_index) {
+            let output_scale_item: any = undefined;
+            output_scale_item = reader.read_float();
+            if (!(-1.5 <= output_scale_item)) reader.failed = true;
+            output.scale[output_scale_index] = output_scale_item;
+        }
+        if (reader.failed) return null;
+        return output;
+    }
+    write(buffer?: ArrayBuffer): ArrayBuffer {
+        let writer = buffer ? new Writer(buffer) : new Writer();
+        if (!(this.hp <= 100)) throw new RangeError(\"Test.hp is out of range\");
+        writer.write_uint8(this.hp);
+        if (!(3 <= this.name.length && this.name.length < 16)) throw new RangeError(\"Test.name is out of range\");
+        writer.write_uint32(this.name.length);
+        writer.write_string(this.name);
+        writer.write_uint32(this.scale.length);
+        for (let this_scale_index = 0; this_scale_index < this.scale.length; ++this_scale_index) {
+            let this_scale_item = this.scale[this_scale_index];
+            if (!(-1.5 <= this_scale_item)) throw new RangeError(\"Test.scale is out of range\");
+            writer.write_float(this_scale_item);
+        }
+        return writer.finish();
     }
 }
 "
@@ -591,6 +2720,7 @@ export namespace Test {
         use check::*;
         let test = Export {
             name: "Test",
+            id: None,
             r#struct: Struct {
                 fields: vec![
                     StructField {
@@ -598,20 +2728,41 @@ export namespace Test {
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "b",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: true,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "c",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                 ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
         let mut gen = Generator::<TypeScript>::new();
@@ -629,7 +2780,7 @@ export class Test {
     ) {}
     static read(data: ArrayBuffer): Test | null {
         let reader = new Reader(data);
-        let output = Object.create(Test);
+        let output = Object.create(Test.prototype);
         if (reader.read_uint8() > 0) {
             output.a = reader.read_uint8();
         } else {
@@ -690,24 +2841,49 @@ export class Test {
                     r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                     array: true,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "second",
                     r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                     array: true,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let test_b = Export {
             name: "TestB",
+            id: None,
             r#struct: Struct {
                 fields: vec![StructField {
                     name: "test_a",
                     r#type: Ptr::new(("TestA", ResolvedType::Struct(test_a))),
                     array: true,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 }],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
         let mut gen = Generator::<TypeScript>::new();
@@ -719,11 +2895,11 @@ export class Test {
             "
 export class TestB {
     constructor(
-        public test_a: TestB.TestA[],
+        public test_a: TestA[],
     ) {}
     static read(data: ArrayBuffer): TestB | null {
         let reader = new Reader(data);
-        let output = Object.create(TestB);
+        let output = Object.create(TestB.prototype);
         let output_test_a_len = reader.read_uint32();
         output.test_a = new Array(output_test_a_len);
         for (let output_test_a_index = 0; output_test_a_index < output_test_a_len; ++output_test_a_index) {
@@ -775,7 +2951,20 @@ export class TestB {
         use check::*;
         let flag = Enum {
             repr: EnumRepr::U8,
-            variants: vec![EnumVariant { name: "A", value: 0 }, EnumVariant { name: "B", value: 1 }],
+            variants: vec![
+                EnumVariant {
+                    name: "A",
+                    value: 0,
+                    doc: vec![],
+                },
+                EnumVariant {
+                    name: "B",
+                    value: 1,
+                    doc: vec![],
+                },
+            ],
+            doc: vec![],
+            attrs: vec![],
         };
         let position = Struct {
             fields: vec![
@@ -784,17 +2973,33 @@ export class TestB {
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "y",
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let test = Export {
             name: "Test",
+            id: None,
             r#struct: Struct {
                 fields: vec![
                     StructField {
@@ -802,68 +3007,137 @@ export class TestB {
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "builtin_array",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "string_scalar",
                         r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "string_array",
                         r#type: Ptr::new(("string", ResolvedType::Builtin(Builtin::String))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "enum_scalar",
                         r#type: Ptr::new(("Flag", ResolvedType::Enum(flag.clone()))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "enum_array",
                         r#type: Ptr::new(("Flag", ResolvedType::Enum(flag.clone()))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "struct_scalar",
                         r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "struct_array",
                         r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "opt_scalar",
                         r#type: Ptr::new(("uint8", ResolvedType::Builtin(Builtin::Uint8))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "opt_enum",
                         r#type: Ptr::new(("Flag", ResolvedType::Enum(flag.clone()))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "opt_struct",
                         r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                         array: false,
                         optional: true,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                 ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
         let mut gen = Generator::<TypeScript>::new();
@@ -879,17 +3153,17 @@ export class Test {
         public builtin_array: number[],
         public string_scalar: string,
         public string_array: string[],
-        public enum_scalar: Test.Flag,
-        public enum_array: Test.Flag[],
-        public struct_scalar: Test.Position,
-        public struct_array: Test.Position[],
+        public enum_scalar: Flag,
+        public enum_array: Flag[],
+        public struct_scalar: Position,
+        public struct_array: Position[],
         public opt_scalar: number | undefined,
-        public opt_enum: Test.Flag | undefined,
-        public opt_struct: Test.Position | undefined,
+        public opt_enum: Flag | undefined,
+        public opt_struct: Position | undefined,
     ) {}
     static read(data: ArrayBuffer): Test | null {
         let reader = new Reader(data);
-        let output = Object.create(Test);
+        let output = Object.create(Test.prototype);
         output.builtin_scalar = reader.read_uint8();
         let output_builtin_array_len = reader.read_uint32();
         output.builtin_array = new Array(output_builtin_array_len);
@@ -1027,14 +3301,29 @@ export class Test {
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "y",
                     r#type: Ptr::new(("float", ResolvedType::Builtin(Builtin::Float))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let entity = Struct {
             fields: vec![
@@ -1043,17 +3332,33 @@ export class Test {
                     r#type: Ptr::new(("uint32", ResolvedType::Builtin(Builtin::Uint32))),
                     array: false,
                     optional: false,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
                 StructField {
                     name: "pos",
                     r#type: Ptr::new(("Position", ResolvedType::Struct(position.clone()))),
                     array: false,
                     optional: true,
+                    doc: vec![],
+                    attrs: vec![],
+                    range: None,
+                    length: None,
+                    condition: None,
+                    tag: None,
                 },
             ],
+            doc: vec![],
+            attrs: vec![],
+            base: None,
         };
         let state = Export {
             name: "State",
+            id: None,
             r#struct: Struct {
                 fields: vec![
                     StructField {
@@ -1061,14 +3366,29 @@ export class Test {
                         r#type: Ptr::new(("uint32", ResolvedType::Builtin(Builtin::Uint32))),
                         array: false,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                     StructField {
                         name: "entities",
                         r#type: Ptr::new(("Entity", ResolvedType::Struct(entity.clone()))),
                         array: true,
                         optional: false,
+                        doc: vec![],
+                        attrs: vec![],
+                        range: None,
+                        length: None,
+                        condition: None,
+                        tag: None,
                     },
                 ],
+                doc: vec![],
+                attrs: vec![],
+                base: None,
             },
         };
         let mut gen = Generator::<TypeScript>::new();
@@ -1081,11 +3401,11 @@ export class Test {
 export class State {
     constructor(
         public id: number,
-        public entities: State.Entity[],
+        public entities: Entity[],
     ) {}
     static read(data: ArrayBuffer): State | null {
         let reader = new Reader(data);
-        let output = Object.create(State);
+        let output = Object.create(State.prototype);
         output.id = reader.read_uint32();
         let output_entities_len = reader.read_uint32();
         output.entities = new Array(output_entities_len);
@@ -1128,4 +3448,127 @@ export class State {
 "
         );
     }
+
+    #[test]
+    fn message_gen() {
+        use check::*;
+        let exports = vec![
+            Export {
+                name: "Move",
+                id: Some(1),
+                r#struct: Struct {
+                    fields: vec![],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: None,
+                },
+            },
+            Export {
+                name: "Spawn",
+                id: Some(2),
+                r#struct: Struct {
+                    fields: vec![],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: None,
+                },
+            },
+        ];
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_message(&exports);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export type Message =
+    | [1, Move]
+    | [2, Spawn];
+export function read_any(data: ArrayBuffer): Message | null {
+    let reader = new Reader(data);
+    let id = reader.read_uint8();
+    if (reader.failed) return null;
+    switch (id) {
+        case 1: {
+            let packet = Move.read(data.slice(1));
+            return packet ? [1, packet] : null;
+        }
+        case 2: {
+            let packet = Spawn.read(data.slice(1));
+            return packet ? [2, packet] : null;
+        }
+        default: return null;
+    }
+}
+export function write_any(msg: Message): ArrayBuffer {
+    let packet = new Uint8Array(msg[1].write());
+    let output = new Uint8Array(packet.length + 1);
+    output[0] = msg[0];
+    output.set(packet, 1);
+    return output.buffer;
+}
+"
+        );
+    }
+
+    #[test]
+    fn handler_gen() {
+        use check::*;
+        let exports = vec![
+            Export {
+                name: "Move",
+                id: Some(1),
+                r#struct: Struct {
+                    fields: vec![],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: None,
+                },
+            },
+            Export {
+                name: "PlayerState",
+                id: Some(2),
+                r#struct: Struct {
+                    fields: vec![],
+                    doc: vec![],
+                    attrs: vec![],
+                    base: None,
+                },
+            },
+        ];
+        let mut gen = Generator::<TypeScript>::new();
+        gen.push_line();
+        gen.push_handler(&exports);
+        let actual = gen.finish();
+        assert_eq!(
+            actual,
+            "
+export interface Handler {
+    on_move(packet: Move): void;
+    on_player_state(packet: PlayerState): void;
+    on_error(data: ArrayBuffer): void;
+}
+export function dispatch(data: ArrayBuffer, handler: Handler) {
+    let msg = read_any(data);
+    if (msg === null) return handler.on_error(data);
+    switch (msg[0]) {
+        case 1: return handler.on_move(msg[1]);
+        case 2: return handler.on_player_state(msg[1]);
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn delta_conditional_follows_condition() {
+        let schema = "Kind: enum { A, B }\n\
+                      @delta\n\
+                      Test: struct { kind: Kind, hp: uint8 if kind == Kind.A, other: uint8 if kind == Kind.B }\n\
+                      export Test";
+        let out = crate::compile::<TypeScript>(schema).unwrap();
+        assert!(out.contains(
+            "(!((this.hp == null ? baseline.hp == null : baseline.hp != null && this.hp === baseline.hp) && this.kind === baseline.kind) ? 2 : 0)"
+        ));
+    }
 }
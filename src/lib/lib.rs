@@ -5,52 +5,282 @@ extern crate peg;
 #[macro_use]
 extern crate thiserror;
 
-use peg::error::ParseError;
-use peg::str::LineCol;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use diagnostic::{Code, Diagnostic, Lints};
 
 pub mod ast;
 pub mod check;
+pub mod compat;
+pub mod diagnostic;
 pub mod gen;
 pub mod parser;
 
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("Parsing failed with:\n{0}")]
-    Parse(String),
-    #[error("One or more type errors:\n{0}")]
-    Check(String),
-}
-
-fn pretty_error(file: &str, err: ParseError<LineCol>) -> String {
-    let mut out_str = String::new();
-    out_str += &format!(
-        "\nUnexpected token '{}' at line {}, column {}",
-        file.chars().nth(err.location.offset).unwrap_or('\0'),
-        err.location.line,
-        err.location.column
-    );
-    let line = file.split('\n').nth(err.location.line - 1).unwrap_or("\0");
-    out_str += &format!("\n|\n|  {}\n", line);
-    let mark_column = match err.location.column {
-        n if n < 1 => 0,
-        n => n - 1,
-    };
-    out_str += &format!("|~~{}^\n", "~".repeat(mark_column));
-
-    out_str
+    /// Every syntax error found, in the order they appear
+    #[error("Parsing failed with:\n{}", join_lines(.0))]
+    Parse(Vec<Diagnostic>),
+    /// Every type error found, sorted by location
+    #[error("One or more type errors:\n{}", join_lines(.0))]
+    Check(Vec<Diagnostic>),
+    #[error("Could not read '{0}'")]
+    Io(PathBuf, #[source] std::io::Error),
+}
+
+fn join_lines(diagnostics: &[Diagnostic]) -> String {
+    diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n")
 }
 
 pub fn compile<Lang>(schema: &str) -> Result<String, Error>
 where
-    Lang: gen::Language + Default + gen::Common + gen::Declaration + gen::Impl,
+    Lang: gen::Language
+        + Default
+        + gen::Common
+        + gen::Declaration
+        + gen::Impl
+        + gen::Message
+        + gen::Handler
+        + gen::Fingerprint
+        + gen::Package,
 {
-    let ast = match parser::pkt::schema(schema) {
-        Ok(ast) => ast,
-        Err(e) => return Err(Error::Parse(pretty_error(schema, e))),
-    };
-    let resolved = match check::type_check(ast) {
-        Ok(r) => r,
-        Err(e) => return Err(Error::Check(e)),
-    };
+    let ast = parser::parse(schema).map_err(Error::Parse)?;
+    if let Some(ast::Node::Import(_, span)) = ast.iter().find(|n| matches!(n, ast::Node::Import(..))) {
+        return Err(Error::Check(vec![Diagnostic::error(
+            Code::Import,
+            "Imports are only supported when compiling a file",
+        )
+        .with_span(*span)]));
+    }
+    let resolved = check::type_check(ast).map_err(Error::Check)?;
     Ok(gen::generate::<Lang>(&resolved))
 }
+
+struct Source {
+    path: PathBuf,
+    text: String,
+    /// Indices of the directly imported sources
+    imports: Vec<usize>,
+    /// Spans of the imports, in the same order
+    import_spans: Vec<diagnostic::Span>,
+}
+
+fn parse_file<'a>(path: &Path, text: &'a str) -> Result<ast::AST<'a>, Error> {
+    parser::parse(text).map_err(|errors| Error::Parse(errors.into_iter().map(|e| e.with_path(path)).collect()))
+}
+
+/// Reads the schema at `path` and every schema it (transitively) imports
+fn load_sources(path: &Path) -> Result<Vec<Source>, Error> {
+    let mut sources = Vec::new();
+    let mut indices = HashMap::new();
+    let root = path.canonicalize().map_err(|e| Error::Io(path.to_path_buf(), e))?;
+    let mut queue = vec![root.clone()];
+    indices.insert(root, 0);
+    while let Some(path) = queue.pop() {
+        let text = std::fs::read_to_string(&path).map_err(|e| Error::Io(path.clone(), e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        let mut imports = Vec::new();
+        let mut import_spans = Vec::new();
+        for node in parse_file(&path, &text)? {
+            if let ast::Node::Import(import, span) = node {
+                // point at the import, rather than just the file it names
+                let import_path = dir.join(import).canonicalize().map_err(|e| {
+                    Error::Check(vec![Diagnostic::error(
+                        Code::Import,
+                        format!("Could not read imported schema '{}': {}", import, e),
+                    )
+                    .with_span(span)
+                    .with_path(&path)])
+                })?;
+                let next = indices.len();
+                let index = *indices.entry(import_path.clone()).or_insert_with(|| {
+                    queue.push(import_path);
+                    next
+                });
+                imports.push(index);
+                import_spans.push(span);
+            }
+        }
+        let source = Source {
+            path,
+            text,
+            imports,
+            import_spans,
+        };
+        sources.push((indices[&source.path], source));
+    }
+    sources.sort_by_key(|(i, _)| *i);
+    Ok(sources.into_iter().map(|(_, s)| s).collect())
+}
+
+/// Path to the module generated for `to`, relative to the directory of `from`,
+/// e.g. `./shared` or `../common/position`
+fn module_path(from: &Path, to: &Path) -> String {
+    let from = from
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .components()
+        .collect::<Vec<_>>();
+    let to = to.with_extension("");
+    let to = to.components().collect::<Vec<_>>();
+    let common = from.iter().zip(to.iter()).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![];
+    if common == from.len() {
+        parts.push(".".to_string());
+    }
+    parts.extend(from[common..].iter().map(|_| "..".to_string()));
+    parts.extend(to[common..].iter().filter_map(|c| match c {
+        Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+        _ => None,
+    }));
+    parts.join("/")
+}
+
+/// Type checks the schema at `path`, resolving its imports relative to it.
+/// The resolved schema borrows from the sources, so it's only lent to `f`.
+/// Also returns the warnings of every checked schema.
+fn resolve_file<R>(path: &Path, f: impl FnOnce(&check::Resolved) -> R) -> Result<(R, Vec<Diagnostic>), Error> {
+    let sources = load_sources(path)?;
+    let names = sources.iter().map(|s| s.path.display().to_string()).collect::<Vec<_>>();
+    let imports = sources
+        .iter()
+        .map(|s| s.imports.iter().copied().zip(s.import_spans.iter().copied()).collect())
+        .collect::<Vec<_>>();
+    let order = check::import_order(&names, &imports).map_err(|e| Error::Check(vec![e]))?;
+
+    let mut resolved: Vec<Option<check::Resolved>> = sources.iter().map(|_| None).collect();
+    let mut warnings = Vec::new();
+    for &index in order.iter() {
+        let source = &sources[index];
+        let ast = parse_file(&source.path, &source.text)?;
+
+        // every schema in the transitive closure is needed to know where
+        // an imported type is declared, but only direct imports are visible
+        let mut visible = Vec::new();
+        let mut stack = source.imports.clone();
+        while let Some(dep) = stack.pop() {
+            if !visible.contains(&dep) {
+                visible.push(dep);
+                stack.extend(sources[dep].imports.iter().copied());
+            }
+        }
+        let deps = visible
+            .into_iter()
+            .map(|dep| check::Import {
+                module: module_path(&source.path, &sources[dep].path),
+                direct: source.imports.contains(&dep),
+                package: resolved[dep].as_ref().map(|r| r.package.clone()).unwrap_or_default(),
+                types: resolved[dep]
+                    .as_ref()
+                    .map(|r| {
+                        r.types
+                            .iter()
+                            .filter(|(_, t)| !matches!(t.borrow().1, check::ResolvedType::Builtin(_)))
+                            .map(|(n, t)| (*n, t.clone()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect();
+        let checked = check::type_check_with_imports(ast, deps)
+            .map_err(|errors| Error::Check(errors.into_iter().map(|e| e.with_path(&source.path)).collect()))?;
+        warnings.extend(checked.warnings.iter().map(|w| w.clone().with_path(&source.path)));
+        resolved[index] = Some(checked);
+    }
+    Ok((f(resolved[0].as_ref().unwrap()), warnings))
+}
+
+/// Generated code, along with the warnings which weren't allowed
+pub struct Compiled {
+    pub code: String,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Compiles the schema at `path`, resolving its imports relative to it.
+/// Warnings denied by `lints` fail the compilation.
+pub fn compile_file<Lang>(path: &Path, lints: &Lints) -> Result<Compiled, Error>
+where
+    Lang: gen::Language
+        + Default
+        + gen::Common
+        + gen::Declaration
+        + gen::Impl
+        + gen::Message
+        + gen::Handler
+        + gen::Fingerprint
+        + gen::Package,
+{
+    let (code, warnings) = resolve_file(path, gen::generate::<Lang>)?;
+    let warnings = lints.apply(warnings).map_err(Error::Check)?;
+    Ok(Compiled { code, warnings })
+}
+
+/// Compares the schemas at `old` and `new`, returning the changes which
+/// break decoding packets written by the other one
+pub fn compat_files(old: &Path, new: &Path) -> Result<Vec<String>, Error> {
+    let (changes, _) = resolve_file(old, |old| resolve_file(new, |new| compat::compare(old, new)))?;
+    Ok(changes?.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_declaration_order() {
+        let schema = "Kind: enum { A, B }\n\
+                      Vec2: struct { x: float, y: float }\n\
+                      Stats: struct { hp: uint8 }\n\
+                      Entity: struct { kind: Kind, pos: Vec2, stats: Stats }\n\
+                      export Entity";
+        // the first line has the time it was generated at
+        let generate = || {
+            let out = compile::<gen::ts::TypeScript>(schema).unwrap();
+            out[out.find('\n').unwrap()..].to_string()
+        };
+        let first = generate();
+        for _ in 0..8 {
+            assert_eq!(generate(), first);
+        }
+        let positions = ["enum Kind", "interface Vec2", "interface Stats"]
+            .iter()
+            .map(|decl| first.find(decl).unwrap())
+            .collect::<Vec<_>>();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn missing_import() {
+        let dir = std::env::temp_dir().join(format!("packetc-missing-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.pkt");
+        std::fs::write(&path, "import \"missing.pkt\"\nTest: struct { a: uint8 }\nexport Test").unwrap();
+        let path = path.canonicalize().unwrap();
+        let result = load_sources(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(Error::Check(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].code, Code::Import);
+                assert!(errors[0]
+                    .message
+                    .starts_with("Could not read imported schema 'missing.pkt'"));
+                assert_eq!(errors[0].primary_span, Some(diagnostic::Span::new(7, 20)));
+                assert_eq!(errors[0].path, Some(path));
+            }
+            _ => panic!("expected an import error"),
+        }
+    }
+
+    #[test]
+    fn relative_module_path() {
+        let from = Path::new("/schemas/game/state.pkt");
+        assert_eq!(module_path(from, Path::new("/schemas/game/entity.pkt")), "./entity");
+        assert_eq!(module_path(from, Path::new("/schemas/game/sub/item.pkt")), "./sub/item");
+        assert_eq!(
+            module_path(from, Path::new("/schemas/common/shared.pkt")),
+            "../common/shared"
+        );
+    }
+}
@@ -1,18 +1,30 @@
 use ast::*;
 
 use super::ast;
+use super::diagnostic::{Code, Diagnostic, Span};
 
 peg::parser!(pub grammar pkt() for str {
     /// Parses whitespace
-    rule _() = [' ' | '\t']*
+    rule _() = quiet!{[' ' | '\t']*}
     /// Parses newlines
-    rule __() = ['\n' | '\r']*
+    rule __() = quiet!{['\n' | '\r']*}
     /// Parses whitespace or newlines
-    rule ___() = [' ' | '\t' | '\n' | '\r']*
+    rule ___() = quiet!{[' ' | '\t' | '\n' | '\r']*}
 
     /// Parses a single-line comment
     rule comment()
-        = "#" [ch if ch != '\n']* __
+        = "#" !"#" [ch if ch != '\n']* __
+
+    /// Parses a doc comment in the form `## text`, which documents the
+    /// declaration, field or variant that follows it
+    rule doc_line() -> &'input str
+        = "##" s:$([ch if ch != '\n' && ch != '\r']*) ___ { s.trim() }
+    rule doc() -> Doc<'input>
+        = d:(_ l:doc_line() { l })* { d }
+
+    /// Parses `x`, along with the span of the input it consumed
+    rule spanned<T>(x: rule<T>) -> (T, Span)
+        = start:position!() v:x() end:position!() { (v, Span::new(start, end)) }
 
     rule string() -> &'input str
         = s:$(['a'..='z'|'A'..='Z'|'0'..='9'|'_']*) { s }
@@ -36,26 +48,76 @@ peg::parser!(pub grammar pkt() for str {
     /// Parses an entire identifier, ensuring no reserved keywords are used
     rule ident() -> &'input str
         = i:quiet!{ $(!reserved() ident_start() ident_chars()*) } { i }
+        / expected!("identifier")
+
+    /// Parses an attribute argument, which is either a string literal or
+    /// a plain word such as `1.2` or `name`
+    rule attribute_arg() -> &'input str
+        = "\"" s:$([ch if ch != '"' && ch != '\n']*) "\"" { s }
+        / s:quiet!{ $(['a'..='z'|'A'..='Z'|'0'..='9'|'_'|'.']+) } { s }
+        / expected!("attribute argument")
+    /// Parses an attribute in the form `@name` or `@name(arg0, arg1, ...)`
+    rule attribute() -> Attribute<'input>
+        = "@" n:ident() args:("(" ___ a:(attribute_arg() ** (___ "," ___)) ___ ")" { a })? ___ {
+            Attribute(n, args.unwrap_or_default())
+        }
+    rule attributes() -> Attributes<'input>
+        = a:(_ a:attribute() { a })* { a }
 
-    rule enum_variant() -> &'input str
-        = s:ident() ___ ","? ___ { s }
+    rule enum_variant() -> (&'input str, Doc<'input>, Span)
+        = d:doc() _ s:spanned(<ident()>) ___ ","? ___ { (s.0, d, s.1) }
     /// Parses an enum in the form `identifier: enum { VARIANT_A, ... }`
     rule enum_type() -> Enum<'input>
         = _ "enum" _ "{" ___ variants:(enum_variant()*) ___ "}" { Enum(variants) }
 
+    /// Parses a non-negative whole number
+    rule integer() -> &'input str
+        = n:quiet!{ $(['0'..='9']+) } { n }
+        / expected!("integer")
+    /// Parses a number, which may be negative or have a fractional part
+    rule number() -> &'input str
+        = n:quiet!{ $("-"? ['0'..='9']+ ("." ['0'..='9']+)?) } { n }
+        / expected!("number")
+    /// Parses a range constraint in the form `in min..max` or `in min..=max`
+    rule range() -> Range<'input>
+        = _ "in" _ min:number()? ".." inclusive:("="?) max:number()? {
+            Range(min, max, inclusive.is_some())
+        }
+
+    /// Parses a condition in the form `if field == Enum.VARIANT`
+    rule condition() -> Condition<'input>
+        = _ start:position!() "if" _ f:ident() _ "==" _ e:ident() "." v:ident() end:position!() {
+            Condition(f, e, v, Span::new(start, end))
+        }
+
     rule is_optional() -> bool
         = o:("?"?) { o.is_some() }
 
-    rule struct_field() -> Option<(&'input str, Unresolved<'input>)>
+    /// Parses the tag of a field in an extensible struct, in the form `@3`
+    rule tag() -> u16
+        = "@" n:integer() _ {? n.parse().or(Err("tag between 0 and 65535")) }
+
+    /// Parses an array suffix in the form `[]` or `[length_field]`
+    rule array() -> Option<&'input str>
+        = "[" _ l:ident()? _ "]" { l }
+
+    rule struct_field() -> Option<Field<'input>>
         = comment() ___ { None }
-        / i:ident() _ opt:is_optional() ":" _ t:string() a:("[]"?) ___ ","? ___ { Some((i, Unresolved(t, a.is_some(), opt))) }
+        / d:doc() attrs:attributes() _ i:spanned(<ident()>) _ opt:is_optional() _ tag:tag()? ":" _ t:spanned(<string()>) a:array()? r:range()? c:condition()? ___ ","? ___ {
+            Some((i.0, Unresolved(t.0, a.is_some(), opt, r, a.flatten(), c, tag, t.1), d, attrs, i.1))
+        }
+
+    /// Parses the base of a struct in the form `extends identifier`
+    rule base() -> (&'input str, Span)
+        = "extends" _ b:spanned(<ident()>) _ { b }
 
-    /// Parses a struct in the from `identifier: struct { name: type or type[], ... }
+    /// Parses a struct in the from `identifier: struct { name: type or type[], ... }`
+    /// or `identifier: struct extends Base { ... }`
     rule struct_type() -> Struct<'input>
-        = _ "struct" _ "{" ___ fields:(struct_field()*) ___ "}" {
+        = _ "struct" _ b:base()? "{" ___ fields:(struct_field()*) ___ "}" {
             Struct(fields.into_iter()
-            .filter_map(|x| x)
-            .collect())
+            .flatten()
+            .collect(), b)
         }
 
     /// Recursively parses a type
@@ -65,29 +127,212 @@ peg::parser!(pub grammar pkt() for str {
 
     /// Parses a declaration in the form `identifier : type`
     rule decl() -> Node<'input>
-        = _ i:ident() _ ":" _ t:r#type() ___ {
-            Node::Decl(i, t)
+        = d:doc() attrs:attributes() _ i:spanned(<ident()>) _ ":" _ t:r#type() ___ {
+            Node::Decl(i.0, t, d, attrs, i.1)
         }
 
+    /// Parses a packet id in the form `= 123`, which must fit in a single byte
+    rule packet_id() -> u8
+        = _ "=" _ n:integer() {? n.parse().or(Err("packet id between 0 and 255")) }
+
     rule export() -> Node<'input>
-        = "export" _ s:string() {
-            Node::Export(s)
+        = "export" _ s:spanned(<string()>) id:packet_id()? {
+            Node::Export(s.0, id, s.1)
+        }
+
+    /// Parses an import in the form `import "path/to/schema.pkt"`
+    rule import() -> Node<'input>
+        = "import" _ p:spanned(<"\"" p:$([ch if ch != '"' && ch != '\n']+) "\"" { p }>) {
+            Node::Import(p.0, p.1)
+        }
+
+    /// Parses a package in the form `package game.combat`
+    rule package() -> Node<'input>
+        = "package" _ p:spanned(<$(ident() ++ ".")>) {
+            Node::Package(p.0, p.1)
         }
 
     rule line() -> Option<Node<'input>>
         = _ comment() __ { None }
+        / _ p:(package()) __ { Some(p) }
+        / _ i:(import()) __ { Some(i) }
         / _ e:(export()) __ { Some(e) }
         / _ s:(decl()) __ { Some(s) }
+        / quiet!{[' ' | '\t']+ __} { None }
 
     /// Parses a schema file
     pub rule schema() -> AST<'input>
         = __? lines:(line()*) {
             lines.into_iter()
-                .filter_map(|x| x)
+                .flatten()
                 .collect()
         }
 });
 
+/// Parses `source`, reporting every syntax error in it rather than just the
+/// first. After an error the field it's in is skipped, or the whole
+/// declaration if that's not enough, and parsing starts again.
+pub fn parse(source: &str) -> Result<AST<'_>, Vec<Diagnostic>> {
+    let err = match pkt::schema(source) {
+        Ok(ast) => return Ok(ast),
+        Err(err) => err,
+    };
+    // skipped text is blanked out instead of removed, so that locations in
+    // later errors still point into `source`
+    let mut text = source.to_string();
+    let mut errors = vec![syntax_error(source, &err)];
+    let mut offset = err.location.offset;
+    let mut whole_decl = false;
+    loop {
+        let (start, end) = if whole_decl || unmatched_brace(&text, offset).is_none() {
+            decl_bounds(&text, offset)
+        } else {
+            field_bounds(&text, offset)
+        };
+        text = blank(&text, start, end);
+        match pkt::schema(&text) {
+            Ok(_) => break,
+            Err(err) if err.location.offset <= offset => {
+                if whole_decl {
+                    break;
+                }
+                whole_decl = true;
+            }
+            Err(err) => {
+                errors.push(syntax_error(source, &err));
+                offset = err.location.offset;
+                whole_decl = false;
+            }
+        }
+    }
+    Err(errors)
+}
+
+fn syntax_error(source: &str, err: &peg::error::ParseError<peg::str::LineCol>) -> Diagnostic {
+    let offset = err.location.offset;
+    let token = source[offset..].chars().next();
+    let span = Span::new(offset, offset + token.map_or(0, char::len_utf8));
+    let found = match token {
+        Some(ch) => format!("Unexpected token '{}'", ch.escape_default()),
+        None => "Unexpected end of file".to_string(),
+    };
+    Diagnostic::error(
+        Code::Syntax,
+        format!(
+            "{} at line {}, column {}, expected {}",
+            found, err.location.line, err.location.column, err.expected
+        ),
+    )
+    .with_span(span)
+    .with_label(span, format!("expected {}", err.expected))
+}
+
+/// Walks `text` up to `end`, calling `f` with the offset of each character
+/// that isn't inside a comment or string literal
+fn scan(text: &str, end: usize, mut f: impl FnMut(usize, char)) {
+    let mut comment = false;
+    let mut string = false;
+    for (i, ch) in text[..end].char_indices() {
+        match ch {
+            '\n' => {
+                comment = false;
+                string = false;
+            }
+            _ if comment => {}
+            '"' => string = !string,
+            _ if string => {}
+            '#' => comment = true,
+            _ => f(i, ch),
+        }
+    }
+}
+
+/// Offset of the `{` opening the struct or enum body containing `offset`
+fn unmatched_brace(text: &str, offset: usize) -> Option<usize> {
+    let mut open = None;
+    scan(text, offset, |i, ch| match ch {
+        '{' => open = Some(i),
+        '}' => open = None,
+        _ => {}
+    });
+    open
+}
+
+/// Start of the line containing `offset`, moved up over any doc comments and
+/// attributes directly above it
+fn item_start(text: &str, offset: usize) -> usize {
+    let mut start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    while start > 0 {
+        let above = text[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+        let line = text[above..start - 1].trim_start();
+        let attributes_only = line.starts_with('@') && !line.contains(':');
+        if !line.starts_with("##") && !attributes_only {
+            break;
+        }
+        start = above;
+    }
+    start
+}
+
+/// Range of the field or variant containing `offset`, which ends at the next
+/// separator, newline or closing brace
+fn field_bounds(text: &str, offset: usize) -> (usize, usize) {
+    let mut start = 0;
+    let mut parens = 0;
+    scan(text, offset, |i, ch| match ch {
+        '(' => parens += 1,
+        ')' => parens -= 1,
+        '{' | ',' if parens == 0 => start = i + 1,
+        _ => {}
+    });
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    if line_start >= start {
+        start = item_start(text, offset);
+    }
+    let end = match text[offset..].find(&[',', '\n', '}'][..]) {
+        Some(i) if text[offset + i..].starts_with(',') => offset + i + 1,
+        Some(i) => offset + i,
+        None => text.len(),
+    };
+    (start, end)
+}
+
+/// Range of the top-level declaration containing `offset`, which ends at the
+/// first line break outside of braces after it
+fn decl_bounds(text: &str, offset: usize) -> (usize, usize) {
+    let start = item_start(text, unmatched_brace(text, offset).unwrap_or(offset));
+    // newlines matter here, so this can't use `scan`
+    let mut depth = 0;
+    let mut comment = false;
+    for (i, ch) in text[start..].char_indices().map(|(i, ch)| (start + i, ch)) {
+        match ch {
+            '\n' if depth == 0 && i >= offset => return (start, i),
+            '\n' => comment = false,
+            _ if comment => {}
+            '#' => comment = true,
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            _ => {}
+        }
+    }
+    (start, text.len())
+}
+
+/// Replaces `text[start..end]` with spaces, keeping line breaks and byte
+/// offsets the same
+fn blank(text: &str, start: usize, end: usize) -> String {
+    let mut blanked = String::with_capacity(text.len());
+    blanked.push_str(&text[..start]);
+    for ch in text[start..end].chars() {
+        match ch {
+            '\n' | '\r' => blanked.push(ch),
+            _ => blanked.push_str(&" ".repeat(ch.len_utf8())),
+        }
+    }
+    blanked.push_str(&text[end..]);
+    blanked
+}
+
 #[cfg(test)]
 mod tests {
     use peg::str::LineCol;
@@ -123,6 +368,49 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn parse_error_diagnostic() {
+        let test = r#"
+        a: struct { v: uint8 [] }
+        "#
+        .build();
+        let errors = parse(&test).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::Syntax);
+        assert_eq!(errors[0].primary_span, Some(Span::new(22, 23)));
+        assert_eq!(
+            errors[0].message,
+            "Unexpected token '[' at line 2, column 22, expected one of \"#\", \"##\", \",\", \"@\", \"if\", \"in\", \"}\", identifier"
+        );
+    }
+
+    #[test]
+    fn parse_reports_every_error() {
+        let test = r#"
+        a: strukt { x: uint8 }
+        b: struct {
+            x float,
+            y: uint8,
+        }
+        c: enum { A, 1, B }
+        export 5 = x
+        d: struct { z: uint8
+        "#
+        .build();
+        let errors = parse(&test).unwrap_err();
+        let messages = errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "Unexpected token 's' at line 2, column 4, expected one of \"enum\", \"struct\"",
+                "Unexpected token 'f' at line 4, column 3, expected one of \":\", \"?\", \"@\"",
+                "Unexpected token '1' at line 7, column 14, expected one of \"##\", \"}\", identifier",
+                "Unexpected token 'x' at line 8, column 12, expected integer",
+                "Unexpected end of file at line 10, column 1, expected one of \"#\", \"##\", \",\", \"@\", \"}\", identifier",
+            ]
+        );
+    }
+
     #[test]
     fn parse_unclosed_struct_brackets() {
         let test = r#"
@@ -161,8 +449,8 @@ mod tests {
         .build();
         let expected = LineCol {
             line: 2,
-            column: 21,
-            offset: 21,
+            column: 23,
+            offset: 23,
         };
         let actual = pkt::schema(&test).unwrap_err().location;
         assert_eq!(actual, expected);
@@ -216,7 +504,19 @@ mod tests {
         .build();
         let expected: AST = vec![Node::Decl(
             "a",
-            Type::Struct(Struct(vec![("v", Unresolved("uint8", false, false))])),
+            Type::Struct(Struct(
+                vec![(
+                    "v",
+                    Unresolved("uint8", false, false, None, None, None, None, Span::new(16, 21)),
+                    vec![],
+                    vec![],
+                    Span::new(13, 14),
+                )],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 2),
         )];
         assert_eq!(pkt::schema(&test).unwrap(), expected);
     }
@@ -235,10 +535,28 @@ mod tests {
         .build();
         let expected: AST = vec![Node::Decl(
             "a",
-            Type::Struct(Struct(vec![
-                ("a", Unresolved("uint8", false, false)),
-                ("b", Unresolved("uint8", false, false)),
-            ])),
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "a",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::new(61, 66)),
+                        vec![],
+                        vec![],
+                        Span::new(58, 59),
+                    ),
+                    (
+                        "b",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::new(115, 120)),
+                        vec![],
+                        vec![],
+                        Span::new(112, 113),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 2),
         )];
         assert_eq!(pkt::schema(&test).unwrap(), expected);
     }
@@ -249,7 +567,16 @@ mod tests {
         asdf: enum { A, B }
         "#
         .build();
-        let expected: AST = vec![Node::Decl("asdf", Type::Enum(Enum(vec!["A", "B"])))];
+        let expected: AST = vec![Node::Decl(
+            "asdf",
+            Type::Enum(Enum(vec![
+                ("A", vec![], Span::new(14, 15)),
+                ("B", vec![], Span::new(17, 18)),
+            ])),
+            vec![],
+            vec![],
+            Span::new(1, 5),
+        )];
         assert_eq!(pkt::schema(&test).unwrap(), expected);
     }
 
@@ -263,10 +590,28 @@ mod tests {
         .build();
         let expected: AST = vec![Node::Decl(
             "asdf",
-            Type::Struct(Struct(vec![
-                ("x", Unresolved("float", false, false)),
-                ("y", Unresolved("float", false, false)),
-            ])),
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "x",
+                        Unresolved("float", false, false, None, None, None, None, Span::new(19, 24)),
+                        vec![],
+                        vec![],
+                        Span::new(16, 17),
+                    ),
+                    (
+                        "y",
+                        Unresolved("float", false, false, None, None, None, None, Span::new(29, 34)),
+                        vec![],
+                        vec![],
+                        Span::new(26, 27),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 5),
         )];
         assert_eq!(pkt::schema(&test).unwrap(), expected);
     }
@@ -282,10 +627,28 @@ mod tests {
         .build();
         let expected: AST = vec![Node::Decl(
             "asdf",
-            Type::Struct(Struct(vec![
-                ("a", Unresolved("A", false, false)),
-                ("b", Unresolved("B", false, false)),
-            ])),
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "a",
+                        Unresolved("A", false, false, None, None, None, None, Span::new(19, 20)),
+                        vec![],
+                        vec![],
+                        Span::new(16, 17),
+                    ),
+                    (
+                        "b",
+                        Unresolved("B", false, false, None, None, None, None, Span::new(25, 26)),
+                        vec![],
+                        vec![],
+                        Span::new(22, 23),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 5),
         )];
         assert_eq!(pkt::schema(&test).unwrap(), expected);
     }
@@ -301,10 +664,28 @@ mod tests {
         .build();
         let expected: AST = vec![Node::Decl(
             "asdf",
-            Type::Struct(Struct(vec![
-                ("a", Unresolved("A", true, false)),
-                ("b", Unresolved("B", true, false)),
-            ])),
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "a",
+                        Unresolved("A", true, false, None, None, None, None, Span::new(19, 20)),
+                        vec![],
+                        vec![],
+                        Span::new(16, 17),
+                    ),
+                    (
+                        "b",
+                        Unresolved("B", true, false, None, None, None, None, Span::new(27, 28)),
+                        vec![],
+                        vec![],
+                        Span::new(24, 25),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 5),
         )];
         assert_eq!(pkt::schema(&test).unwrap(), expected);
     }
@@ -321,11 +702,35 @@ mod tests {
         .build();
         let expected: AST = vec![Node::Decl(
             "asdf",
-            Type::Struct(Struct(vec![
-                ("a", Unresolved("A", true, true)),
-                ("b", Unresolved("B", false, true)),
-                ("c", Unresolved("C", false, false)),
-            ])),
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "a",
+                        Unresolved("A", true, true, None, None, None, None, Span::new(20, 21)),
+                        vec![],
+                        vec![],
+                        Span::new(16, 17),
+                    ),
+                    (
+                        "b",
+                        Unresolved("B", false, true, None, None, None, None, Span::new(29, 30)),
+                        vec![],
+                        vec![],
+                        Span::new(25, 26),
+                    ),
+                    (
+                        "c",
+                        Unresolved("C", false, false, None, None, None, None, Span::new(35, 36)),
+                        vec![],
+                        vec![],
+                        Span::new(32, 33),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 5),
         )];
         assert_eq!(pkt::schema(&test).unwrap(), expected);
     }
@@ -336,7 +741,471 @@ mod tests {
         export Test
         "#
         .build();
-        let expected: AST = vec![Node::Export("Test")];
+        let expected: AST = vec![Node::Export("Test", None, Span::new(8, 12))];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_export_with_id() {
+        let test = r#"
+        export Move = 1
+        export Spawn = 255
+        "#
+        .build();
+        let expected: AST = vec![
+            Node::Export("Move", Some(1), Span::new(8, 12)),
+            Node::Export("Spawn", Some(255), Span::new(24, 29)),
+        ];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_export_id_out_of_range() {
+        let test = r#"
+        export Move = 256
+        "#
+        .build();
+        let expected = LineCol {
+            line: 2,
+            column: 18,
+            offset: 18,
+        };
+        let actual = pkt::schema(&test).unwrap_err().location;
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn parse_import() {
+        let test = r#"
+        import "shared/position.pkt"
+        Test: struct { pos: Position }
+        export Test
+        "#
+        .build();
+        let expected: AST = vec![
+            Node::Import("shared/position.pkt", Span::new(8, 29)),
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "pos",
+                        Unresolved("Position", false, false, None, None, None, None, Span::new(50, 58)),
+                        vec![],
+                        vec![],
+                        Span::new(45, 48),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::new(30, 34),
+            ),
+            Node::Export("Test", None, Span::new(68, 72)),
+        ];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_doc_comments() {
+        let test = r#"
+        ## A flag
+        Flag: enum {
+            ## The first one
+            A,
+            B
+        }
+        ## A test
+        ## over two lines
+        Test: struct {
+            # not a doc comment
+            ##   Health points
+            hp: uint8
+        }
+        "#
+        .build();
+        let expected: AST = vec![
+            Node::Decl(
+                "Flag",
+                Type::Enum(Enum(vec![
+                    ("A", vec!["The first one"], Span::new(41, 42)),
+                    ("B", vec![], Span::new(44, 45)),
+                ])),
+                vec!["A flag"],
+                vec![],
+                Span::new(11, 15),
+            ),
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "hp",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::new(134, 139)),
+                        vec!["Health points"],
+                        vec![],
+                        Span::new(130, 132),
+                    )],
+                    None,
+                )),
+                vec!["A test", "over two lines"],
+                vec![],
+                Span::new(76, 80),
+            ),
+        ];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let test = r#"
+        @deprecated("use Other") @since(1.2)
+        Test: struct {
+            ## Health points
+            @rename(health)
+            hp: uint8
+            @deprecated flag: uint8
+        }
+        "#
+        .build();
+        let expected: AST = vec![Node::Decl(
+            "Test",
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "hp",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::new(90, 95)),
+                        vec!["Health points"],
+                        vec![Attribute("rename", vec!["health"])],
+                        Span::new(86, 88),
+                    ),
+                    (
+                        "flag",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::new(114, 119)),
+                        vec![],
+                        vec![Attribute("deprecated", vec![])],
+                        Span::new(108, 112),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![
+                Attribute("deprecated", vec!["use Other"]),
+                Attribute("since", vec!["1.2"]),
+            ],
+            Span::new(38, 42),
+        )];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_ranges() {
+        let test = r#"
+        Test: struct {
+            hp: uint8 in 0..=100
+            name: string in 3..16,
+            scale: float[] in -1.5..
+            level: uint8 in ..10
+        }
+        "#
+        .build();
+        let expected: AST = vec![Node::Decl(
+            "Test",
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "hp",
+                        Unresolved(
+                            "uint8",
+                            false,
+                            false,
+                            Some(Range(Some("0"), Some("100"), true)),
+                            None,
+                            None,
+                            None,
+                            Span::new(20, 25),
+                        ),
+                        vec![],
+                        vec![],
+                        Span::new(16, 18),
+                    ),
+                    (
+                        "name",
+                        Unresolved(
+                            "string",
+                            false,
+                            false,
+                            Some(Range(Some("3"), Some("16"), false)),
+                            None,
+                            None,
+                            None,
+                            Span::new(43, 49),
+                        ),
+                        vec![],
+                        vec![],
+                        Span::new(37, 41),
+                    ),
+                    (
+                        "scale",
+                        Unresolved(
+                            "float",
+                            true,
+                            false,
+                            Some(Range(Some("-1.5"), None, false)),
+                            None,
+                            None,
+                            None,
+                            Span::new(67, 72),
+                        ),
+                        vec![],
+                        vec![],
+                        Span::new(60, 65),
+                    ),
+                    (
+                        "level",
+                        Unresolved(
+                            "uint8",
+                            false,
+                            false,
+                            Some(Range(None, Some("10"), false)),
+                            None,
+                            None,
+                            None,
+                            Span::new(92, 97),
+                        ),
+                        vec![],
+                        vec![],
+                        Span::new(85, 90),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 5),
+        )];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_length_fields() {
+        let test = r#"
+        Test: struct {
+            count: uint8
+            data: uint8[count]
+            names: string[ count ]
+        }
+        "#
+        .build();
+        let expected: AST = vec![Node::Decl(
+            "Test",
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "count",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::new(23, 28)),
+                        vec![],
+                        vec![],
+                        Span::new(16, 21),
+                    ),
+                    (
+                        "data",
+                        Unresolved("uint8", true, false, None, Some("count"), None, None, Span::new(35, 40)),
+                        vec![],
+                        vec![],
+                        Span::new(29, 33),
+                    ),
+                    (
+                        "names",
+                        Unresolved(
+                            "string",
+                            true,
+                            false,
+                            None,
+                            Some("count"),
+                            None,
+                            None,
+                            Span::new(55, 61),
+                        ),
+                        vec![],
+                        vec![],
+                        Span::new(48, 53),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 5),
+        )];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_conditions() {
+        let test = r#"
+        Test: struct {
+            kind: Kind
+            name: string if kind == Kind.Player
+            level: uint8 in ..10 if kind==Kind.Monster
+        }
+        "#
+        .build();
+        let expected: AST = vec![Node::Decl(
+            "Test",
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "kind",
+                        Unresolved("Kind", false, false, None, None, None, None, Span::new(22, 26)),
+                        vec![],
+                        vec![],
+                        Span::new(16, 20),
+                    ),
+                    (
+                        "name",
+                        Unresolved(
+                            "string",
+                            false,
+                            false,
+                            None,
+                            None,
+                            Some(Condition("kind", "Kind", "Player", Span::new(40, 62))),
+                            None,
+                            Span::new(33, 39),
+                        ),
+                        vec![],
+                        vec![],
+                        Span::new(27, 31),
+                    ),
+                    (
+                        "level",
+                        Unresolved(
+                            "uint8",
+                            false,
+                            false,
+                            Some(Range(None, Some("10"), false)),
+                            None,
+                            Some(Condition("kind", "Kind", "Monster", Span::new(84, 105))),
+                            None,
+                            Span::new(70, 75),
+                        ),
+                        vec![],
+                        vec![],
+                        Span::new(63, 68),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 5),
+        )];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_extends() {
+        let test = r#"
+        Player: struct extends Entity { name: string }
+        "#
+        .build();
+        let expected: AST = vec![Node::Decl(
+            "Player",
+            Type::Struct(Struct(
+                vec![(
+                    "name",
+                    Unresolved("string", false, false, None, None, None, None, Span::new(39, 45)),
+                    vec![],
+                    vec![],
+                    Span::new(33, 37),
+                )],
+                Some(("Entity", Span::new(24, 30))),
+            )),
+            vec![],
+            vec![],
+            Span::new(1, 7),
+        )];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_tags() {
+        let test = r#"
+        @extensible
+        Test: struct {
+            id @1: uint32
+            name? @20: string
+        }
+        "#
+        .build();
+        let expected: AST = vec![Node::Decl(
+            "Test",
+            Type::Struct(Struct(
+                vec![
+                    (
+                        "id",
+                        Unresolved("uint32", false, false, None, None, None, Some(1), Span::new(35, 41)),
+                        vec![],
+                        vec![],
+                        Span::new(28, 30),
+                    ),
+                    (
+                        "name",
+                        Unresolved("string", false, true, None, None, None, Some(20), Span::new(53, 59)),
+                        vec![],
+                        vec![],
+                        Span::new(42, 46),
+                    ),
+                ],
+                None,
+            )),
+            vec![],
+            vec![Attribute("extensible", vec![])],
+            Span::new(13, 17),
+        )];
+        assert_eq!(pkt::schema(&test).unwrap(), expected);
+
+        let test = r#"
+        Test: struct { id @65536: uint32 }
+        "#
+        .build();
+        let expected = LineCol {
+            line: 2,
+            column: 25,
+            offset: 25,
+        };
+        let actual = pkt::schema(&test).unwrap_err().location;
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn parse_package() {
+        let test = r#"
+        package game.combat
+        Test: struct { hp: uint8 }
+        export Test
+        "#
+        .build();
+        let expected: AST = vec![
+            Node::Package("game.combat", Span::new(9, 20)),
+            Node::Decl(
+                "Test",
+                Type::Struct(Struct(
+                    vec![(
+                        "hp",
+                        Unresolved("uint8", false, false, None, None, None, None, Span::new(40, 45)),
+                        vec![],
+                        vec![],
+                        Span::new(36, 38),
+                    )],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::new(21, 25),
+            ),
+            Node::Export("Test", None, Span::new(55, 59)),
+        ];
         assert_eq!(pkt::schema(&test).unwrap(), expected);
     }
 
@@ -360,33 +1229,120 @@ mod tests {
         "#
         .build();
         let expected: AST = vec![
-            Node::Decl("Flag", Type::Enum(Enum(vec!["A", "B"]))),
             Node::Decl(
-                "Position",
-                Type::Struct(Struct(vec![
-                    ("x", Unresolved("float", false, false)),
-                    ("y", Unresolved("float", false, false)),
+                "Flag",
+                Type::Enum(Enum(vec![
+                    ("A", vec![], Span::new(89, 90)),
+                    ("B", vec![], Span::new(92, 93)),
                 ])),
+                vec![],
+                vec![],
+                Span::new(76, 80),
+            ),
+            Node::Decl(
+                "Position",
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "x",
+                            Unresolved("float", false, false, None, None, None, None, Span::new(118, 123)),
+                            vec![],
+                            vec![],
+                            Span::new(115, 116),
+                        ),
+                        (
+                            "y",
+                            Unresolved("float", false, false, None, None, None, None, Span::new(128, 133)),
+                            vec![],
+                            vec![],
+                            Span::new(125, 126),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::new(96, 104),
             ),
             Node::Decl(
                 "Value",
-                Type::Struct(Struct(vec![
-                    ("a", Unresolved("uint32", false, false)),
-                    ("b", Unresolved("int32", false, false)),
-                    ("c", Unresolved("uint8", false, false)),
-                    ("d", Unresolved("uint8", false, false)),
-                ])),
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "a",
+                            Unresolved("uint32", false, false, None, None, None, None, Span::new(156, 162)),
+                            vec![],
+                            vec![],
+                            Span::new(153, 154),
+                        ),
+                        (
+                            "b",
+                            Unresolved("int32", false, false, None, None, None, None, Span::new(167, 172)),
+                            vec![],
+                            vec![],
+                            Span::new(164, 165),
+                        ),
+                        (
+                            "c",
+                            Unresolved("uint8", false, false, None, None, None, None, Span::new(177, 182)),
+                            vec![],
+                            vec![],
+                            Span::new(174, 175),
+                        ),
+                        (
+                            "d",
+                            Unresolved("uint8", false, false, None, None, None, None, Span::new(187, 192)),
+                            vec![],
+                            vec![],
+                            Span::new(184, 185),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::new(136, 141),
             ),
             Node::Decl(
                 "ComplexType",
-                Type::Struct(Struct(vec![
-                    ("flag", Unresolved("Flag", false, false)),
-                    ("pos", Unresolved("Position", false, false)),
-                    ("names", Unresolved("string", true, false)),
-                    ("values", Unresolved("Value", true, false)),
-                ])),
+                Type::Struct(Struct(
+                    vec![
+                        (
+                            "flag",
+                            Unresolved("Flag", false, false, None, None, None, None, Span::new(223, 227)),
+                            vec![],
+                            vec![],
+                            Span::new(217, 221),
+                        ),
+                        (
+                            "pos",
+                            Unresolved("Position", false, false, None, None, None, None, Span::new(234, 242)),
+                            vec![],
+                            vec![],
+                            Span::new(229, 232),
+                        ),
+                        (
+                            "names",
+                            Unresolved("string", true, false, None, None, None, None, Span::new(251, 257)),
+                            vec![],
+                            vec![],
+                            Span::new(244, 249),
+                        ),
+                        (
+                            "values",
+                            Unresolved("Value", true, false, None, None, None, None, Span::new(269, 274)),
+                            vec![],
+                            vec![],
+                            Span::new(261, 267),
+                        ),
+                    ],
+                    None,
+                )),
+                vec![],
+                vec![],
+                Span::new(195, 206),
             ),
-            Node::Export("ComplexType"),
+            Node::Export("ComplexType", None, Span::new(286, 297)),
         ];
         assert_eq!(pkt::schema(&test).unwrap(), expected);
     }
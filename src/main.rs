@@ -3,23 +3,61 @@ extern crate clap;
 extern crate fstrings;
 extern crate packetc_lib as pkt;
 
+use std::io::IsTerminal;
 use std::path::PathBuf;
 use std::{fs, path::Component, path::Path};
 
 use anyhow::Result;
-use clap::Clap;
+use clap::{AppSettings, ArgSettings, Clap};
+use pkt::diagnostic::{Code, Diagnostic, Level, Lints};
 
 #[derive(Clap)]
 #[clap(version = "1.0", author = "Jan P. <honza.spacir1@gmail.com>")]
+#[clap(setting = AppSettings::SubcommandsNegateReqs)]
 struct Opts {
-    lang: Lang,
-    path: String,
-    out: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+    // only optional when running a subcommand
+    #[clap(setting = ArgSettings::Required)]
+    lang: Option<Lang>,
+    #[clap(setting = ArgSettings::Required)]
+    path: Option<String>,
+    #[clap(setting = ArgSettings::Required)]
+    out: Option<String>,
+    /// When to color diagnostics: auto, always or never
+    #[clap(long, default_value = "auto")]
+    color: Color,
+    /// How to print diagnostics: human, or json for one object per line
+    #[clap(long, default_value = "human")]
+    message_format: MessageFormat,
+    /// Fails on a warning, given by its code (e.g. W0001), or on all of
+    /// them with `warnings`
+    #[clap(long, short = 'D', number_of_values = 1)]
+    deny: Vec<Lint>,
+    /// Silences a warning, given by its code (e.g. W0001), or all of them
+    /// with `warnings`
+    #[clap(long, short = 'A', number_of_values = 1)]
+    allow: Vec<Lint>,
+}
+
+#[derive(Clap)]
+enum Command {
+    /// Checks that packets written with either schema can be read with the
+    /// other, and fails if they can't
+    Compat(Compat),
+}
+
+#[derive(Clap)]
+struct Compat {
+    /// The previous version of the schema
+    old: String,
+    /// The new version of the schema
+    new: String,
 }
 
 #[derive(Clone, Copy, Debug)]
 enum Lang {
-    /* Rust, */
+    Rust,
     TypeScript,
 }
 impl std::str::FromStr for Lang {
@@ -27,7 +65,7 @@ impl std::str::FromStr for Lang {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
-            /* "rust" => Ok(Lang::Rust), */
+            "rust" => Ok(Lang::Rust),
             "ts" => Ok(Lang::TypeScript),
             s => {
                 println!("{}", s);
@@ -37,9 +75,131 @@ impl std::str::FromStr for Lang {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+impl std::str::FromStr for Color {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            _ => Err("expected one of auto, always or never"),
+        }
+    }
+}
+
+impl Color {
+    fn enabled(self) -> bool {
+        match self {
+            Color::Auto => std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal(),
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MessageFormat {
+    Human,
+    Json,
+}
+impl std::str::FromStr for MessageFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err("expected one of human or json"),
+        }
+    }
+}
+
+/// Where and how diagnostics are printed
+#[derive(Clone, Copy, Debug)]
+struct Output {
+    format: MessageFormat,
+    color: bool,
+}
+
+/// Exit code of each kind of failure, so that scripts can tell them apart.
+/// Invalid arguments exit with 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Exit {
+    /// Anything not covered below
+    Failure = 1,
+    /// The schema couldn't be parsed
+    Syntax = 3,
+    /// The schema has type errors, or denied warnings
+    Check = 4,
+    /// `compat` found breaking changes
+    Incompatible = 5,
+    /// A file couldn't be read or written
+    Io = 6,
+}
+
+impl Exit {
+    fn of(err: &anyhow::Error) -> Exit {
+        match err.downcast_ref::<pkt::Error>() {
+            Some(pkt::Error::Parse(_)) => Exit::Syntax,
+            Some(pkt::Error::Check(_)) => Exit::Check,
+            Some(pkt::Error::Io(..)) => Exit::Io,
+            None if err.is::<Incompatible>() => Exit::Incompatible,
+            None if err.chain().any(|e| e.is::<std::io::Error>()) => Exit::Io,
+            None => Exit::Failure,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Incompatible(usize);
+impl std::fmt::Display for Incompatible {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Found {} breaking change(s)", self.0)
+    }
+}
+impl std::error::Error for Incompatible {}
+
+/// A warning code, or `None` for every warning
+#[derive(Clone, Copy, Debug)]
+struct Lint(Option<Code>);
+impl std::str::FromStr for Lint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "warnings" {
+            return Ok(Lint(None));
+        }
+        match s.parse::<Code>()? {
+            code if code.is_warning() => Ok(Lint(Some(code))),
+            code => Err(format!(
+                "'{}' is an error, only warnings can be allowed or denied",
+                code
+            )),
+        }
+    }
+}
+
+fn lints(opts: &Opts) -> Lints {
+    let mut lints = Lints::default();
+    for Lint(code) in opts.allow.iter() {
+        lints.set(*code, Level::Allow);
+    }
+    for Lint(code) in opts.deny.iter() {
+        lints.set(*code, Level::Deny);
+    }
+    lints
+}
+
 fn extension(lang: Lang) -> &'static str {
     match lang {
-        /* Lang::Rust => "rs", */
+        Lang::Rust => "rs",
         Lang::TypeScript => "ts",
     }
 }
@@ -65,18 +225,20 @@ struct Schema {
     generated: String,
 }
 
-fn run_one(path: String, lang: Lang) -> Result<Schema> {
+fn run_one(path: String, lang: Lang, lints: &Lints, output: Output) -> Result<Schema> {
+    let compiled = match lang {
+        Lang::Rust => pkt::compile_file::<pkt::gen::rust::Rust>(Path::new(&path), lints)?,
+        Lang::TypeScript => pkt::compile_file::<pkt::gen::ts::TypeScript>(Path::new(&path), lints)?,
+        //_ => return println!("not implemented"),
+    };
+    print_diagnostics(&compiled.warnings, output);
     Ok(Schema {
-        path: PathBuf::from(path.clone()),
-        generated: match lang {
-            /* Lang::Rust => pkt::compile::<pkt::gen::rust::Rust>(&fs::read_to_string(path)?)?, */
-            Lang::TypeScript => pkt::compile::<pkt::gen::ts::TypeScript>(&fs::read_to_string(path)?)?,
-            //_ => return println!("not implemented"),
-        },
+        path: PathBuf::from(path),
+        generated: compiled.code,
     })
 }
 
-fn run_all(dir: String, lang: Lang) -> Result<Vec<Schema>> {
+fn run_all(dir: String, lang: Lang, lints: &Lints, output: Output) -> Result<Vec<Schema>> {
     let mut out = Vec::new();
     visit_files(&dir, &mut |entry| {
         out.push(run_one(
@@ -86,6 +248,8 @@ fn run_all(dir: String, lang: Lang) -> Result<Vec<Schema>> {
                 .ok_or_else(|| anyhow::anyhow!("invalid path"))?
                 .into(),
             lang,
+            lints,
+            output,
         )?);
         Ok(())
     })?;
@@ -113,27 +277,86 @@ fn format_path(file: &Path, base_dir: &Path, out_dir: &Path, lang: Lang, preserv
         Ok(out_dir
             .components()
             // This should never panic
-            .chain(vec![Component::Normal(file.file_stem().unwrap())].into_iter())
+            .chain(vec![Component::Normal(file.file_stem().unwrap())])
             .collect::<PathBuf>()
             .with_extension(extension(lang)))
     }
 }
 
-fn main() -> Result<()> {
+fn compat(opts: Compat) -> Result<()> {
+    let changes = pkt::compat_files(Path::new(&opts.old), Path::new(&opts.new))?;
+    if changes.is_empty() {
+        println!("'{}' is compatible with '{}'", opts.new, opts.old);
+        return Ok(());
+    }
+    for change in changes.iter() {
+        println!("{}", change);
+    }
+    Err(Incompatible(changes.len()).into())
+}
+
+/// Prints `err`, rendering schema diagnostics along with the lines of the
+/// file they point into
+fn report(err: &anyhow::Error, output: Output) {
+    let file = match err.downcast_ref::<pkt::Error>() {
+        Some(pkt::Error::Parse(diagnostics)) | Some(pkt::Error::Check(diagnostics)) => {
+            return print_diagnostics(diagnostics, output)
+        }
+        Some(pkt::Error::Io(path, _)) => pkt::diagnostic::json_string(&path.display().to_string()),
+        None => "null".to_string(),
+    };
+    match output.format {
+        MessageFormat::Json => eprintln!(
+            "{{\"file\":{},\"code\":null,\"severity\":\"error\",\"message\":{},\"span\":null,\"labels\":[],\"notes\":[]}}",
+            file,
+            pkt::diagnostic::json_string(&format!("{:#}", err))
+        ),
+        MessageFormat::Human => eprintln!("Error: {:?}", err),
+    }
+}
+
+/// Prints the diagnostics to stderr, like rustc does in both formats
+fn print_diagnostics(diagnostics: &[Diagnostic], output: Output) {
+    for diagnostic in diagnostics {
+        let source = diagnostic.path.as_ref().and_then(|p| fs::read_to_string(p).ok());
+        match (output.format, source) {
+            (MessageFormat::Json, source) => eprintln!("{}", diagnostic.to_json(source.as_deref())),
+            (MessageFormat::Human, Some(source)) => eprintln!("{}", diagnostic.render(&source, output.color)),
+            (MessageFormat::Human, None) => eprintln!("{}\n", diagnostic),
+        }
+    }
+}
+
+fn main() {
     let opts = Opts::parse();
-    let base_dir = PathBuf::from(opts.path.clone());
-    let out_dir = PathBuf::from(opts.out.clone());
-    if let Ok(meta) = fs::metadata(&opts.path) {
-        if meta.is_dir() {
-            for result in run_all(opts.path.clone(), opts.lang)? {
-                let out = result.path.clone();
-                save_one(result, &format_path(&out, &base_dir, &out_dir, opts.lang, true)?)?;
-            }
-        } else {
-            let result = run_one(opts.path.clone(), opts.lang)?;
+    let output = Output {
+        format: opts.message_format,
+        color: opts.color.enabled(),
+    };
+    if let Err(err) = run(opts, output) {
+        report(&err, output);
+        std::process::exit(Exit::of(&err) as i32);
+    }
+}
+
+fn run(opts: Opts, output: Output) -> Result<()> {
+    if let Some(Command::Compat(opts)) = opts.command {
+        return compat(opts);
+    }
+    let lints = lints(&opts);
+    let (lang, path, out) = (opts.lang.unwrap(), opts.path.unwrap(), opts.out.unwrap());
+    let base_dir = PathBuf::from(path.clone());
+    let out_dir = PathBuf::from(out);
+    let meta = fs::metadata(&path).map_err(|e| pkt::Error::Io(base_dir.clone(), e))?;
+    if meta.is_dir() {
+        for result in run_all(path.clone(), lang, &lints, output)? {
             let out = result.path.clone();
-            save_one(result, &format_path(&out, &base_dir, &out_dir, opts.lang, false)?)?;
+            save_one(result, &format_path(&out, &base_dir, &out_dir, lang, true)?)?;
         }
+    } else {
+        let result = run_one(path.clone(), lang, &lints, output)?;
+        let out = result.path.clone();
+        save_one(result, &format_path(&out, &base_dir, &out_dir, lang, false)?)?;
     }
 
     Ok(())